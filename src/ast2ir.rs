@@ -1,116 +1,1476 @@
 // converts the Ast representation of source code
 // to a variant of SSA form
 
+use std::collections::HashMap;
+
 use crate::{
-  ir::{InstrIdx, Instruction, InstructionValue, IrBlock, IrFunction, IrUnit, Type},
-  node::{Binary, Node, NodeData, NodeIdx},
+  context::CompilerContext,
+  diagnostic::{Diagnostic, DiagnosticLevel},
+  ir::{
+    builder::IrBuilder, ConstIdx, FuncIdx, ImplIdx, InstrIdx, InstructionValue, IrBody, IrConst,
+    IrFunction, IrImpl, IrMethodSig, IrNewtype, IrTrait, IrUnit, Linkage, TraitIdx, Type,
+  },
+  node::{
+    self, Argument, Attribute, Binary, For, If, Let, Node, NodeData, NodeIdx, ParameterDeclList,
+    Pattern, While,
+  },
   parser::Ast,
+  sema::arguments::{self, ArgumentSource},
+  token::{Span, TokIdx},
 };
 
 pub struct IrEmitter<'a> {
-  // ast already holds onto a context
+  ctx: &'a CompilerContext,
   ast: &'a Ast,
+
+  // maps the name of every function to its index in the unit
+  functions: HashMap<&'a str, FuncIdx>,
+
+  // maps the name of every constant to its index in the unit
+  consts: HashMap<&'a str, ConstIdx>,
+
+  // maps the name of every trait to its index in the unit
+  traits: HashMap<&'a str, TraitIdx>,
+
+  // maps the name of every alias to the type it stands for,
+  // and the name of every newtype to the newtype
+  type_names: HashMap<&'a str, Type>,
+
+  // names of the type parameters of the function being emitted
+  type_params: Vec<&'a str>,
+
+  // the type Self refers to, set within traits and implementations
+  self_type: Option<Type>,
+
+  // stack of lexical scopes,
+  // maps the name of a variable to the instruction holding its value
+  scopes: Vec<HashMap<&'a str, InstrIdx>>,
+
+  // the induction variables of the range loops being emitted,
+  // which can not be rebound within their body
+  induction_vars: Vec<InstrIdx>,
 }
 
 impl<'a> IrEmitter<'a> {
+  fn lookup_variable(&self, name: &str) -> Option<InstrIdx> {
+    self
+      .scopes
+      .iter()
+      .rev()
+      .find_map(|scope| scope.get(name).copied())
+  }
+
+  // @tok is where an error in the type is reported
+  fn lower_type(&self, ty: &node::Type, tok: TokIdx) -> Result<Type, String> {
+    Ok(match ty {
+      node::Type::Undecided => Type::Undecided,
+      node::Type::Integer => Type::Integer,
+      node::Type::Floating => Type::Floating,
+      node::Type::Moot => Type::Moot,
+
+      node::Type::Tuple(types) => Type::Tuple(
+        types
+          .iter()
+          .map(|x| self.lower_type(x, tok))
+          .collect::<Result<_, _>>()?,
+      ),
+
+      node::Type::Option(ty) => Type::Option(Box::new(self.lower_type(ty, tok)?)),
+      node::Type::Result(ok, err) => Type::Result(
+        Box::new(self.lower_type(ok, tok)?),
+        Box::new(self.lower_type(err, tok)?),
+      ),
+
+      node::Type::Named(span) if self.ctx.get_str_from_span(*span) == "Self" => {
+        self.lower_type(&node::Type::Receiver, tok)?
+      }
+
+      node::Type::Named(span) => {
+        let name = self.ctx.get_str_from_span(*span);
+
+        match self.type_params.iter().position(|x| *x == name) {
+          Some(n) => Type::Generic(n),
+          None => match self.type_names.get(name) {
+            Some(ty) => ty.clone(),
+            None => return self.fail(tok, format!("use of undeclared type {}", name)),
+          },
+        }
+      }
+
+      node::Type::Receiver => match &self.self_type {
+        Some(ty) => ty.clone(),
+        None => {
+          return self.fail(
+            tok,
+            "use of Self outside of a trait or implementation".to_string(),
+          )
+        }
+      },
+    })
+  }
+
+  // the type a call to @name converts to, if @name is a type
+  fn conversion_type(&self, name: &str) -> Option<Type> {
+    match name {
+      "Integer" => Some(Type::Integer),
+      "Floating" => Some(Type::Floating),
+      name => self.type_names.get(name).cloned(),
+    }
+  }
+
+  // the builtin constructors of Option and Result
+  fn constructor(name: &str) -> Option<fn(&mut IrBuilder, InstrIdx, TokIdx) -> InstrIdx> {
+    match name {
+      "Some" => Some(IrBuilder::build_some),
+      "Ok" => Some(IrBuilder::build_ok),
+      "Err" => Some(IrBuilder::build_err),
+      _ => None,
+    }
+  }
+
+  fn error(&self, tokidx: TokIdx, info: String) {
+    self.ctx.push_diagnostic(Diagnostic {
+      info,
+      level: DiagnosticLevel::Error,
+      tokidx,
+    });
+  }
+
+  // reports an error at @tokidx and gives up on emitting the unit
+  fn fail<T>(&self, tokidx: TokIdx, info: String) -> Result<T, String> {
+    self.error(tokidx, info.clone());
+    Err(info)
+  }
+
+  fn warning(&self, tokidx: TokIdx, info: String) {
+    self.ctx.push_diagnostic(Diagnostic {
+      info,
+      level: DiagnosticLevel::Warning,
+      tokidx,
+    });
+  }
+
+  // warns about a call to a function marked with @deprecated
+  fn check_deprecated(&self, func: FuncIdx, tokidx: TokIdx) {
+    let Some(nidx) = self.ast.funcs.get(func) else {
+      return;
+    };
+
+    let NodeData::FunctionDef(def) = &self.ast.nodes[*nidx].data else {
+      unreachable!()
+    };
+
+    for attribute in def.attributes.iter() {
+      let Attribute::Deprecated(message) = attribute else {
+        continue;
+      };
+
+      let name = self.ctx.get_str_from_span(def.name);
+
+      self.warning(
+        tokidx,
+        match message {
+          Some(message) => format!(
+            "{} is deprecated: {}",
+            name,
+            self.ctx.get_str_from_span(*message)
+          ),
+          None => format!("{} is deprecated", name),
+        },
+      );
+    }
+  }
+
+  // the parameters of a function or extern function
+  fn parameters(&self, func: FuncIdx) -> &'a ParameterDeclList {
+    let (nidx, externs) = match self.ast.funcs.get(func) {
+      Some(nidx) => (*nidx, false),
+      None => (self.ast.externs[func - self.ast.funcs.len()], true),
+    };
+
+    match &self.ast.nodes[nidx].data {
+      NodeData::FunctionDef(def) if !externs => &def.params,
+      NodeData::Extern(sig) => &sig.params,
+      _ => unreachable!(),
+    }
+  }
+
+  // emits the arguments of a call to @func in the order they were written in,
+  // and passes them in the order of the parameters, see sema::arguments
+  //
+  // returns None if the arguments do not fit the parameters
+  fn emit_arguments(
+    &mut self,
+    func: FuncIdx,
+    args: &[Argument],
+    tok: TokIdx,
+    builder: &mut IrBuilder,
+  ) -> Result<Option<Vec<InstrIdx>>, String> {
+    let sources = arguments::resolve(self.ctx, self.parameters(func), args, tok);
+
+    let mut values = vec![];
+    for arg in args.iter() {
+      values.push(self.emit_node(arg.value, builder)?);
+    }
+
+    let Some(sources) = sources else {
+      return Ok(None);
+    };
+
+    let mut params = vec![];
+
+    for source in sources {
+      params.push(match source {
+        ArgumentSource::Argument(i) => values[i],
+
+        // defaults can not refer to the variables of the caller
+        ArgumentSource::Default(default) => {
+          let scopes = std::mem::take(&mut self.scopes);
+          let value = self.emit_node(default, builder);
+          self.scopes = scopes;
+
+          value?
+        }
+      });
+    }
+
+    Ok(Some(params))
+  }
+
+  fn lookup_trait(&self, name: Span, tok: TokIdx) -> Result<TraitIdx, String> {
+    let name = self.ctx.get_str_from_span(name);
+
+    match self.traits.get(name) {
+      Some(idx) => Ok(*idx),
+      None => self.fail(tok, format!("use of undeclared trait {}", name)),
+    }
+  }
+
+  // the shape that a value has to fit into to be destructured by @pattern
+  fn pattern_shape(pattern: &Pattern) -> Type {
+    match pattern {
+      Pattern::Name(_) => Type::Undecided,
+      Pattern::Tuple(patterns) => Type::Tuple(patterns.iter().map(Self::pattern_shape).collect()),
+    }
+  }
+
+  // binds every name within @pattern to the matching part of @value
+  fn bind_pattern(
+    &mut self,
+    pattern: &Pattern,
+    value: InstrIdx,
+    tok: usize,
+    builder: &mut IrBuilder,
+  ) {
+    match pattern {
+      Pattern::Name(span) => {
+        let name = self.ctx.get_str_from_span(*span);
+
+        // rebinding a name within the same block is fine,
+        // hiding the variable of an enclosing block likely is not
+        let scope = self.scopes.last().unwrap();
+        match self.lookup_variable(name) {
+          Some(var) if self.induction_vars.contains(&var) => self.error(
+            tok,
+            format!(
+              "{} is the variable of a range loop, and can not be rebound in its body",
+              name
+            ),
+          ),
+
+          Some(_) if !scope.contains_key(name) => self.warning(
+            tok,
+            format!("{} shadows a variable of an enclosing block", name),
+          ),
+
+          _ => (),
+        }
+
+        self.scopes.last_mut().unwrap().insert(name, value);
+      }
+
+      Pattern::Tuple(patterns) => {
+        for (i, pattern) in patterns.iter().enumerate() {
+          let element = builder.build_tuple_get(value, i, tok);
+
+          self.bind_pattern(pattern, element, tok, builder);
+        }
+      }
+    }
+  }
+
+  fn emit_let(
+    &mut self,
+    binding: &Let,
+    tok: usize,
+    builder: &mut IrBuilder,
+  ) -> Result<InstrIdx, String> {
+    let value = self.emit_node(binding.value, builder)?;
+
+    // checks that the value fits into the pattern
+    let value = builder.build(
+      InstructionValue::Assign(value),
+      Self::pattern_shape(&binding.pattern),
+      tok,
+    );
+
+    self.bind_pattern(&binding.pattern, value, tok, builder);
+
+    Ok(value)
+  }
+
   fn emit_binary(
     &mut self,
     binary: &Binary,
-    buffer: &mut Vec<Instruction>,
+    builder: &mut IrBuilder,
   ) -> Result<(InstrIdx, InstrIdx), String> {
-    let l = self.emit_node(binary.left, buffer)?;
-    let r = self.emit_node(binary.right, buffer)?;
+    let l = self.emit_node(binary.left, builder)?;
+    let r = self.emit_node(binary.right, builder)?;
     Ok((l, r))
   }
 
-  fn emit_node(
+  // emits a node whose value is not used,
+  // an if or a block used as a statement does not need to produce a value
+  fn emit_statement(&mut self, nidx: NodeIdx, builder: &mut IrBuilder) -> Result<(), String> {
+    let node = &self.ast.nodes[nidx];
+
+    match &node.data {
+      NodeData::If(branch) => self.emit_if(branch, node.tok, false, builder)?,
+      NodeData::Block(_) => self.emit_block(nidx, false, builder)?,
+      _ => Some(self.emit_node(nidx, builder)?),
+    };
+
+    Ok(())
+  }
+
+  // emits the statements of a block within a new scope
+  //
+  // if @want_value is set the last statement is the value of the block,
+  // None is returned if the block returns from the function instead
+  fn emit_block(
     &mut self,
     nidx: NodeIdx,
-    buffer: &mut Vec<Instruction>,
+    want_value: bool,
+    builder: &mut IrBuilder,
+  ) -> Result<Option<InstrIdx>, String> {
+    let ast = self.ast;
+    let NodeData::Block(block) = &ast.nodes[nidx].data else {
+      unreachable!()
+    };
+
+    self.scopes.push(HashMap::new());
+    let result = self.emit_block_statements(block, want_value, builder);
+    self.scopes.pop();
+
+    result
+  }
+
+  fn emit_block_statements(
+    &mut self,
+    block: &[NodeIdx],
+    want_value: bool,
+    builder: &mut IrBuilder,
+  ) -> Result<Option<InstrIdx>, String> {
+    let Some((last, statements)) = block.split_last() else {
+      unreachable!()
+    };
+
+    for nidx in statements {
+      self.emit_statement(*nidx, builder)?;
+    }
+
+    if !want_value {
+      self.emit_statement(*last, builder)?;
+      return Ok(None);
+    }
+
+    let node = &self.ast.nodes[*last];
+    match &node.data {
+      NodeData::Return(_) => {
+        self.emit_node(*last, builder)?;
+        Ok(None)
+      }
+
+      NodeData::If(branch) => self.emit_if(branch, node.tok, true, builder),
+      NodeData::Block(_) => self.emit_block(*last, true, builder),
+
+      NodeData::Let(_) | NodeData::For(_) | NodeData::While(_) => self.fail(
+        node.tok,
+        "the last statement of a block used as a value has to be an expression".to_string(),
+      ),
+
+      _ => Ok(Some(self.emit_node(*last, builder)?)),
+    }
+  }
+
+  // emits the then or else part of an if, a block or another if
+  fn emit_branch(
+    &mut self,
+    nidx: NodeIdx,
+    want_value: bool,
+    builder: &mut IrBuilder,
+  ) -> Result<Option<InstrIdx>, String> {
+    let node = &self.ast.nodes[nidx];
+
+    match &node.data {
+      NodeData::If(branch) => self.emit_if(branch, node.tok, want_value, builder),
+      _ => self.emit_block(nidx, want_value, builder),
+    }
+  }
+
+  // if <cond>:
+  //   <then>
+  // else:
+  //   <otherwise>
+  //
+  // bb0:
+  //   %0 = <cond>
+  //   %1 = Branch(%0, bb1, bb2)
+  // bb1:
+  //   %2   <then>
+  //   %3 = Assign(<then>)
+  //   %4 = Jump(bb3)
+  // bb2:
+  //   %5   <otherwise>
+  //   %6 = Assign(<otherwise>)
+  //   %7 = Jump(bb3)
+  // bb3:
+  //   %8 = Phi(%3, %6)
+  //
+  // the Phi and the Assigns are only emitted if @want_value is set,
+  // every branch gets an Assign of its own, as the value of a branch
+  // may be a variable that was defined before the if
+  // without an else, %1 continues straight past the then branch
+  //
+  // a branch that returns gets neither a Jump nor an operand,
+  // and if only one branch is left its Assign is the value of the if
+  //
+  // returns None if the if is a statement, or every branch returns
+  fn emit_if(
+    &mut self,
+    branch: &If,
+    tok: usize,
+    want_value: bool,
+    builder: &mut IrBuilder,
+  ) -> Result<Option<InstrIdx>, String> {
+    if want_value && branch.otherwise.is_none() {
+      return self.fail(
+        tok,
+        "an if used as a value needs an else branch".to_string(),
+      );
+    }
+
+    let cond = self.emit_node(branch.cond, builder)?;
+
+    let then_block = builder.create_block();
+    let else_block = builder.create_block();
+    let end_block = builder.create_block();
+
+    builder.build_branch(
+      cond,
+      then_block,
+      match branch.otherwise {
+        Some(_) => else_block,
+        None => end_block,
+      },
+      tok,
+    );
+
+    let mut operands = vec![];
+
+    builder.set_insertion_point(then_block);
+    let then = self.emit_branch(branch.then, want_value, builder)?;
+    operands.extend(then.map(|x| builder.build_assign(x, tok)));
+
+    if let Some(otherwise) = branch.otherwise {
+      if !builder.is_terminated() {
+        builder.build_jump(end_block, tok);
+      }
+
+      builder.set_insertion_point(else_block);
+      let otherwise = self.emit_branch(otherwise, want_value, builder)?;
+      operands.extend(otherwise.map(|x| builder.build_assign(x, tok)));
+    }
+
+    builder.set_insertion_point(end_block);
+
+    match operands[..] {
+      [] => Ok(None),
+      [value] => Ok(Some(value)),
+      _ => Ok(Some(builder.build_phi(operands, Type::Undecided, tok))),
+    }
+  }
+
+  // while <cond>:
+  //   <body>
+  //
+  // bb1:
+  //   %0   <cond>
+  //   %1 = Branch(%0, bb2, bb3)
+  // bb2:
+  //   %2   <body>
+  //   %3 = Jump(bb1)
+  // bb3:
+  //   %4   ...
+  //
+  // the condition is evaluated again on every iteration
+  fn emit_while(
+    &mut self,
+    while_loop: &While,
+    tok: usize,
+    builder: &mut IrBuilder,
   ) -> Result<InstrIdx, String> {
+    let header = builder.create_block();
+    let body = builder.create_block();
+    let exit = builder.create_block();
+
+    builder.set_insertion_point(header);
+    let cond = self.emit_node(while_loop.cond, builder)?;
+
+    let branch = builder.build_branch(cond, body, exit, tok);
+
+    builder.set_insertion_point(body);
+    self.emit_statement(while_loop.body, builder)?;
+    if !builder.is_terminated() {
+      builder.build_jump(header, tok);
+    }
+
+    builder.set_insertion_point(exit);
+
+    Ok(branch)
+  }
+
+  // the value of @nidx if it is an Integer known before running the program,
+  // i.e. made up of literals, arithmetic and constants defined that way
+  //
+  // @consts are the constants being folded already,
+  // which a constant depending on its own value would otherwise loop on
+  fn fold_integer(&self, nidx: NodeIdx, consts: &mut Vec<&'a str>) -> Option<i64> {
+    let ast = self.ast;
+
+    let (l, r) = match &ast.nodes[nidx].data {
+      NodeData::Integer(val) => return Some(*val),
+
+      // variables hide constants of the same name
+      NodeData::Identifier(span) => {
+        let name = self.ctx.get_str_from_span(*span);
+        if self.lookup_variable(name).is_some() || consts.contains(&name) {
+          return None;
+        }
+
+        let NodeData::ConstDef(def) = &ast.nodes[ast.consts[*self.consts.get(name)?]].data else {
+          unreachable!()
+        };
+
+        consts.push(name);
+        let val = self.fold_integer(def.value, consts);
+        consts.pop();
+
+        return val;
+      }
+
+      NodeData::Add(bin)
+      | NodeData::Subtract(bin)
+      | NodeData::Multiply(bin)
+      | NodeData::Divide(bin) => (
+        self.fold_integer(bin.left, consts)?,
+        self.fold_integer(bin.right, consts)?,
+      ),
+
+      _ => return None,
+    };
+
+    match ast.nodes[nidx].data {
+      NodeData::Add(_) => l.checked_add(r),
+      NodeData::Subtract(_) => l.checked_sub(r),
+      NodeData::Multiply(_) => l.checked_mul(r),
+      _ => l.checked_div(r),
+    }
+  }
+
+  // for i in 0..n step 0:
+  //   <body>
+  //
+  // a range that does not advance never reaches its end,
+  // so a step known to be zero or negative is rejected
+  fn check_step(&self, step: NodeIdx) {
+    if let Some(val @ ..=0) = self.fold_integer(step, &mut vec![]) {
+      self.error(
+        self.ast.nodes[step].tok,
+        format!("The step of a range has to be positive, but it is {}", val),
+      );
+    }
+  }
+
+  // desugars a range loop into a conditional loop
+  // around a Phi holding the induction variable
+  //
+  // for i in a..b step c:
+  //   <body>
+  //
+  // bb0:
+  //   %0 = a
+  //   %1 = b
+  //   %2 = c
+  //   %3 = Assign(%0)
+  //   %4 = Jump(bb1)
+  // bb1:
+  //   %5 = Phi(%3, %9)         <- i
+  //   %6 = LessThan(%5, %1)    (LessEqual for ..=)
+  //   %7 = Branch(%6, bb2, bb3)
+  // bb2:
+  //   %8   <body>
+  //   %9 = Add(%5, %2)
+  //   %10 = Jump(bb1)
+  // bb3:
+  //   %11  ...
+  //
+  // the Assign gives the Phi an entry value that is re-evaluated
+  // every time the loop is entered, e.g. when nested in another loop
+  fn emit_for(
+    &mut self,
+    for_loop: &For,
+    tok: usize,
+    builder: &mut IrBuilder,
+  ) -> Result<InstrIdx, String> {
+    let start = self.emit_node(for_loop.start, builder)?;
+    let end = self.emit_node(for_loop.end, builder)?;
+    let step = match for_loop.step {
+      Some(step) => {
+        self.check_step(step);
+        self.emit_node(step, builder)?
+      }
+      None => builder.build_const_integer(1, tok),
+    };
+
+    let header_block = builder.create_block();
+    let body = builder.create_block();
+    let exit = builder.create_block();
+
+    let entry = builder.build_assign(start, tok);
+
+    // the induction variable is always an integer,
+    // the back-edge operand gets added once the body is emitted
+    builder.set_insertion_point(header_block);
+    let header = builder.build_phi(vec![entry], Type::Integer, tok);
+
+    let cond = match for_loop.inclusive {
+      true => builder.build_less_equal(header, end, tok),
+      false => builder.build_less_than(header, end, tok),
+    };
+
+    builder.build_branch(cond, body, exit, tok);
+
+    // the induction variable is bound directly to the Phi,
+    // in a scope of its own around the body
+    builder.set_insertion_point(body);
+    let var = self.ctx.get_str_from_span(for_loop.var);
+    if self.lookup_variable(var).is_some() {
+      self.warning(
+        tok,
+        format!("{} shadows a variable of an enclosing block", var),
+      );
+    }
+
+    self.scopes.push(HashMap::from([(var, header)]));
+    self.induction_vars.push(header);
+    let result = self.emit_block(for_loop.body, false, builder);
+    self.induction_vars.pop();
+    self.scopes.pop();
+    result?;
+
+    let next = builder.build_add(header, step, tok);
+    builder.build_jump(header_block, tok);
+
+    builder.add_phi_operand(header, next);
+    builder.set_insertion_point(exit);
+
+    Ok(header)
+  }
+
+  // returns early with the failure case of an Option or Result,
+  // and otherwise unwraps the value held by it
+  //
+  // <expr>?
+  //
+  // bb0:
+  //   %0 = <expr>
+  //   %1 = IsFailure(%0)
+  //   %2 = Branch(%1, bb1, bb2)
+  // bb1:
+  //   %3 = Failure(%0)
+  //   %4 = Return(%3)
+  // bb2:
+  //   %5 = Unwrap(%0)
+  fn emit_try(
+    &mut self,
+    val: NodeIdx,
+    tok: usize,
+    builder: &mut IrBuilder,
+  ) -> Result<InstrIdx, String> {
+    let val = self.emit_node(val, builder)?;
+
+    let fail_block = builder.create_block();
+    let ok_block = builder.create_block();
+
+    let failed = builder.build_is_failure(val, tok);
+    builder.build_branch(failed, fail_block, ok_block, tok);
+
+    builder.set_insertion_point(fail_block);
+    let failure = builder.build_failure(val, tok);
+    builder.build_return(failure, tok);
+
+    builder.set_insertion_point(ok_block);
+    Ok(builder.build_unwrap(val, tok))
+  }
+
+  fn emit_node(&mut self, nidx: NodeIdx, builder: &mut IrBuilder) -> Result<InstrIdx, String> {
     let node = &self.ast.nodes[nidx];
+    let tok = node.tok;
+
+    Ok(match &node.data {
+      NodeData::Integer(val) => builder.build_const_integer(*val, tok),
+      NodeData::Floating(val) => builder.build_const_float(*val, tok),
 
-    let (instr_val, instr_ty): (InstructionValue, Type) = match &node.data {
-      NodeData::Floating(val) => (InstructionValue::ConstFloat(*val), Type::Floating),
+      NodeData::Identifier(span) => {
+        let name = self.ctx.get_str_from_span(*span);
+
+        // variables are plain references to the instruction holding their value
+        match self.lookup_variable(name) {
+          Some(instr) => instr,
+          None if self.consts.contains_key(name) => {
+            builder.build_const(self.consts[name], Type::Undecided, tok)
+          }
+          None if name == "None" => builder.build_none(tok),
+          None => return self.fail(tok, format!("use of undeclared variable {}", name)),
+        }
+      }
+
+      NodeData::Tuple(vals) => {
+        let mut elements = vec![];
+        for val in vals {
+          elements.push(self.emit_node(*val, builder)?);
+        }
+
+        builder.build_tuple(elements, tok)
+      }
+
+      NodeData::Call(call) => {
+        let name = self.ctx.get_str_from_span(call.name);
+
+        // calling a type converts the argument to it, e.g. UserId(1)
+        if let Some(ty) = self.conversion_type(name) {
+          let [Argument {
+            name: None,
+            value: arg,
+          }] = call.args[..]
+          else {
+            return self.fail(
+              tok,
+              format!("conversion to {} expects a single argument", name),
+            );
+          };
+
+          let arg = self.emit_node(arg, builder)?;
+          builder.build_cast(arg, ty, tok)
+        } else if let Some(constructor) = Self::constructor(name) {
+          let [Argument {
+            name: None,
+            value: arg,
+          }] = call.args[..]
+          else {
+            return self.fail(tok, format!("{} expects a single argument", name));
+          };
+
+          let arg = self.emit_node(arg, builder)?;
+          constructor(builder, arg, tok)
+        } else {
+          let Some(func) = self.functions.get(name).copied() else {
+            return self.fail(tok, format!("call to undeclared function {}", name));
+          };
+
+          self.check_deprecated(func, tok);
+
+          match self.emit_arguments(func, &call.args, tok, builder)? {
+            Some(params) => builder.build_call(func, params, Type::Undecided, tok),
+
+            // the call is replaced with an invalid value,
+            // so that no further errors are reported for it
+            None => builder.build(InstructionValue::ConstInteger(0), Type::Invalid, tok),
+          }
+        }
+      }
+
+      // the receiver is passed as the first parameter,
+      // the method itself is resolved once its type is known
+      NodeData::MethodCall(call) => {
+        let mut params = vec![self.emit_node(call.receiver, builder)?];
+        for arg in call.args.iter() {
+          params.push(self.emit_node(*arg, builder)?);
+        }
+
+        builder.build_method_call(self.ctx.get_str_from_span(call.method), params, tok)
+      }
 
       NodeData::Add(bin) => {
-        let (l, r) = self.emit_binary(bin, buffer)?;
-        (InstructionValue::Add(l, r), Type::Undecided)
+        let (l, r) = self.emit_binary(bin, builder)?;
+        builder.build_add(l, r, tok)
       }
 
       NodeData::Subtract(bin) => {
-        let (l, r) = self.emit_binary(bin, buffer)?;
-        (InstructionValue::Subtract(l, r), Type::Undecided)
+        let (l, r) = self.emit_binary(bin, builder)?;
+        builder.build_sub(l, r, tok)
       }
 
       NodeData::Multiply(bin) => {
-        let (l, r) = self.emit_binary(bin, buffer)?;
-        (InstructionValue::Multiply(l, r), Type::Undecided)
+        let (l, r) = self.emit_binary(bin, builder)?;
+        builder.build_mul(l, r, tok)
       }
 
       NodeData::Divide(bin) => {
-        let (l, r) = self.emit_binary(bin, buffer)?;
-        (InstructionValue::Divide(l, r), Type::Undecided)
+        let (l, r) = self.emit_binary(bin, builder)?;
+        builder.build_div(l, r, tok)
       }
 
-      NodeData::Block(block) => {
-        // block have no Instruction representation,
-        // and they should semantically never be transformed as a value
-        // thus just return a 0 and hope for the best
-        for nidx in block {
-          self.emit_node(*nidx, buffer)?;
-        }
+      NodeData::LessThan(bin) => {
+        let (l, r) = self.emit_binary(bin, builder)?;
+        builder.build_less_than(l, r, tok)
+      }
+
+      NodeData::LessEqual(bin) => {
+        let (l, r) = self.emit_binary(bin, builder)?;
+        builder.build_less_equal(l, r, tok)
+      }
 
-        return Ok(0);
+      // a > b is evaluated as b < a
+      NodeData::GreaterThan(bin) => {
+        let (l, r) = self.emit_binary(bin, builder)?;
+        builder.build_less_than(r, l, tok)
       }
 
+      NodeData::GreaterEqual(bin) => {
+        let (l, r) = self.emit_binary(bin, builder)?;
+        builder.build_less_equal(r, l, tok)
+      }
+
+      NodeData::Block(_) | NodeData::If(_) => match self.emit_branch(nidx, true, builder)? {
+        Some(value) => value,
+        None => {
+          return self.fail(
+            tok,
+            "every branch of an if used as a value returns".to_string(),
+          )
+        }
+      },
+
+      NodeData::For(for_loop) => self.emit_for(for_loop, tok, builder)?,
+
+      NodeData::While(while_loop) => self.emit_while(while_loop, tok, builder)?,
+
+      NodeData::Let(binding) => self.emit_let(binding, tok, builder)?,
+
       NodeData::Return(ret) => {
-        let expr = self.emit_node(*ret, buffer)?;
-        (InstructionValue::Return(expr), Type::Undecided)
+        let expr = self.emit_node(*ret, builder)?;
+        builder.build_return(expr, tok)
       }
 
-      _ => return Err(format!("unknown node in ast->ir emitter {:?}", node)),
+      NodeData::Try(val) => self.emit_try(*val, tok, builder)?,
+
+      _ => return self.fail(tok, format!("unknown node in ast->ir emitter {:?}", node)),
+    })
+  }
+
+  // @method_of is set when emitting the methods of an implementation
+  fn emit_function(
+    &mut self,
+    nidx: NodeIdx,
+    method_of: Option<ImplIdx>,
+  ) -> Result<IrFunction, String> {
+    let Some(Node {
+      data: NodeData::FunctionDef(node),
+      tok,
+    }) = self.ast.nodes.get(nidx)
+    else {
+      panic!();
     };
 
-    buffer.push(Instruction {
-      val: instr_val,
+    let mut builder = IrBuilder::new();
 
-      ty: instr_ty,
-      // TODO: implement this
-      tok: node.tok,
-    });
+    self.type_params = node
+      .type_params
+      .iter()
+      .map(|x| self.ctx.get_str_from_span(x.name))
+      .collect();
 
-    Ok(buffer.len() - 1)
-  }
+    let type_params = node
+      .type_params
+      .iter()
+      .map(|x| {
+        x.bounds
+          .iter()
+          .map(|x| self.lookup_trait(*x, *tok))
+          .collect()
+      })
+      .collect::<Result<Vec<_>, _>>()?;
+
+    let param_types = node
+      .params
+      .iter()
+      .map(|x| self.lower_type(&x.ty, *tok))
+      .collect::<Result<Vec<_>, _>>()?;
+    let return_type = self.lower_type(&node.return_type, *tok)?;
 
-  fn emit_function(&mut self, nidx: NodeIdx) -> Result<IrFunction, String> {
-    let Some(Node{data: NodeData::FunctionDef(node), ..}) = self.ast.nodes.get(nidx) else { panic!(); };
+    // parameters are bound in a scope of their own around the function body
+    let mut params = HashMap::new();
+    for (i, param) in node.params.iter().enumerate() {
+      let instr = builder.build_param(i, param_types[i].clone(), *tok);
 
-    let mut buf = vec![];
-    self.emit_node(node.exec, &mut buf)?;
+      params.insert(self.ctx.get_str_from_span(param.name), instr);
+    }
+
+    self.scopes.push(params);
+    let result = self.emit_block(node.exec, false, &mut builder);
+    self.scopes.pop();
+    result?;
+
+    // falling off the end leaves a function returning Moot,
+    // any other function has to return before, see sema::returns
+    if !builder.is_terminated() {
+      builder.build_exit(*tok);
+    }
 
     Ok(IrFunction {
       name: node.name,
-      instrs: IrBlock(buf),
+      params: param_types,
+      return_type,
+      type_params,
+      type_args: vec![],
+      method_of,
+      linkage: if node.exported {
+        Linkage::Export
+      } else {
+        Linkage::Internal
+      },
+      is_const: node.is_const,
+      inline: node
+        .attributes
+        .iter()
+        .any(|x| matches!(x, Attribute::Inline)),
+      docs: node.docs.clone(),
+      body: builder.finish(),
+    })
+  }
+
+  // the value of a constant is computed by a function
+  // that takes no parameters and returns the value
+  fn emit_const(&mut self, nidx: NodeIdx) -> Result<IrConst, String> {
+    let Node {
+      data: NodeData::ConstDef(def),
+      tok,
+    } = &self.ast.nodes[nidx]
+    else {
+      unreachable!()
+    };
+
+    self.type_params = vec![];
+    let ty = self.lower_type(&def.ty, *tok)?;
+
+    let mut builder = IrBuilder::new();
+
+    let value = self.emit_node(def.value, &mut builder)?;
+    builder.build_return(value, *tok);
+
+    Ok(IrConst {
+      name: def.name,
+      ty: ty.clone(),
+      init: IrFunction {
+        name: def.name,
+        params: vec![],
+        return_type: ty,
+        type_params: vec![],
+        type_args: vec![],
+        method_of: None,
+        linkage: Linkage::Internal,
+        is_const: true,
+        inline: false,
+        docs: vec![],
+        body: builder.finish(),
+      },
+      tok: *tok,
+    })
+  }
+
+  // extern functions only carry a signature,
+  // which has to be expressible in C
+  fn emit_extern(&mut self, nidx: NodeIdx) -> Result<IrFunction, String> {
+    let Node {
+      data: NodeData::Extern(sig),
+      tok,
+    } = &self.ast.nodes[nidx]
+    else {
+      unreachable!()
+    };
+
+    self.type_params = vec![];
+
+    let params = sig
+      .params
+      .iter()
+      .map(|x| self.lower_type(&x.ty, *tok))
+      .collect::<Result<Vec<_>, _>>()?;
+    let return_type = self.lower_type(&sig.return_type, *tok)?;
+
+    let is_scalar = |ty: &Type| matches!(ty, Type::Integer | Type::Floating);
+    if !params.iter().all(is_scalar) || !(is_scalar(&return_type) || return_type == Type::Moot) {
+      return self.fail(
+        *tok,
+        format!(
+          "extern function {} can only take and return Integer and Floating values",
+          self.ctx.get_str_from_span(sig.name)
+        ),
+      );
+    }
+
+    Ok(IrFunction {
+      name: sig.name,
+      params,
+      return_type,
+      type_params: vec![],
+      type_args: vec![],
+      method_of: None,
+      linkage: Linkage::Extern,
+      is_const: false,
+      inline: false,
+      docs: vec![],
+      body: IrBody::empty(),
+    })
+  }
+
+  // aliases are resolved to the type they stand for right away,
+  // newtypes are kept distinct until typechecking is done
+  fn emit_type_definition(&mut self, nidx: NodeIdx) -> Result<Option<IrNewtype>, String> {
+    let Node {
+      data: NodeData::TypeDef(def),
+      tok,
+    } = &self.ast.nodes[nidx]
+    else {
+      unreachable!()
+    };
+
+    let name = self.ctx.get_str_from_span(def.name);
+    if self.type_names.contains_key(name) {
+      return self.fail(*tok, format!("type {} is defined more than once", name));
+    }
+
+    let ty = self.lower_type(&def.ty, *tok)?;
+
+    if def.distinct {
+      self
+        .type_names
+        .insert(name, Type::Newtype(name.to_string()));
+
+      Ok(Some(IrNewtype { name: def.name, ty }))
+    } else {
+      self.type_names.insert(name, ty);
+      Ok(None)
+    }
+  }
+
+  // the method signatures of a trait are lowered with Self as Generic(0)
+  fn emit_trait(&mut self, nidx: NodeIdx) -> Result<IrTrait, String> {
+    let Node {
+      data: NodeData::TraitDef(def),
+      tok,
+    } = &self.ast.nodes[nidx]
+    else {
+      unreachable!()
+    };
+
+    self.type_params = vec![];
+    self.self_type = Some(Type::Generic(0));
+
+    let mut methods: Vec<IrMethodSig> = vec![];
+    for method in def.methods.iter() {
+      let name = self.ctx.get_str_from_span(method.name);
+      if methods
+        .iter()
+        .any(|x| self.ctx.get_str_from_span(x.name) == name)
+      {
+        return self.fail(*tok, format!("method {} is declared more than once", name));
+      }
+
+      methods.push(IrMethodSig {
+        name: method.name,
+        params: method
+          .params
+          .iter()
+          .map(|x| self.lower_type(&x.ty, *tok))
+          .collect::<Result<_, _>>()?,
+        return_type: self.lower_type(&method.return_type, *tok)?,
+      });
+    }
+
+    self.self_type = None;
+
+    Ok(IrTrait {
+      name: def.name,
+      methods,
+      tok: *tok,
+    })
+  }
+
+  // the methods of an implementation are appended to @funcs,
+  // they are only reachable through method calls
+  fn emit_impl(
+    &mut self,
+    nidx: NodeIdx,
+    idx: ImplIdx,
+    funcs: &mut Vec<IrFunction>,
+  ) -> Result<IrImpl, String> {
+    let Node {
+      data: NodeData::Implements(def),
+      tok,
+    } = &self.ast.nodes[nidx]
+    else {
+      unreachable!()
+    };
+
+    self.type_params = vec![];
+    let trait_idx = self.lookup_trait(def.trait_name, *tok)?;
+    let ty = self.lower_type(&def.ty, *tok)?;
+
+    self.self_type = Some(ty.clone());
+
+    let mut methods = vec![];
+    for method in def.methods.iter() {
+      let Node {
+        data: NodeData::FunctionDef(func),
+        tok,
+      } = &self.ast.nodes[*method]
+      else {
+        unreachable!()
+      };
+
+      if !func.type_params.is_empty() {
+        return self.fail(
+          *tok,
+          format!(
+            "method {} can not have type parameters",
+            self.ctx.get_str_from_span(func.name)
+          ),
+        );
+      }
+
+      methods.push(funcs.len());
+      funcs.push(self.emit_function(*method, Some(idx))?);
+    }
+
+    self.self_type = None;
+
+    Ok(IrImpl {
+      trait_idx,
+      ty,
+      methods,
+      tok: *tok,
     })
   }
 
   fn emit_unit(&mut self) -> Result<IrUnit, String> {
     // the node in idx 0 can only be a functiondef if and only if defn main is defined
-    let Node {data: NodeData::FunctionDef(_), ..} = self.ast.nodes[0] else {
-      return Err("Main function is not defined".to_string());
+    let Node {
+      data: NodeData::FunctionDef(_),
+      ..
+    } = self.ast.nodes[0]
+    else {
+      // point at the end of the input, where main is missing from
+      return self.fail(
+        self.ast.toks.len() - 1,
+        "Main function is not defined".to_string(),
+      );
     };
 
+    for (idx, func) in self.ast.funcs.iter().enumerate() {
+      let Node {
+        data: NodeData::FunctionDef(def),
+        tok,
+      } = &self.ast.nodes[*func]
+      else {
+        unreachable!()
+      };
+
+      let name = self.ctx.get_str_from_span(def.name);
+      if self.functions.insert(name, idx).is_some() {
+        return self.fail(*tok, format!("function {} is defined more than once", name));
+      }
+    }
+
+    // extern functions are placed right after the functions
+    for (idx, def) in self.ast.externs.iter().enumerate() {
+      let Node {
+        data: NodeData::Extern(sig),
+        tok,
+      } = &self.ast.nodes[*def]
+      else {
+        unreachable!()
+      };
+
+      let name = self.ctx.get_str_from_span(sig.name);
+      if self
+        .functions
+        .insert(name, self.ast.funcs.len() + idx)
+        .is_some()
+      {
+        return self.fail(*tok, format!("function {} is defined more than once", name));
+      }
+    }
+
+    for (idx, def) in self.ast.traits.iter().enumerate() {
+      let Node {
+        data: NodeData::TraitDef(def),
+        tok,
+      } = &self.ast.nodes[*def]
+      else {
+        unreachable!()
+      };
+
+      let name = self.ctx.get_str_from_span(def.name);
+      if self.traits.insert(name, idx).is_some() {
+        return self.fail(*tok, format!("trait {} is defined more than once", name));
+      }
+    }
+
+    for (idx, def) in self.ast.consts.iter().enumerate() {
+      let Node {
+        data: NodeData::ConstDef(def),
+        tok,
+      } = &self.ast.nodes[*def]
+      else {
+        unreachable!()
+      };
+
+      let name = self.ctx.get_str_from_span(def.name);
+      if self.consts.insert(name, idx).is_some() {
+        return self.fail(*tok, format!("constant {} is defined more than once", name));
+      }
+    }
+
+    // types can only refer to the types defined before them
+    let mut newtypes = vec![];
+
+    for def in self.ast.types.iter() {
+      newtypes.extend(self.emit_type_definition(*def)?);
+    }
+
     let mut funcs = vec![];
 
     for func in self.ast.funcs.iter() {
-      funcs.push(self.emit_function(*func)?);
+      funcs.push(self.emit_function(*func, None)?);
+    }
+
+    for def in self.ast.externs.iter() {
+      funcs.push(self.emit_extern(*def)?);
     }
 
-    Ok(IrUnit { funcs })
+    let mut consts = vec![];
+
+    for def in self.ast.consts.iter() {
+      consts.push(self.emit_const(*def)?);
+    }
+
+    let mut traits = vec![];
+
+    for def in self.ast.traits.iter() {
+      traits.push(self.emit_trait(*def)?);
+    }
+
+    let mut impls = vec![];
+
+    for (idx, def) in self.ast.impls.iter().enumerate() {
+      impls.push(self.emit_impl(*def, idx, &mut funcs)?);
+    }
+
+    Ok(IrUnit {
+      funcs,
+      traits,
+      impls,
+      newtypes,
+      consts,
+      includes: self.ast.includes.clone(),
+    })
   }
 
-  pub fn emit(ast: &'a Ast) -> Result<IrUnit, String> {
-    Self { ast }.emit_unit()
+  // the error the unit failed to emit with has been reported as a diagnostic
+  pub fn emit(ctx: &'a CompilerContext, ast: &'a Ast) -> Result<IrUnit, String> {
+    Self {
+      ctx,
+      ast,
+      functions: HashMap::new(),
+      consts: HashMap::new(),
+      traits: HashMap::new(),
+      type_names: HashMap::new(),
+      type_params: vec![],
+      self_type: None,
+      scopes: vec![],
+      induction_vars: vec![],
+    }
+    .emit_unit()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{context::CompilerContextBuilder, ir::text::FunctionText, parser::Parser, testing};
+
+  #[test]
+  fn skips_the_jump_and_operand_of_a_returning_branch() {
+    let ctx = CompilerContextBuilder::new()
+      .filedata(
+        "defn clamp(x: Integer) -> Integer:
+  let y = if x < 0:
+    return 0
+  else:
+    x
+  if y < 10:
+    return y
+  else:
+    return 10
+
+defn main() -> Integer:
+  return clamp(7)"
+          .into(),
+      )
+      .take();
+
+    let ast = Parser::new(&ctx).unwrap().parse().unwrap();
+    let unit = IrEmitter::emit(&ctx, &ast).unwrap();
+
+    let clamp = unit
+      .funcs
+      .iter()
+      .find(|x| ctx.get_str_from_span(x.name) == "clamp")
+      .unwrap();
+
+    assert_eq!(
+      FunctionText(&ctx, clamp).to_string(),
+      "fn clamp(Integer) -> Integer:
+  bb0:
+    %0 = Param(0) : Integer
+    %1 = ConstInteger(0) : Integer
+    %2 = LessThan(%0, %1) : Boolean
+    %3 = Branch(%2, bb1, bb2) : Moot
+  bb1:
+    %4 = ConstInteger(0) : Integer
+    %5 = Return(%4) : Integer
+  bb2:
+    %6 = Assign(%0) : Integer
+    %7 = Jump(bb3) : Moot
+  bb3:
+    %8 = Assign(%6) : Undecided
+    %9 = ConstInteger(10) : Integer
+    %10 = LessThan(%8, %9) : Boolean
+    %11 = Branch(%10, bb4, bb5) : Moot
+  bb4:
+    %12 = Return(%8) : Undecided
+  bb5:
+    %13 = ConstInteger(10) : Integer
+    %14 = Return(%13) : Integer
+  bb6:
+    %15 = Exit : Moot
+"
+    );
+  }
+
+  #[test]
+  fn reports_errors_at_their_node() {
+    for (src, line) in [
+      (
+        "defn main() -> Integer:
+  let x = if 1 < 2: 3
+  return x",
+        "2:      let x = i\nError: | an if used as a value needs an else branch",
+      ),
+      (
+        "defn main() -> Integer:
+  let x = if 1 < 2:
+    let y = 1
+  else:
+    2
+  return x",
+        "3:        l\nError: | the last statement of a block used as a value has to be an expression",
+      ),
+      (
+        "defn main() -> Integer:
+  do:
+    let y = 1
+  return y",
+        "4:      return y\nError: | use of undeclared variable y",
+      ),
+    ] {
+      let ctx = CompilerContextBuilder::new().filedata(src.into()).take();
+      let ast = Parser::new(&ctx).unwrap().parse().unwrap();
+
+      assert!(IrEmitter::emit(&ctx, &ast).is_err());
+
+      let diagnostics = ctx.get_diagnostics();
+      assert_eq!(diagnostics.len(), 1);
+      assert_eq!(diagnostics[0].display(&ctx, &ast.toks), line);
+    }
+  }
+
+  #[test]
+  fn binds_parameters_and_loop_variables_around_the_body() {
+    let ctx = testing::context(
+      "defn f(n: Integer) -> Integer:
+  let n = 5
+  for i in 0..n:
+    for i in 0..2:
+      let j = i
+    do:
+      let i = 9
+  return n
+
+defn main() -> Integer:
+  return f(1)",
+    );
+
+    assert!(IrEmitter::emit(&ctx, &testing::parse(&ctx)).is_ok());
+    assert_eq!(
+      testing::diagnostics(&ctx),
+      [
+        "n shadows a variable of an enclosing block",
+        "i shadows a variable of an enclosing block",
+        "i is the variable of a range loop, and can not be rebound in its body"
+      ]
+    );
+  }
+
+  #[test]
+  fn rejects_steps_that_are_not_positive() {
+    let ctx = testing::context(
+      "const STEP: Integer = 2 - 2
+const A: Integer = B
+const B: Integer = A
+
+defn main() -> Integer:
+  for i in 0..10 step STEP:
+    return i
+  for j in 0..3 step 0 - 1:
+    return j
+  for k in 0..3 step 3:
+    return k
+  for m in 0..3 step A:
+    return m
+  return 0",
+    );
+
+    assert!(IrEmitter::emit(&ctx, &testing::parse(&ctx)).is_ok());
+    assert_eq!(
+      testing::diagnostics(&ctx),
+      [
+        "The step of a range has to be positive, but it is 0",
+        "The step of a range has to be positive, but it is -1"
+      ]
+    );
   }
 }
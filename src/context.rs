@@ -1,6 +1,4 @@
-use std::{
-  cell::{Ref, RefCell},
-};
+use std::cell::{Ref, RefCell};
 
 use crate::{diagnostic::Diagnostic, optimizers::OptimizerFlags, token::Span};
 
@@ -13,6 +11,9 @@ pub struct CompilerContext {
   filedata: String,
   verbose: bool,
 
+  // number of columns a tab advances the indentation to the next multiple of
+  tab_width: usize,
+
   optimizer_flags: OptimizerFlags,
 
   diagnostics: RefCell<Vec<Diagnostic>>,
@@ -23,22 +24,27 @@ impl CompilerContext {
   // always inline these
 
   #[inline(always)]
-  pub fn get_input_str(self: &Self) -> &str {
+  pub fn get_input_str(&self) -> &str {
     &self.filedata
   }
 
   #[inline(always)]
-  pub fn get_str_from_span(self: &Self, span: Span) -> &str {
+  pub fn get_str_from_span(&self, span: Span) -> &str {
     &self.filedata[span.start..span.end]
   }
 
   #[inline(always)]
-  pub fn get_diagnostics(&self) -> Ref<Vec<Diagnostic>> {
+  pub fn get_tab_width(&self) -> usize {
+    self.tab_width
+  }
+
+  #[inline(always)]
+  pub fn get_diagnostics(&self) -> Ref<'_, Vec<Diagnostic>> {
     self.diagnostics.borrow()
   }
 
   #[inline(always)]
-  pub fn get_optimizer_flags(self: &Self) -> OptimizerFlags {
+  pub fn get_optimizer_flags(&self) -> OptimizerFlags {
     self.optimizer_flags.clone()
   }
 
@@ -58,6 +64,7 @@ impl CompilerContextBuilder {
     Self {
       ctx: CompilerContext {
         verbose: false,
+        tab_width: 4,
         ..Default::default()
       },
     }
@@ -73,6 +80,11 @@ impl CompilerContextBuilder {
     self
   }
 
+  pub fn tab_width(mut self, tab_width: usize) -> Self {
+    self.ctx.tab_width = tab_width.max(1);
+    self
+  }
+
   pub fn flags(mut self, flags: OptimizerFlags) -> Self {
     self.ctx.optimizer_flags = flags;
     self
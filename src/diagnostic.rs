@@ -23,13 +23,26 @@ pub struct Diagnostic {
 impl Diagnostic {
   // convert the diagnostic to a printable string
   // requires context and tokens for lookup purposes
-  pub fn display(&self, ctx: &CompilerContext, toks: &Vec<Token>) -> String {
+  pub fn display(&self, ctx: &CompilerContext, toks: &[Token]) -> String {
+    self.display_at(ctx, toks[self.tokidx].span.start)
+  }
+
+  // same as display, but for a position in the input,
+  // used before there are any tokens to point at, e.g. by the lexer
+  pub fn display_at(&self, ctx: &CompilerContext, pos: usize) -> String {
     let str = ctx.get_input_str();
 
+    // include the character at the position,
+    // however many bytes it takes up
+    let mut end = (pos + 1).min(str.len());
+    while !str.is_char_boundary(end) {
+      end += 1;
+    }
+
     // find the line position in input
-    let lines = str[..toks[self.tokidx].span.start + 1].lines();
+    let lines = str[..end].lines();
     let line = lines.clone().count();
-    let line_data = lines.last().unwrap();
+    let line_data = lines.last().unwrap_or_default();
 
     let out = format!(
       "{}:    {}\n{}: | {}",
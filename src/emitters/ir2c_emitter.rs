@@ -1,25 +1,36 @@
-use std::rc::Rc;
+use std::collections::{HashMap, HashSet};
 
 use crate::{
   context::CompilerContext,
-  ir::{InstructionValue, IrFunction, IrUnit, Type},
+  ir::{
+    self,
+    thir::{self, Type},
+    BlockIdx, InstrIdx, InstructionValue, Linkage,
+  },
   parser::Ast,
 };
 
 pub struct Ir2CEmitterContext<'a> {
   ctx: &'a CompilerContext,
   ast: &'a Ast,
-  unit: IrUnit,
+  unit: thir::Unit,
+}
+
+// the generated C source,
+// and a header declaring the exported functions if there are any
+pub struct CFiles {
+  pub source: String,
+  pub header: Option<String>,
 }
 
 struct FunctionEmitter<'a> {
   emitter: &'a Ir2CEmitterContext<'a>,
-  function: &'a IrFunction,
+  function: &'a thir::Function,
 }
 
 impl<'a> crate::emitter::Emitter<'a> for Ir2CEmitterContext<'a> {
-  type Input = IrUnit;
-  type Output = Result<String, String>;
+  type Input = thir::Unit;
+  type Output = Result<CFiles, String>;
 
   fn emit(ctx: &'a CompilerContext, ast: &'a Ast, unit: Self::Input) -> Self::Output {
     Self { ctx, ast, unit }.inner_emit()
@@ -27,24 +38,178 @@ impl<'a> crate::emitter::Emitter<'a> for Ir2CEmitterContext<'a> {
 }
 
 impl<'a> Ir2CEmitterContext<'a> {
-  fn emit_type(&self, ty: Type) -> String {
+  // a C identifier unique to each type,
+  // used to name the generated structs, as well as the instantiations
+  // and methods named after the types they were written with
+  fn mangle_type(ty: &ir::Type) -> String {
+    let components = ty
+      .components()
+      .into_iter()
+      .map(Self::mangle_type)
+      .collect::<Vec<_>>()
+      .join("_");
+
+    match ty {
+      ir::Type::Tuple(types) => format!("tuple{}_{}", types.len(), components),
+      ir::Type::Option(_) => format!("option_{}", components),
+      ir::Type::Result(..) => format!("result_{}", components),
+      ir::Type::Pointer(_) => format!("ptr_{}", components),
+
+      ty => ty.to_string(),
+    }
+  }
+
+  fn emit_type(&self, ty: &Type) -> String {
     match ty {
       Type::Floating => "double".to_string(),
       Type::Integer => "long long".to_string(),
+      Type::Boolean => "int".to_string(),
       Type::Moot => "void".to_string(),
 
-      Type::Undecided | Type::Invalid => "ran into invalid types in typechecker".to_string(),
+      Type::Tuple(_) | Type::Option(_) | Type::Result(..) => Self::mangle_type(&ty.into()),
+
+      Type::Pointer(ty) => format!("{} *", self.emit_type(ty)),
+    }
+  }
+
+  // instantiations of generic functions get their type arguments
+  // appended to their name, e.g. max[Integer] -> max__Integer
+  fn function_name(&self, function: &thir::Function) -> String {
+    let name = self.ctx.get_str_from_span(function.name);
+
+    // methods are named after their trait and implementing type,
+    // e.g. Len__tuple2_Integer_Integer__len
+    if let Some(imp) = function.method_of {
+      let imp = &self.unit.impls[imp];
+
+      return format!(
+        "{}__{}__{}",
+        self
+          .ctx
+          .get_str_from_span(self.unit.traits[imp.trait_idx].name),
+        Self::mangle_type(&imp.ty),
+        name
+      );
+    }
+
+    if function.type_args.is_empty() {
+      name.to_string()
+    } else {
+      format!(
+        "{}__{}",
+        name,
+        function
+          .type_args
+          .iter()
+          .map(Self::mangle_type)
+          .collect::<Vec<_>>()
+          .join("_")
+      )
+    }
+  }
+
+  // collects every compound type within @ty,
+  // each one after the types it is built out of
+  //
+  // pointers are not structs, only the type they point to may be one
+  fn collect_compound_types(ty: &Type, out: &mut Vec<Type>) {
+    if let Type::Pointer(ty) = ty {
+      return Self::collect_compound_types(ty, out);
+    }
+
+    let components = ty.components();
+
+    if components.is_empty() {
+      return;
+    }
+
+    for ty in components {
+      Self::collect_compound_types(ty, out);
+    }
+
+    if !out.contains(ty) {
+      out.push(ty.clone());
+    }
+  }
+
+  // a struct field, moot fields are left out as they can not be stored
+  fn emit_field(&self, ty: &Type, name: &str) -> String {
+    if *ty == Type::Moot {
+      String::new()
+    } else {
+      format!("{} {};\n", self.emit_type(ty), name)
+    }
+  }
+
+  // tuples are emitted as structs with one field per element,
+  // options and results as structs with a tag that is 1 for Some and Ok
+  fn emit_compound_types(&self, buffer: &mut String, compounds: &[Type]) {
+    for compound in compounds.iter() {
+      buffer.push_str("typedef struct {\n");
+
+      match compound {
+        Type::Tuple(types) => {
+          for (i, ty) in types.iter().enumerate() {
+            buffer.push_str(&self.emit_field(ty, &format!("_{}", i)));
+          }
+        }
+
+        Type::Option(ty) => {
+          buffer.push_str("int tag;\n");
+          buffer.push_str(&self.emit_field(ty, "value"));
+        }
+
+        Type::Result(ok, err) => {
+          buffer.push_str("int tag;\n");
+          buffer.push_str(&self.emit_field(ok, "value"));
+          buffer.push_str(&self.emit_field(err, "err"));
+        }
+
+        _ => unreachable!(),
+      }
+
+      buffer.push_str(&format!("}} {};\n\n", self.emit_type(compound)));
     }
   }
 
+  fn emit_signature(&self, function: &thir::Function) -> String {
+    let name_str = self.function_name(function);
+
+    // main has to follow the C signature for main
+    if name_str == "main" {
+      return "extern int main()".to_string();
+    }
+
+    let params = function
+      .params
+      .iter()
+      .enumerate()
+      .map(|(i, ty)| format!("{} PARAM{}", self.emit_type(ty), i))
+      .collect::<Vec<_>>()
+      .join(", ");
+
+    format!(
+      "{} {} {}({})",
+      match function.linkage {
+        Linkage::Internal if function.inline => "static inline",
+        Linkage::Internal => "static",
+        Linkage::Extern | Linkage::Export => "extern",
+      },
+      self.emit_type(&function.return_type),
+      name_str,
+      if params.is_empty() { "void" } else { &params },
+    )
+  }
+
   // TODO:
   //   implement custom add/sub/mul/div binary functions
   //   for each type
 
   fn generate_binary_functions(&self, buffer: &mut String, ty: Type) {
     match ty {
-      Type::Undecided => panic!("ran into a non-propogated type in ir2c"),
-      Type::Invalid => panic!("ran into invalid type in ir2c"),
+      Type::Tuple(_) | Type::Option(_) | Type::Result(..) | Type::Pointer(_) => {
+        panic!("ran into a compound type while generating binary functions")
+      }
 
       Type::Floating => buffer.push_str(
         "
@@ -62,78 +227,310 @@ impl<'a> Ir2CEmitterContext<'a> {
           ",
       ),
 
-      // can not generate binary operation for moot
-      Type::Moot => {}
+      // can not generate binary operation for moot or booleans
+      Type::Moot | Type::Boolean => {}
     }
   }
 
+  // a designated initializer for a struct field,
+  // moot values have no field to initialize
+  fn initializer(&self, function: &thir::Function, field: &str, val: InstrIdx) -> Option<String> {
+    (function.body.instrs[val].ty != Type::Moot).then(|| format!(".{} = TEMP{}", field, val))
+  }
+
   fn emit_instruction(
     &self,
     buffer: &mut String,
-    function: &IrFunction,
+    function: &thir::Function,
+    phis: &HashMap<InstrIdx, Vec<InstrIdx>>,
     instridx: usize,
   ) -> Result<(), String> {
-    let instr = &function.instrs.0[instridx];
+    let instr = &function.body.instrs[instridx];
 
-    let expr = match instr.val {
-      InstructionValue::ConstInteger(i) => {
-        format!("long long TEMP{} = (long long){};", instridx, i)
+    let value = match instr.val {
+      InstructionValue::ConstInteger(i) => format!("(long long){}", i),
+
+      InstructionValue::ConstFloat(f) => format!("(double){:?}", f),
+
+      InstructionValue::Param(n) => format!("PARAM{}", n),
+
+      InstructionValue::Assign(val) => format!("TEMP{}", val),
+
+      InstructionValue::Add(l, r) => format!("TEMP{} + TEMP{}", l, r),
+      InstructionValue::Subtract(l, r) => format!("TEMP{} - TEMP{}", l, r),
+      InstructionValue::Multiply(l, r) => format!("TEMP{} * TEMP{}", l, r),
+      InstructionValue::Divide(l, r) => format!("TEMP{} / TEMP{}", l, r),
+
+      InstructionValue::LessThan(l, r) => format!("TEMP{} < TEMP{}", l, r),
+      InstructionValue::LessEqual(l, r) => format!("TEMP{} <= TEMP{}", l, r),
+
+      InstructionValue::Tuple(ref vals) => format!(
+        "({}){{{}}}",
+        self.emit_type(&instr.ty),
+        vals
+          .iter()
+          .enumerate()
+          .filter_map(|(i, x)| self.initializer(function, &format!("_{}", i), *x))
+          .collect::<Vec<_>>()
+          .join(", ")
+      ),
+
+      InstructionValue::TupleGet(tuple, n) => format!("TEMP{}._{}", tuple, n),
+
+      InstructionValue::OptionSome(val) | InstructionValue::ResultOk(val) => format!(
+        "({}){{{}}}",
+        self.emit_type(&instr.ty),
+        std::iter::once(".tag = 1".to_string())
+          .chain(self.initializer(function, "value", val))
+          .collect::<Vec<_>>()
+          .join(", ")
+      ),
+
+      InstructionValue::ResultErr(val) => format!(
+        "({}){{{}}}",
+        self.emit_type(&instr.ty),
+        std::iter::once(".tag = 0".to_string())
+          .chain(self.initializer(function, "err", val))
+          .collect::<Vec<_>>()
+          .join(", ")
+      ),
+
+      InstructionValue::OptionNone => format!("({}){{.tag = 0}}", self.emit_type(&instr.ty)),
+
+      InstructionValue::IsFailure(val) => format!("!TEMP{}.tag", val),
+
+      InstructionValue::Unwrap(val) => format!("TEMP{}.value", val),
+
+      InstructionValue::UnwrapOr(val, default) => {
+        format!("TEMP{0}.tag ? TEMP{0}.value : TEMP{1}", val, default)
       }
 
-      InstructionValue::ConstFloat(f) => format!("double TEMP{} = (double){}f;", instridx, f),
+      // None, or an Err holding the same error
+      InstructionValue::Failure(val) => match instr.ty {
+        Type::Result(_, ref err) if **err != Type::Moot => format!(
+          "({}){{.tag = 0, .err = TEMP{}.err}}",
+          self.emit_type(&instr.ty),
+          val
+        ),
 
-      InstructionValue::Add(l, r) => {
-        self.emit_instruction(buffer, function, l)?;
-        self.emit_instruction(buffer, function, r)?;
-        format!(
-          "{} TEMP{} = TEMP{} + TEMP{};",
-          self.emit_type(instr.ty),
-          instridx,
-          l,
-          r
-        )
+        _ => format!("({}){{.tag = 0}}", self.emit_type(&instr.ty)),
+      },
+
+      // tuples can not be cast in C, and only ever convert to themselves
+      InstructionValue::Cast(val, ref ty) if *ty == function.body.instrs[val].ty => {
+        format!("TEMP{}", val)
       }
+      InstructionValue::Cast(val, ref ty) => format!("({})TEMP{}", self.emit_type(ty), val),
+
+      InstructionValue::Alloca(_) => format!("&SLOT{}", instridx),
+
+      InstructionValue::Load(ptr) => format!("*TEMP{}", ptr),
+
+      // moot elements have no field to point to,
+      // see emit_field
+      InstructionValue::GetElementAddr(ptr, _)
+        if instr.ty == Type::Pointer(Box::new(Type::Moot)) =>
+      {
+        format!("(void *)TEMP{}", ptr)
+      }
+      InstructionValue::GetElementAddr(ptr, n) => format!("&TEMP{}->_{}", ptr, n),
+
+      InstructionValue::Call { func, ref params } => format!(
+        "{}({})",
+        self.function_name(&self.unit.funcs[func]),
+        params
+          .iter()
+          .map(|x| format!("TEMP{}", x))
+          .collect::<Vec<_>>()
+          .join(", ")
+      ),
+
+      _ => String::new(),
+    };
+
+    let expr = match instr.val {
+      // a phi is written to by each of its operands
+      InstructionValue::Phi(_) => String::new(),
+
+      InstructionValue::Nop => String::new(),
+
+      InstructionValue::Jump(target) => format!("goto BB{};", target),
+      InstructionValue::Branch(cond, then, otherwise) => format!(
+        "if (TEMP{}) goto BB{}; else goto BB{};",
+        cond, then, otherwise
+      ),
 
       InstructionValue::Return(i) => format!("return TEMP{};", i),
 
-      _ => unimplemented!(),
+      // only a function returning void gets to its end
+      InstructionValue::Exit if function.return_type == Type::Moot => "return;".to_string(),
+      InstructionValue::Exit => String::new(),
+
+      InstructionValue::Store(_, val) if function.body.instrs[val].ty == Type::Moot => {
+        String::new()
+      }
+      InstructionValue::Store(ptr, val) => format!("*TEMP{} = TEMP{};", ptr, val),
+
+      // moot values are never stored,
+      // only calls have to be emitted for their side effects
+      InstructionValue::Call { .. } if instr.ty == Type::Moot => format!("{};", value),
+      _ if instr.ty == Type::Moot => String::new(),
+
+      _ => format!("TEMP{} = {};", instridx, value),
     };
 
-    buffer.push_str(&expr);
-    buffer.push('\n');
+    if !expr.is_empty() {
+      buffer.push_str(&expr);
+      buffer.push('\n');
+    }
+
+    // forward the value into every phi that it is an operand of
+    if let Some(targets) = phis.get(&instridx) {
+      for phi in targets {
+        buffer.push_str(&format!("TEMP{} = TEMP{};\n", phi, instridx));
+      }
+    }
 
     Ok(())
   }
 
-  fn emit_function(&self, function: &IrFunction) -> Result<String, String> {
-    let name_str = self.ctx.get_str_from_span(function.name);
-    let linkage = if name_str == "main" {
-      "extern"
-    } else {
-      "static"
-    };
+  fn emit_function(&self, function: &thir::Function) -> Result<String, String> {
+    let mut buf = format!("{} {{\n", self.emit_signature(function));
 
-    let mut buf = format!(
-      "{linkage} int {name}() {{\n",
-      linkage = linkage,
-      name = name_str
-    );
+    let instrs = &function.body.instrs;
 
-    for idx in 0..function.instrs.0.len() {
-      self.emit_instruction(&mut buf, function, idx)?;
+    // every value is declared upfront,
+    // as jumps are allowed to cross their definitions
+    for (idx, instr) in instrs.iter().enumerate() {
+      match instr.val {
+        InstructionValue::Jump(_)
+        | InstructionValue::Branch(..)
+        | InstructionValue::Return(_)
+        | InstructionValue::Exit
+        | InstructionValue::Nop => {}
+
+        _ if instr.ty == Type::Moot => {}
+
+        // the slot itself, and the pointer to it
+        InstructionValue::Alloca(ref ty) => {
+          buf.push_str(&format!("{} SLOT{};\n", self.emit_type(ty), idx));
+          buf.push_str(&format!("{} TEMP{};\n", self.emit_type(&instr.ty), idx));
+        }
+
+        _ => buf.push_str(&format!("{} TEMP{};\n", self.emit_type(&instr.ty), idx)),
+      }
+    }
+
+    let mut phis: HashMap<InstrIdx, Vec<InstrIdx>> = HashMap::new();
+    let mut labels: HashSet<BlockIdx> = HashSet::new();
+
+    for (idx, instr) in instrs.iter().enumerate() {
+      if let InstructionValue::Phi(ref vals) = instr.val {
+        for val in vals {
+          phis.entry(*val).or_default().push(idx);
+        }
+      }
+
+      labels.extend(instr.val.successors());
+    }
+
+    // only the blocks that are jumped to get a label
+    for (n, block) in function.body.blocks.iter().enumerate() {
+      if labels.contains(&n) {
+        buf.push_str(&format!("BB{}:;\n", n));
+      }
+
+      for idx in block.instrs() {
+        self.emit_instruction(&mut buf, function, &phis, idx)?;
+      }
     }
 
     buf.push_str("}\n\n");
     Ok(buf)
   }
 
-  fn inner_emit(self) -> Result<String, String> {
+  // declares the exported functions, along with the types of their signatures
+  fn emit_header(&self) -> Option<String> {
+    let exports = self
+      .unit
+      .funcs
+      .iter()
+      .filter(|x| x.linkage == Linkage::Export)
+      .collect::<Vec<_>>();
+
+    if exports.is_empty() {
+      return None;
+    }
+
+    let mut compounds = vec![];
+
+    for func in exports.iter() {
+      for ty in func.params.iter().chain(std::iter::once(&func.return_type)) {
+        Self::collect_compound_types(ty, &mut compounds);
+      }
+    }
+
+    let mut buffer = "#pragma once\n\n#ifdef __cplusplus\nextern \"C\" {\n#endif\n\n".to_string();
+
+    self.emit_compound_types(&mut buffer, &compounds);
+
+    // the documentation of a function is kept next to its declaration
+    for func in exports {
+      for doc in func.docs.iter() {
+        buffer.push_str(&format!("// {}\n", self.ctx.get_str_from_span(*doc)));
+      }
+
+      buffer.push_str(&format!("{};\n", self.emit_signature(func)));
+    }
+
+    buffer.push_str("\n#ifdef __cplusplus\n}\n#endif\n");
+
+    Some(buffer)
+  }
+
+  fn inner_emit(self) -> Result<CFiles, String> {
     let mut file_buf = String::new();
 
+    for header in self.unit.includes.iter() {
+      file_buf.push_str(&format!(
+        "#include \"{}\"\n",
+        self.ctx.get_str_from_span(*header)
+      ));
+    }
+    if !self.unit.includes.is_empty() {
+      file_buf.push('\n');
+    }
+
+    let mut compounds = vec![];
+
+    for func in self.unit.funcs.iter() {
+      for ty in func.params.iter().chain(std::iter::once(&func.return_type)) {
+        Self::collect_compound_types(ty, &mut compounds);
+      }
+
+      for instr in func.body.instrs.iter() {
+        Self::collect_compound_types(&instr.ty, &mut compounds);
+      }
+    }
+
+    self.emit_compound_types(&mut file_buf, &compounds);
+
+    // prototypes allow functions to be called before their definition
     for func in self.unit.funcs.iter() {
-      file_buf.push_str(&self.emit_function(func)?);
+      file_buf.push_str(&format!("{};\n", self.emit_signature(func)));
+    }
+    file_buf.push('\n');
+
+    // extern functions are defined elsewhere
+    for func in self.unit.funcs.iter() {
+      if func.linkage != Linkage::Extern {
+        file_buf.push_str(&self.emit_function(func)?);
+      }
     }
 
-    Ok(file_buf)
+    Ok(CFiles {
+      source: file_buf,
+      header: self.emit_header(),
+    })
   }
 }
@@ -1,22 +1,20 @@
-use std::fmt::Display;
-
 use crate::{
   context::CompilerContext,
   emitter::Emitter,
-  ir::{Instruction, InstructionValue, IrFunction, IrUnit},
+  ir::{thir, Instruction},
   parser::Ast,
 };
 
 pub struct X86EmitterContext<'a> {
   ctx: &'a CompilerContext,
   ast: &'a Ast,
-  unit: IrUnit,
+  unit: thir::Unit,
 
   buffer: String,
 }
 
 impl<'a> Emitter<'a> for X86EmitterContext<'a> {
-  type Input = IrUnit;
+  type Input = thir::Unit;
   type Output = Result<String, String>;
 
   fn emit(ctx: &'a CompilerContext, ast: &'a Ast, unit: Self::Input) -> Self::Output {
@@ -33,9 +31,9 @@ impl<'a> Emitter<'a> for X86EmitterContext<'a> {
 // "const" register : %rbx
 
 impl<'a> X86EmitterContext<'a> {
-  fn inner_start_emit(mut self) -> Result<String, String> {
+  fn inner_start_emit(self) -> Result<String, String> {
     for func in self.unit.funcs.iter() {
-      let out = FunctionEmitter::emit(&self, &func);
+      let _out = FunctionEmitter::emit(&self, func);
     }
 
     Ok(self.buffer)
@@ -44,13 +42,13 @@ impl<'a> X86EmitterContext<'a> {
 
 struct FunctionEmitter<'a> {
   sema: &'a X86EmitterContext<'a>,
-  func: &'a IrFunction,
+  func: &'a thir::Function,
 
   out_buffer: String,
 }
 
 impl<'a> FunctionEmitter<'a> {
-  fn emit(ctx: &'a X86EmitterContext, func: &'a IrFunction) -> String {
+  fn emit(ctx: &'a X86EmitterContext, func: &'a thir::Function) -> String {
     Self {
       sema: ctx,
       func,
@@ -60,7 +58,7 @@ impl<'a> FunctionEmitter<'a> {
     .out_buffer
   }
 
-  fn emit_instruction(&mut self, instr: &Instruction) {
+  fn emit_instruction(&mut self, _instr: &Instruction<thir::Type>) {
     // let out = match instr {
     //   InstructionValue::ConstInteger(i) => self.emit_const(i),
     //   InstructionValue::ConstFloat(f) => self.emit_const(f),
@@ -80,7 +78,7 @@ impl<'a> FunctionEmitter<'a> {
 
     self.out_buffer.push_str(prelude.as_str());
 
-    for i in self.func.instrs.0.iter() {
+    for i in self.func.body.instrs.iter() {
       self.emit_instruction(i);
     }
     self
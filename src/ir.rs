@@ -1,10 +1,21 @@
-use std::fmt::Display;
+use std::{fmt::Display, ops::Range};
 
 use crate::{
   context::CompilerContext,
   token::{Span, TokIdx},
 };
 
+use builder::IrBuilder;
+
+pub mod builder;
+pub mod cfg;
+pub mod dom;
+pub mod interpreter;
+pub mod ssa;
+pub mod text;
+pub mod thir;
+pub mod verify;
+
 /*
 
 defn main():
@@ -39,16 +50,17 @@ else
 
 goes into:
 
-%0 ConstBool(False)
-%1 CondJmp(%0, %3)
-%2 Jump(%6)
-
-%3 ConstInt(3)
-%4 Jump(%6)
-
-%5 ConstInt(5)
-
-%6 Phi(%3, %5)
+bb0:
+  %0 ConstBool(False)
+  %1 Branch(%0, bb1, bb2)
+bb1:
+  %2 ConstInt(3)
+  %3 Jump(bb3)
+bb2:
+  %4 ConstInt(5)
+  %5 Jump(bb3)
+bb3:
+  %6 Phi(%2, %4)
 
 
 
@@ -87,14 +99,33 @@ multiple level of IRs?
   contains dataflow, but as a flat map
   where most of the optimizations take place
 
+both share the same instructions, and only differ in the type
+every instruction carries, see IrType,
+sema is the only step converting the former into the latter (see thir)
+
 */
 
 pub type InstrIdx = usize;
 
+// index into IrBody::blocks
+pub type BlockIdx = usize;
+
+// index into IrUnit::funcs
+pub type FuncIdx = usize;
+
+// index into IrUnit::traits
+pub type TraitIdx = usize;
+
+// index into IrUnit::impls
+pub type ImplIdx = usize;
+
+// index into IrUnit::consts
+pub type ConstIdx = usize;
+
 #[derive(Debug, Clone)]
-pub enum InstructionValue {
+pub enum InstructionValue<T = Type> {
   // attempts to perform typecasting to a specified type
-  Cast(InstrIdx, Type),
+  Cast(InstrIdx, T),
 
   Assign(InstrIdx),
 
@@ -107,26 +138,254 @@ pub enum InstructionValue {
   ConstFloat(f64),
   ConstInteger(i64),
 
+  // the value of the n-th parameter of the function
+  Param(usize),
+
+  // the value of a constant,
+  // replaced with the computed value once it is evaluated
+  Const(ConstIdx),
+
+  // builds a tuple out of a list of values
+  Tuple(Vec<InstrIdx>),
+
+  // reads the n-th element of a tuple
+  TupleGet(InstrIdx, usize),
+
+  // constructors of Option[T] and Result[T, E]
+  OptionSome(InstrIdx),
+  OptionNone,
+  ResultOk(InstrIdx),
+  ResultErr(InstrIdx),
+
+  // whether an Option or Result holds None or an Err
+  IsFailure(InstrIdx),
+
+  // the value held by a Some or Ok
+  Unwrap(InstrIdx),
+
+  // the value held by a Some or Ok, or the second operand otherwise
+  UnwrapOr(InstrIdx, InstrIdx),
+
+  // the failure case of an Option or Result converted to the
+  // return type of the function, i.e. None or Err(<the held error>),
+  // returned by the ? operator
+  Failure(InstrIdx),
+
+  // reserves a stack slot holding a value of the type,
+  // results in a pointer to the slot that lives until the function returns,
+  // each call gets one slot per Alloca, however often it is executed
+  Alloca(T),
+
+  // reads the value behind a pointer
+  Load(InstrIdx),
+
+  // writes the second operand behind the pointer of the first
+  Store(InstrIdx, InstrIdx),
+
+  // a pointer to the n-th element of the tuple behind a pointer
+  GetElementAddr(InstrIdx, usize),
+
   // index into local temps
   Add(InstrIdx, InstrIdx),
   Subtract(InstrIdx, InstrIdx),
   Multiply(InstrIdx, InstrIdx),
   Divide(InstrIdx, InstrIdx),
 
+  // comparisons, result in a Boolean
+  LessThan(InstrIdx, InstrIdx),
+  LessEqual(InstrIdx, InstrIdx),
+
+  // the terminators, every basic block ends with exactly one of them
+
+  // unconditionally continue execution at the start of a block
+  Jump(BlockIdx),
+
+  // continue execution at the first block if the condition (a Boolean)
+  // is true, and at the second one otherwise
+  Branch(InstrIdx, BlockIdx, BlockIdx),
+
   Return(InstrIdx),
 
+  // leaves a function returning Moot, by reaching its end
+  Exit,
+
   Call {
-    // index into the token list to an identifier
-    name: TokIdx,
+    // the called function
+    func: FuncIdx,
 
     // list of locals to pass as parameters
     params: Vec<InstrIdx>,
   },
+
+  // a call to a trait method, resolved to a Call
+  // once the type of the receiver (the first parameter) is known
+  MethodCall {
+    method: String,
+    params: Vec<InstrIdx>,
+  },
+
+  // an instruction removed by an optimization,
+  // left in place so that the ids of the other instructions stay the same
+  // until the block gets flattened
+  Nop,
+}
+
+impl<T: Clone> InstructionValue<T> {
+  // applies @f to every instruction referenced by this one,
+  // the targets of jumps are blocks and stay as they are
+  pub fn map_refs(&self, mut f: impl FnMut(InstrIdx) -> InstrIdx) -> Self {
+    match self {
+      Self::Cast(val, ty) => Self::Cast(f(*val), ty.clone()),
+      Self::Assign(val) => Self::Assign(f(*val)),
+      Self::Phi(vals) => Self::Phi(vals.iter().map(|x| f(*x)).collect()),
+      Self::Tuple(vals) => Self::Tuple(vals.iter().map(|x| f(*x)).collect()),
+      Self::TupleGet(val, n) => Self::TupleGet(f(*val), *n),
+
+      Self::OptionSome(val) => Self::OptionSome(f(*val)),
+      Self::ResultOk(val) => Self::ResultOk(f(*val)),
+      Self::ResultErr(val) => Self::ResultErr(f(*val)),
+      Self::IsFailure(val) => Self::IsFailure(f(*val)),
+      Self::Unwrap(val) => Self::Unwrap(f(*val)),
+      Self::UnwrapOr(val, default) => Self::UnwrapOr(f(*val), f(*default)),
+      Self::Failure(val) => Self::Failure(f(*val)),
+
+      Self::Load(ptr) => Self::Load(f(*ptr)),
+      Self::Store(ptr, val) => Self::Store(f(*ptr), f(*val)),
+      Self::GetElementAddr(ptr, n) => Self::GetElementAddr(f(*ptr), *n),
+
+      Self::Add(l, r) => Self::Add(f(*l), f(*r)),
+      Self::Subtract(l, r) => Self::Subtract(f(*l), f(*r)),
+      Self::Multiply(l, r) => Self::Multiply(f(*l), f(*r)),
+      Self::Divide(l, r) => Self::Divide(f(*l), f(*r)),
+      Self::LessThan(l, r) => Self::LessThan(f(*l), f(*r)),
+      Self::LessEqual(l, r) => Self::LessEqual(f(*l), f(*r)),
+
+      Self::Branch(cond, then, otherwise) => Self::Branch(f(*cond), *then, *otherwise),
+      Self::Return(val) => Self::Return(f(*val)),
+
+      Self::Call { func, params } => Self::Call {
+        func: *func,
+        params: params.iter().map(|x| f(*x)).collect(),
+      },
+
+      Self::MethodCall { method, params } => Self::MethodCall {
+        method: method.clone(),
+        params: params.iter().map(|x| f(*x)).collect(),
+      },
+
+      Self::ConstFloat(_)
+      | Self::ConstInteger(_)
+      | Self::Param(_)
+      | Self::Const(_)
+      | Self::OptionNone
+      | Self::Alloca(_)
+      | Self::Jump(_)
+      | Self::Exit
+      | Self::Nop => self.clone(),
+    }
+  }
+
+  // every instruction whose value is used by this one
+  pub fn operands(&self) -> Vec<InstrIdx> {
+    let mut out = vec![];
+
+    self.map_refs(|x| {
+      out.push(x);
+      x
+    });
+
+    out
+  }
+}
+
+impl<T> InstructionValue<T> {
+  // whether the instruction ends a basic block
+  pub fn is_terminator(&self) -> bool {
+    matches!(
+      self,
+      Self::Jump(_) | Self::Branch(..) | Self::Return(_) | Self::Exit
+    )
+  }
+
+  // applies @f to the blocks a Jump or Branch continues at
+  pub fn map_targets(self, mut f: impl FnMut(BlockIdx) -> BlockIdx) -> Self {
+    match self {
+      Self::Jump(target) => Self::Jump(f(target)),
+      Self::Branch(cond, then, otherwise) => Self::Branch(cond, f(then), f(otherwise)),
+      val => val,
+    }
+  }
+
+  // the blocks execution may continue at after this instruction,
+  // empty for every instruction but Jump and Branch
+  pub fn successors(&self) -> Vec<BlockIdx> {
+    match *self {
+      Self::Jump(target) => vec![target],
+      Self::Branch(_, then, otherwise) if then == otherwise => vec![then],
+      Self::Branch(_, then, otherwise) => vec![then, otherwise],
+      _ => vec![],
+    }
+  }
+}
+
+impl<T> InstructionValue<T> {
+  // converts the types held by the instruction with @f,
+  // the target type of a Cast and the type of the slot of an Alloca
+  pub fn map_types<U, E>(
+    self,
+    mut f: impl FnMut(&T) -> Result<U, E>,
+  ) -> Result<InstructionValue<U>, E> {
+    use InstructionValue as Value;
+
+    Ok(match self {
+      Self::Cast(val, ty) => Value::Cast(val, f(&ty)?),
+      Self::Alloca(ty) => Value::Alloca(f(&ty)?),
+
+      Self::Assign(val) => Value::Assign(val),
+      Self::Phi(vals) => Value::Phi(vals),
+      Self::ConstFloat(float) => Value::ConstFloat(float),
+      Self::ConstInteger(i) => Value::ConstInteger(i),
+      Self::Param(n) => Value::Param(n),
+      Self::Const(n) => Value::Const(n),
+      Self::Tuple(vals) => Value::Tuple(vals),
+      Self::TupleGet(val, n) => Value::TupleGet(val, n),
+
+      Self::OptionSome(val) => Value::OptionSome(val),
+      Self::OptionNone => Value::OptionNone,
+      Self::ResultOk(val) => Value::ResultOk(val),
+      Self::ResultErr(val) => Value::ResultErr(val),
+      Self::IsFailure(val) => Value::IsFailure(val),
+      Self::Unwrap(val) => Value::Unwrap(val),
+      Self::UnwrapOr(val, default) => Value::UnwrapOr(val, default),
+      Self::Failure(val) => Value::Failure(val),
+
+      Self::Load(ptr) => Value::Load(ptr),
+      Self::Store(ptr, val) => Value::Store(ptr, val),
+      Self::GetElementAddr(ptr, n) => Value::GetElementAddr(ptr, n),
+
+      Self::Add(l, r) => Value::Add(l, r),
+      Self::Subtract(l, r) => Value::Subtract(l, r),
+      Self::Multiply(l, r) => Value::Multiply(l, r),
+      Self::Divide(l, r) => Value::Divide(l, r),
+      Self::LessThan(l, r) => Value::LessThan(l, r),
+      Self::LessEqual(l, r) => Value::LessEqual(l, r),
+
+      Self::Jump(target) => Value::Jump(target),
+      Self::Branch(cond, then, otherwise) => Value::Branch(cond, then, otherwise),
+      Self::Return(val) => Value::Return(val),
+      Self::Exit => Value::Exit,
+
+      Self::Call { func, params } => Value::Call { func, params },
+      Self::MethodCall { method, params } => Value::MethodCall { method, params },
+
+      Self::Nop => Value::Nop,
+    })
+  }
 }
 
 #[derive(Clone, Debug)]
-pub struct Instruction {
-  pub val: InstructionValue,
+pub struct Instruction<T = Type> {
+  pub val: InstructionValue<T>,
 
   // index into the instanced token list
   // relative token
@@ -135,10 +394,10 @@ pub struct Instruction {
 
   // the associated type with the expression
   // e.g. Add(ConstInt(1), ConstInt(2)) has a type of Integer
-  pub ty: Type,
+  pub ty: T,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Type {
   // an undecided type, not allowed during codegen
   Undecided,
@@ -153,43 +412,418 @@ pub enum Type {
   // a 64-bit signed integer
   Integer,
 
+  // the result of a comparison
+  Boolean,
+
   // equivalent to a void value
   Moot,
+
+  // a fixed size list of values, e.g. (Integer, Floating)
+  Tuple(Vec<Type>),
+
+  // Option[T], either Some(T) or None
+  Option(Box<Type>),
+
+  // Result[T, E], either Ok(T) or Err(E)
+  Result(Box<Type>, Box<Type>),
+
+  // the n-th type parameter of the enclosing function,
+  // replaced with a concrete type during monomorphization
+  Generic(usize),
+
+  // a distinct type declared by newtype, refered to by its name,
+  // erased to the type it wraps once typechecking is done
+  Newtype(String),
+
+  // the address of a stack slot, or of an element within one
+  Pointer(Box<Type>),
 }
 
-// a block is a list of IrInstructions where control flow
-// enters at the top (idx. 0) and leaves from different
-// specified exit points (IrReturn instructions)
-#[derive(Debug)]
-pub struct IrBlock(pub Vec<Instruction>);
+impl Type {
+  // the types a compound type is built out of,
+  // e.g. the elements of a tuple
+  pub fn components(&self) -> Vec<&Type> {
+    match self {
+      Type::Tuple(types) => types.iter().collect(),
+      Type::Option(ty) => vec![ty],
+      Type::Result(ok, err) => vec![ok, err],
+      Type::Pointer(ty) => vec![ty],
+      _ => vec![],
+    }
+  }
 
-#[derive(Debug)]
-pub struct IrFunction {
+  // rebuilds a compound type with @f applied to each of its components
+  pub fn map(&self, mut f: impl FnMut(&Type) -> Type) -> Type {
+    match self {
+      Type::Tuple(types) => Type::Tuple(types.iter().map(f).collect()),
+      Type::Option(ty) => Type::Option(Box::new(f(ty))),
+      Type::Result(ok, err) => Type::Result(Box::new(f(ok)), Box::new(f(err))),
+      Type::Pointer(ty) => Type::Pointer(Box::new(f(ty))),
+      ty => ty.clone(),
+    }
+  }
+
+  // pairs up the components of two compound types of the same kind,
+  // returns None if the types are built differently
+  pub fn zip<'a>(&'a self, other: &'a Type) -> Option<Vec<(&'a Type, &'a Type)>> {
+    let (l, r) = (self.components(), other.components());
+
+    if l.is_empty()
+      || l.len() != r.len()
+      || std::mem::discriminant(self) != std::mem::discriminant(other)
+    {
+      return None;
+    }
+
+    Some(l.into_iter().zip(r).collect())
+  }
+
+  // whether the type has no undecided parts left
+  pub fn is_complete(&self) -> bool {
+    !matches!(self, Type::Undecided) && self.components().iter().all(|x| x.is_complete())
+  }
+}
+
+// the type carried by every instruction of an IR,
+// Type while sema is still deciding the types and thir::Type afterwards
+pub trait IrType: Clone + Display {
+  // whether the type has no undecided or invalid parts
+  fn is_settled(&self) -> bool;
+
+  fn is_pointer(&self) -> bool;
+
+  fn is_moot(&self) -> bool;
+}
+
+impl IrType for Type {
+  fn is_settled(&self) -> bool {
+    !matches!(self, Type::Undecided | Type::Invalid)
+      && self.components().into_iter().all(|x| x.is_settled())
+  }
+
+  fn is_pointer(&self) -> bool {
+    matches!(self, Type::Pointer(_))
+  }
+
+  fn is_moot(&self) -> bool {
+    *self == Type::Moot
+  }
+}
+
+// the instructions start..end of a function,
+// only entered through the first one and only left through the last one,
+// the terminator of the block
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BasicBlock {
+  pub start: InstrIdx,
+  pub end: InstrIdx,
+}
+
+impl BasicBlock {
+  pub fn instrs(&self) -> Range<InstrIdx> {
+    self.start..self.end
+  }
+
+  pub fn terminator(&self) -> InstrIdx {
+    self.end - 1
+  }
+}
+
+// the body of a function, its instructions split into basic blocks
+//
+// bb0:
+//   %0 = Param(0)
+//   %1 = Branch(%0, bb1, bb2)
+// bb1:
+//   %2 = Return(%0)
+// bb2:
+//   %3 = Exit
+//
+// the blocks are laid out one after the other, in the order of their
+// instructions, the first one is the entry of the function,
+// see cfg for the predecessors of each block and the dominators
+#[derive(Debug, Clone)]
+pub struct IrBody<T = Type> {
+  pub instrs: Vec<Instruction<T>>,
+  pub blocks: Vec<BasicBlock>,
+}
+
+impl<T> IrBody<T> {
+  // the body of a function without one, e.g. an extern function
+  pub fn empty() -> Self {
+    Self {
+      instrs: vec![],
+      blocks: vec![],
+    }
+  }
+
+  pub fn terminator(&self, block: BlockIdx) -> &Instruction<T> {
+    &self.instrs[self.blocks[block].terminator()]
+  }
+
+  pub fn successors(&self, block: BlockIdx) -> Vec<BlockIdx> {
+    self.terminator(block).val.successors()
+  }
+}
+
+#[derive(Debug, Clone)]
+pub struct IrFunction<T = Type> {
   // index into the token array
   pub name: Span,
 
-  pub instrs: IrBlock,
+  pub params: Vec<T>,
+  pub return_type: T,
+
+  // the trait bounds of each type parameter,
+  // a function with type parameters is a template that only
+  // gets emitted through its instantiations
+  pub type_params: Vec<Vec<TraitIdx>>,
+
+  // the type arguments of an instantiated template,
+  // e.g. [Integer] for max[Integer],
+  // kept as they were written as they only name the function
+  pub type_args: Vec<Type>,
+
+  // the implementation this function is a method of
+  pub method_of: Option<ImplIdx>,
+
+  pub linkage: Linkage,
+
+  // const functions can be evaluated at compile time
+  pub is_const: bool,
+
+  // set by @inline, a hint for the emitted code
+  pub inline: bool,
+
+  // the ## comments documenting the function, one span per line
+  pub docs: Vec<Span>,
+
+  // empty for extern functions
+  pub body: IrBody<T>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Linkage {
+  // only visible within the compiled unit
+  Internal,
+
+  // declared by yacwir, but defined outside of it,
+  // e.g. a function from libm
+  Extern,
+
+  // defined by yacwir, and visible to C code linking against the unit
+  Export,
+}
+
+#[derive(Debug, Clone)]
+pub struct IrMethodSig {
+  pub name: Span,
+  pub params: Vec<Type>,
+  pub return_type: Type,
+}
+
+// the methods of a trait refer to the type implementing them (Self)
+// as Generic(0)
+#[derive(Debug, Clone)]
+pub struct IrTrait {
+  pub name: Span,
+  pub methods: Vec<IrMethodSig>,
+  pub tok: TokIdx,
+}
+
+// implements <trait> for <ty>
+#[derive(Debug, Clone)]
+pub struct IrImpl {
+  pub trait_idx: TraitIdx,
+  pub ty: Type,
+
+  // one function per method of the trait,
+  // in the order they were defined in
+  pub methods: Vec<FuncIdx>,
+
+  pub tok: TokIdx,
+}
+
+// const <name>: <ty> = <value>
+#[derive(Debug, Clone)]
+pub struct IrConst<T = Type> {
+  pub name: Span,
+  pub ty: T,
+
+  // computes the value of the constant,
+  // evaluated at compile time
+  pub init: IrFunction<T>,
+
+  pub tok: TokIdx,
+}
+
+// newtype <name> = <ty>
+#[derive(Debug, Clone)]
+pub struct IrNewtype {
+  pub name: Span,
+  pub ty: Type,
 }
 
+// the traits, implementations and newtypes describe the source program,
+// and keep their types the way sema saw them
 #[derive(Debug)]
-pub struct IrUnit {
-  pub funcs: Vec<IrFunction>,
+pub struct IrUnit<T = Type> {
+  pub funcs: Vec<IrFunction<T>>,
+  pub traits: Vec<IrTrait>,
+  pub impls: Vec<IrImpl>,
+  pub newtypes: Vec<IrNewtype>,
+  pub consts: Vec<IrConst<T>>,
+
+  // names of the C headers declaring the extern functions
+  pub includes: Vec<Span>,
+}
+
+impl<T> IrBody<T> {
+  pub fn map_types<U, E>(self, mut f: impl FnMut(&T) -> Result<U, E>) -> Result<IrBody<U>, E> {
+    let instrs = self
+      .instrs
+      .into_iter()
+      .map(|x| {
+        Ok(Instruction {
+          ty: f(&x.ty)?,
+          val: x.val.map_types(&mut f)?,
+          tok: x.tok,
+        })
+      })
+      .collect::<Result<_, E>>()?;
+
+    Ok(IrBody {
+      instrs,
+      blocks: self.blocks,
+    })
+  }
+}
+
+impl<T> IrFunction<T> {
+  pub fn map_types<U, E>(self, mut f: impl FnMut(&T) -> Result<U, E>) -> Result<IrFunction<U>, E> {
+    Ok(IrFunction {
+      params: self.params.iter().map(&mut f).collect::<Result<_, E>>()?,
+      return_type: f(&self.return_type)?,
+      body: self.body.map_types(&mut f)?,
+      name: self.name,
+      type_params: self.type_params,
+      type_args: self.type_args,
+      method_of: self.method_of,
+      linkage: self.linkage,
+      is_const: self.is_const,
+      inline: self.inline,
+      docs: self.docs,
+    })
+  }
+}
+
+impl<T> IrUnit<T> {
+  // converts every type of the functions and constants with @f,
+  // e.g. to lower a unit to THIR,
+  // fails with the first error @f returns
+  pub fn map_types<U, E>(self, mut f: impl FnMut(&T) -> Result<U, E>) -> Result<IrUnit<U>, E> {
+    let funcs = self
+      .funcs
+      .into_iter()
+      .map(|x| x.map_types(&mut f))
+      .collect::<Result<_, E>>()?;
+
+    let consts = self
+      .consts
+      .into_iter()
+      .map(|x| {
+        Ok(IrConst {
+          ty: f(&x.ty)?,
+          init: x.init.map_types(&mut f)?,
+          name: x.name,
+          tok: x.tok,
+        })
+      })
+      .collect::<Result<_, E>>()?;
+
+    Ok(IrUnit {
+      funcs,
+      consts,
+      traits: self.traits,
+      impls: self.impls,
+      newtypes: self.newtypes,
+      includes: self.includes,
+    })
+  }
 }
 
-/// "flattens" a block, by un-fragmenting all of the SSA
+/// "flattens" a body, by un-fragmenting all of the SSA
 /// id values,
 /// this is a very expensive function
 /// e.g.
-///     %1 ConstInt(1)
-///     %3 ConstInt(2)
-///     %5 Add(1, 3)
+///     %0 ConstInt(1)
+///     %1 Nop
+///     %2 ConstInt(2)
+///     %3 Nop
+///     %4 Add(0, 2)
 /// converts to
-///     %0 ConstInt(0)
+///     %0 ConstInt(1)
 ///     %1 ConstInt(2)
 ///     %2 Add(0, 1)
+///
+/// a block is either kept along with its terminator, or removed as a whole,
+/// in which case the blocks after it move up
+pub fn flatten<T: Clone>(body: IrBody<T>) -> IrBody<T> {
+  let is_kept = |x: &Instruction<T>| !matches!(x.val, InstructionValue::Nop);
+
+  // the new id of every instruction,
+  // a removed instruction has none, so nothing that is kept may refer to it
+  const REMOVED: InstrIdx = InstrIdx::MAX;
+
+  let mut ids = Vec::with_capacity(body.instrs.len());
+  let mut next = 0;
+
+  for instr in body.instrs.iter() {
+    if is_kept(instr) {
+      ids.push(next);
+      next += 1;
+    } else {
+      ids.push(REMOVED);
+    }
+  }
+
+  let mut block_ids = Vec::with_capacity(body.blocks.len());
+  let mut next = 0;
+
+  for block in body.blocks.iter() {
+    block_ids.push(next);
 
-pub fn flatten(_unit: IrBlock) -> IrBlock {
-  unimplemented!()
+    if body.instrs[block.instrs()].iter().any(is_kept) {
+      debug_assert!(
+        is_kept(&body.instrs[block.terminator()]),
+        "the terminator of a block that is kept has been removed"
+      );
+
+      next += 1;
+    }
+  }
+
+  // every block ends with its terminator,
+  // so the builder starts the next one right after it
+  let mut builder = IrBuilder::new();
+
+  for instr in body.instrs.into_iter().filter(is_kept) {
+    let val = instr
+      .val
+      .map_refs(|idx| {
+        debug_assert!(
+          ids[idx] != REMOVED,
+          "%{} has been removed, but is still used",
+          idx
+        );
+        ids[idx]
+      })
+      .map_targets(|block| block_ids[block]);
+
+    builder.build(val, instr.ty, instr.tok);
+  }
+
+  builder.finish()
 }
 
 /*
@@ -207,9 +841,10 @@ optimize them, push the operands, then push the operator
 
 // beauty print functions
 
-impl Display for InstructionValue {
+impl<T: Display> Display for InstructionValue<T> {
   fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
     let str: String = match self {
+      Self::Nop => "Nop".to_string(),
       Self::ConstInteger(i) => format!("ConstInteger({})", i),
       Self::ConstFloat(f) => format!("ConstFloat({})", f),
 
@@ -218,16 +853,82 @@ impl Display for InstructionValue {
       Self::Multiply(left, right) => format!("Multiply(%{}, %{})", left, right),
       Self::Divide(left, right) => format!("Divide(%{}, %{})", left, right),
 
-      Self::Return(ret) => format!("Return(%{})", ret),
+      Self::Param(n) => format!("Param({})", n),
+      Self::Const(n) => format!("Const(${})", n),
+
+      Self::Tuple(vals) => format!(
+        "Tuple({})",
+        vals
+          .iter()
+          .map(|x| format!("%{}", x))
+          .collect::<Vec<_>>()
+          .join(", ")
+      ),
+      Self::TupleGet(tuple, n) => format!("TupleGet(%{}, {})", tuple, n),
+
+      Self::OptionSome(val) => format!("Some(%{})", val),
+      Self::OptionNone => "None".to_string(),
+      Self::ResultOk(val) => format!("Ok(%{})", val),
+      Self::ResultErr(val) => format!("Err(%{})", val),
+
+      Self::IsFailure(val) => format!("IsFailure(%{})", val),
+      Self::Unwrap(val) => format!("Unwrap(%{})", val),
+      Self::UnwrapOr(val, default) => format!("UnwrapOr(%{}, %{})", val, default),
+      Self::Failure(val) => format!("Failure(%{})", val),
+
+      Self::Alloca(ty) => format!("Alloca({})", ty),
+      Self::Load(ptr) => format!("Load(%{})", ptr),
+      Self::Store(ptr, val) => format!("Store(%{}, %{})", ptr, val),
+      Self::GetElementAddr(ptr, n) => format!("GetElementAddr(%{}, {})", ptr, n),
+
+      Self::Call { func, params } => format!(
+        "Call(@{}{})",
+        func,
+        params
+          .iter()
+          .map(|x| format!(", %{}", x))
+          .collect::<String>()
+      ),
+
+      Self::MethodCall { method, params } => format!(
+        "MethodCall({}{})",
+        method,
+        params
+          .iter()
+          .map(|x| format!(", %{}", x))
+          .collect::<String>()
+      ),
+
+      Self::Assign(val) => format!("Assign(%{})", val),
+
+      Self::Cast(val, ty) => format!("Cast(%{}, {})", val, ty),
+
+      Self::LessThan(left, right) => format!("LessThan(%{}, %{})", left, right),
+      Self::LessEqual(left, right) => format!("LessEqual(%{}, %{})", left, right),
+
+      Self::Phi(vals) => format!(
+        "Phi({})",
+        vals
+          .iter()
+          .map(|x| format!("%{}", x))
+          .collect::<Vec<_>>()
+          .join(", ")
+      ),
+
+      Self::Jump(target) => format!("Jump(bb{})", target),
+      Self::Branch(cond, then, otherwise) => {
+        format!("Branch(%{}, bb{}, bb{})", cond, then, otherwise)
+      }
 
-      _ => unimplemented!(),
+      Self::Return(ret) => format!("Return(%{})", ret),
+      Self::Exit => "Exit".to_string(),
     };
 
     f.write_str(str.as_str())
   }
 }
 
-impl Display for Instruction {
+impl<T: Display> Display for Instruction<T> {
   fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
     // assume that no instruction display will ever get past 40 cols
     // could probably do some length finageling
@@ -236,23 +937,23 @@ impl Display for Instruction {
   }
 }
 
-impl Display for IrBlock {
+impl<T: Display> Display for IrBody<T> {
   fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-    if self.0.len() == 0 {
+    if self.instrs.is_empty() {
       return f.write_str("");
     }
 
     let mut str = String::new();
 
-    for instr in self.0.iter() {
+    for instr in self.instrs.iter() {
       str.push_str(format!("{}\n", instr).as_str());
     }
 
     // calculate the maximum length of the idx as a str
     let idx_disp_max_len = {
-      let log = self.0.len().ilog10() as usize + 1;
+      let log = self.instrs.len().ilog10() as usize + 1;
 
-      if log % 2 == 0 {
+      if log.is_multiple_of(2) {
         log + 1
       } else {
         log
@@ -263,7 +964,12 @@ impl Display for IrBlock {
       .lines()
       .fold(
         (String::new(), 0),
-        |(mut acc, mut idx): (String, u32), next| {
+        |(mut acc, mut idx): (String, usize), next| {
+          // each block is preceded by its label
+          if let Some(block) = self.blocks.iter().position(|x| x.start == idx) {
+            acc.push_str(format!("bb{}:\n", block).as_str());
+          }
+
           acc.push_str(format!("=={:^width$}==\t", idx, width = idx_disp_max_len).as_str());
           acc.push_str(next);
           acc.push('\n');
@@ -277,20 +983,36 @@ impl Display for IrBlock {
   }
 }
 
-pub struct IrFuncDisplay<'a>(pub &'a CompilerContext, pub &'a IrFunction);
-impl<'a> From<(&'a CompilerContext, &'a IrFunction)> for IrFuncDisplay<'a> {
-  fn from(input: (&'a CompilerContext, &'a IrFunction)) -> Self {
+pub struct IrFuncDisplay<'a, T = Type>(pub &'a CompilerContext, pub &'a IrFunction<T>);
+impl<'a, T> From<(&'a CompilerContext, &'a IrFunction<T>)> for IrFuncDisplay<'a, T> {
+  fn from(input: (&'a CompilerContext, &'a IrFunction<T>)) -> Self {
     Self(input.0, input.1)
   }
 }
 
-impl<'a> Display for IrFuncDisplay<'a> {
+impl<'a, T: Display> Display for IrFuncDisplay<'a, T> {
   fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-    let instrs = format!("{}", self.1.instrs);
+    let instrs = format!("{}", self.1.body);
+
+    let type_args = if self.1.type_args.is_empty() {
+      String::new()
+    } else {
+      format!(
+        "[{}]",
+        self
+          .1
+          .type_args
+          .iter()
+          .map(|x| x.to_string())
+          .collect::<Vec<_>>()
+          .join(", ")
+      )
+    };
 
     let str = format!(
-      "Function <{}>:\n{}",
+      "Function <{}{}>:\n{}",
       self.0.get_str_from_span(self.1.name),
+      type_args,
       instrs,
     );
 
@@ -300,12 +1022,223 @@ impl<'a> Display for IrFuncDisplay<'a> {
 
 impl Display for Type {
   fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    if let Type::Generic(n) = self {
+      return write!(f, "T{}", n);
+    }
+
+    if let Type::Newtype(name) = self {
+      return f.write_str(name);
+    }
+
+    if let Type::Option(ty) = self {
+      return write!(f, "Option[{}]", ty);
+    }
+
+    if let Type::Result(ok, err) = self {
+      return write!(f, "Result[{}, {}]", ok, err);
+    }
+
+    if let Type::Pointer(ty) = self {
+      return write!(f, "Pointer[{}]", ty);
+    }
+
+    if let Type::Tuple(types) = self {
+      return write!(
+        f,
+        "({})",
+        types
+          .iter()
+          .map(|x| x.to_string())
+          .collect::<Vec<_>>()
+          .join(", ")
+      );
+    }
+
     f.write_str(match self {
       Type::Integer => "Integer",
       Type::Floating => "Floating",
+      Type::Boolean => "Boolean",
       Type::Moot => "Moot",
       Type::Invalid => "Invalid",
       Type::Undecided => "Undecided",
+      Type::Tuple(_)
+      | Type::Option(_)
+      | Type::Result(..)
+      | Type::Generic(_)
+      | Type::Newtype(_)
+      | Type::Pointer(_) => unreachable!(),
     })
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn instr(val: InstructionValue, tok: TokIdx) -> Instruction {
+    Instruction {
+      val,
+      tok,
+      ty: Type::Integer,
+    }
+  }
+
+  fn block(vals: Vec<InstructionValue>) -> IrBody {
+    let mut builder = IrBuilder::new();
+
+    for (tok, val) in vals.into_iter().enumerate() {
+      builder.build(val, Type::Integer, tok);
+    }
+
+    builder.finish()
+  }
+
+  fn values(body: &IrBody) -> Vec<String> {
+    body.instrs.iter().map(|x| x.val.to_string()).collect()
+  }
+
+  #[test]
+  fn flatten_removes_holes() {
+    use InstructionValue::*;
+
+    let flat = flatten(block(vec![
+      ConstInteger(1),
+      Nop,
+      ConstInteger(2),
+      Nop,
+      Add(0, 2),
+      Return(4),
+    ]));
+
+    assert_eq!(
+      values(&flat),
+      [
+        "ConstInteger(1)",
+        "ConstInteger(2)",
+        "Add(%0, %1)",
+        "Return(%2)"
+      ]
+    );
+  }
+
+  #[test]
+  #[cfg(debug_assertions)]
+  #[should_panic(expected = "%1 has been removed, but is still used")]
+  fn flatten_rejects_uses_of_removed_instructions() {
+    use InstructionValue::*;
+
+    flatten(block(vec![
+      ConstInteger(1),
+      Nop,
+      ConstInteger(2),
+      Add(0, 1),
+      Return(3),
+    ]));
+  }
+
+  #[test]
+  fn flatten_without_holes_is_unchanged() {
+    use InstructionValue::*;
+
+    let unit = block(vec![Param(0), ConstInteger(2), Multiply(0, 1), Return(2)]);
+    let expected = values(&unit);
+
+    assert_eq!(values(&flatten(unit)), expected);
+  }
+
+  #[test]
+  fn flatten_rewrites_phi_and_call_params() {
+    use InstructionValue::*;
+
+    let flat = flatten(block(vec![
+      Nop,
+      Param(0),
+      Nop,
+      Param(1),
+      Call {
+        func: 3,
+        params: vec![1, 3],
+      },
+      Nop,
+      Phi(vec![1, 4]),
+      MethodCall {
+        method: "show".to_string(),
+        params: vec![6],
+      },
+    ]));
+
+    assert_eq!(
+      values(&flat),
+      [
+        "Param(0)",
+        "Param(1)",
+        "Call(@3, %0, %1)",
+        "Phi(%0, %2)",
+        "MethodCall(show, %3)"
+      ]
+    );
+  }
+
+  #[test]
+  fn flatten_removes_empty_blocks() {
+    use InstructionValue::*;
+
+    // bb1 could not be reached and has been removed as a whole
+    let flat = flatten(IrBody {
+      instrs: [Param(0), Jump(2), Nop, Nop, Return(0)]
+        .into_iter()
+        .enumerate()
+        .map(|(tok, val)| instr(val, tok))
+        .collect(),
+      blocks: vec![
+        BasicBlock { start: 0, end: 2 },
+        BasicBlock { start: 2, end: 4 },
+        BasicBlock { start: 4, end: 5 },
+      ],
+    });
+
+    assert_eq!(values(&flat), ["Param(0)", "Jump(bb1)", "Return(%0)"]);
+    assert_eq!(
+      flat.blocks,
+      [
+        BasicBlock { start: 0, end: 2 },
+        BasicBlock { start: 2, end: 3 }
+      ]
+    );
+  }
+
+  #[test]
+  fn flatten_preserves_tokens_and_types() {
+    use InstructionValue::*;
+
+    let flat = flatten(IrBody {
+      instrs: vec![
+        instr(ConstFloat(1.5), 7),
+        instr(Nop, 8),
+        Instruction {
+          val: Cast(0, Type::Integer),
+          tok: 9,
+          ty: Type::Integer,
+        },
+      ],
+      blocks: vec![BasicBlock { start: 0, end: 3 }],
+    });
+
+    assert_eq!(flat.instrs.len(), 2);
+    assert_eq!(flat.instrs[0].tok, 7);
+    assert_eq!(flat.instrs[1].tok, 9);
+    assert_eq!(flat.instrs[1].ty, Type::Integer);
+    assert_eq!(flat.instrs[1].val.to_string(), "Cast(%0, Integer)");
+  }
+
+  #[test]
+  fn operands_exclude_jump_targets() {
+    use InstructionValue::*;
+
+    let operands = |val: InstructionValue| val.operands();
+
+    assert_eq!(operands(Branch(2, 5, 6)), [2]);
+    assert!(operands(Jump(5)).is_empty());
+    assert_eq!(operands(UnwrapOr(1, 3)), [1, 3]);
+  }
+}
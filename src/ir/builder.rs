@@ -0,0 +1,515 @@
+// builds the instructions of a function,
+// so that the frontend and the passes never have to work out indices themselves
+//
+// let mut b = IrBuilder::new();
+// let body = b.create_block();
+// let exit = b.create_block();
+//                                                   bb0:
+// let x = b.build_param(0, Type::Boolean, tok);       %0 = Param(0)
+// b.build_branch(x, body, exit, tok);                 %1 = Branch(%0, bb1, bb2)
+// b.set_insertion_point(body);                      bb1:
+// let one = b.build_const_integer(1, tok);            %2 = ConstInteger(1)
+// b.build_return(one, tok);                           %3 = Return(%2)
+// b.set_insertion_point(exit);                      bb2:
+// ...                                                 %4 ...
+//
+// the blocks are laid out in the order they are entered,
+// a block that is not ended by a terminator yet gets a Jump to the block
+// entered after it, and jumps to blocks that have not been entered yet
+// get their target once the function is finished
+//
+// building past a terminator starts a new block without a label,
+// so passes copying a body instruction by instruction keep its blocks
+//
+// every helper works out the type of its value where the instruction decides it,
+// or from its operands once they are typed, and leaves it undecided otherwise,
+// passes building THIR know every type already and place their instructions with build
+
+use std::ops::{Index, IndexMut};
+
+use crate::token::TokIdx;
+
+use super::{
+  BasicBlock, BlockIdx, ConstIdx, FuncIdx, InstrIdx, Instruction, InstructionValue, IrBody, Type,
+};
+
+// a block of the function being built,
+// refered to before the instruction it starts at is known
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Label(usize);
+
+#[derive(Debug)]
+pub struct IrBuilder<T = Type> {
+  instrs: Vec<Instruction<T>>,
+
+  // the blocks laid out so far, the last one is the one being built
+  blocks: Vec<BasicBlock>,
+
+  // the block each label was entered as
+  labels: Vec<Option<BlockIdx>>,
+
+  // the jumps whose targets are resolved once the function is finished
+  jumps: Vec<(InstrIdx, Vec<Label>)>,
+}
+
+impl<T> Default for IrBuilder<T> {
+  fn default() -> Self {
+    Self {
+      instrs: vec![],
+      blocks: vec![BasicBlock { start: 0, end: 0 }],
+      labels: vec![],
+      jumps: vec![],
+    }
+  }
+}
+
+impl<T> IrBuilder<T> {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn create_block(&mut self) -> Label {
+    self.labels.push(None);
+    Label(self.labels.len() - 1)
+  }
+
+  fn start_block(&mut self) {
+    self.blocks.push(BasicBlock {
+      start: self.instrs.len(),
+      end: self.instrs.len(),
+    });
+  }
+
+  // whether the block being built already ends in a terminator,
+  // anything built after it starts a new block that is never entered
+  pub fn is_terminated(&self) -> bool {
+    let block = self.blocks.last().unwrap();
+
+    block.start < block.end && self.instrs[block.terminator()].val.is_terminator()
+  }
+
+  // the index of the next instruction to be built
+  pub fn position(&self) -> InstrIdx {
+    self.instrs.len()
+  }
+
+  // the index of the block being built
+  pub fn current_block(&self) -> BlockIdx {
+    self.blocks.len() - 1
+  }
+
+  pub fn instrs(&self) -> &[Instruction<T>] {
+    &self.instrs
+  }
+
+  // resolves the targets of every jump
+  pub fn finish(mut self) -> IrBody<T> {
+    for (jump, labels) in std::mem::take(&mut self.jumps) {
+      let mut targets = labels
+        .iter()
+        .map(|x| self.labels[x.0].expect("a jump to a block that was never entered"));
+
+      self.instrs[jump].val = match self.instrs[jump].val {
+        InstructionValue::Jump(_) => InstructionValue::Jump(targets.next().unwrap()),
+        InstructionValue::Branch(cond, ..) => {
+          InstructionValue::Branch(cond, targets.next().unwrap(), targets.next().unwrap())
+        }
+        _ => unreachable!(),
+      };
+    }
+
+    // a function without instructions has no blocks either
+    if self.instrs.is_empty() {
+      return IrBody::empty();
+    }
+
+    IrBody {
+      instrs: self.instrs,
+      blocks: self.blocks,
+    }
+  }
+
+  // builds an instruction as it is
+  pub fn build(&mut self, val: InstructionValue<T>, ty: T, tok: TokIdx) -> InstrIdx {
+    if self.is_terminated() {
+      self.start_block();
+    }
+
+    self.instrs.push(Instruction { val, tok, ty });
+    self.blocks.last_mut().unwrap().end = self.instrs.len();
+    self.instrs.len() - 1
+  }
+}
+
+impl IrBuilder {
+  // the following instructions are placed within @block,
+  // which starts right after the instructions built so far
+  pub fn set_insertion_point(&mut self, block: Label) {
+    assert!(
+      self.labels[block.0].is_none(),
+      "a block can only be entered once"
+    );
+
+    // the block being left continues at the new one
+    if !self.is_terminated() {
+      let tok = self.instrs.last().map_or(0, |x| x.tok);
+      self.build_jump(block, tok);
+    }
+
+    self.labels[block.0] = Some(self.blocks.len());
+    self.start_block();
+  }
+
+  // the type of @idx, as long as it is known to be valid
+  fn settled(&self, idx: InstrIdx) -> Option<&Type> {
+    let ty = &self.instrs[idx].ty;
+    (ty.is_complete() && *ty != Type::Invalid).then_some(ty)
+  }
+
+  // a type built out of the type of @idx, undecided if that is not known yet
+  fn derive(&self, idx: InstrIdx, f: impl FnOnce(&Type) -> Option<Type>) -> Type {
+    self.settled(idx).and_then(f).unwrap_or(Type::Undecided)
+  }
+
+  pub fn build_const_integer(&mut self, val: i64, tok: TokIdx) -> InstrIdx {
+    self.build(InstructionValue::ConstInteger(val), Type::Integer, tok)
+  }
+
+  pub fn build_const_float(&mut self, val: f64, tok: TokIdx) -> InstrIdx {
+    self.build(InstructionValue::ConstFloat(val), Type::Floating, tok)
+  }
+
+  pub fn build_param(&mut self, n: usize, ty: Type, tok: TokIdx) -> InstrIdx {
+    self.build(InstructionValue::Param(n), ty, tok)
+  }
+
+  pub fn build_const(&mut self, idx: ConstIdx, ty: Type, tok: TokIdx) -> InstrIdx {
+    self.build(InstructionValue::Const(idx), ty, tok)
+  }
+
+  pub fn build_assign(&mut self, val: InstrIdx, tok: TokIdx) -> InstrIdx {
+    let ty = self.derive(val, |x| Some(x.clone()));
+    self.build(InstructionValue::Assign(val), ty, tok)
+  }
+
+  pub fn build_cast(&mut self, val: InstrIdx, ty: Type, tok: TokIdx) -> InstrIdx {
+    self.build(InstructionValue::Cast(val, ty.clone()), ty, tok)
+  }
+
+  // the type of an arithmetic operation is the type of its operands
+  fn build_arithmetic(&mut self, val: InstructionValue, l: InstrIdx, tok: TokIdx) -> InstrIdx {
+    let ty = self.derive(l, |x| Some(x.clone()));
+    self.build(val, ty, tok)
+  }
+
+  pub fn build_add(&mut self, l: InstrIdx, r: InstrIdx, tok: TokIdx) -> InstrIdx {
+    self.build_arithmetic(InstructionValue::Add(l, r), l, tok)
+  }
+
+  pub fn build_sub(&mut self, l: InstrIdx, r: InstrIdx, tok: TokIdx) -> InstrIdx {
+    self.build_arithmetic(InstructionValue::Subtract(l, r), l, tok)
+  }
+
+  pub fn build_mul(&mut self, l: InstrIdx, r: InstrIdx, tok: TokIdx) -> InstrIdx {
+    self.build_arithmetic(InstructionValue::Multiply(l, r), l, tok)
+  }
+
+  pub fn build_div(&mut self, l: InstrIdx, r: InstrIdx, tok: TokIdx) -> InstrIdx {
+    self.build_arithmetic(InstructionValue::Divide(l, r), l, tok)
+  }
+
+  pub fn build_less_than(&mut self, l: InstrIdx, r: InstrIdx, tok: TokIdx) -> InstrIdx {
+    self.build(InstructionValue::LessThan(l, r), Type::Boolean, tok)
+  }
+
+  pub fn build_less_equal(&mut self, l: InstrIdx, r: InstrIdx, tok: TokIdx) -> InstrIdx {
+    self.build(InstructionValue::LessEqual(l, r), Type::Boolean, tok)
+  }
+
+  pub fn build_tuple(&mut self, vals: Vec<InstrIdx>, tok: TokIdx) -> InstrIdx {
+    let ty = vals
+      .iter()
+      .map(|x| self.settled(*x).cloned())
+      .collect::<Option<Vec<_>>>()
+      .map_or(Type::Undecided, Type::Tuple);
+
+    self.build(InstructionValue::Tuple(vals), ty, tok)
+  }
+
+  pub fn build_tuple_get(&mut self, tuple: InstrIdx, n: usize, tok: TokIdx) -> InstrIdx {
+    let ty = self.derive(tuple, |x| match x {
+      Type::Tuple(types) => types.get(n).cloned(),
+      _ => None,
+    });
+
+    self.build(InstructionValue::TupleGet(tuple, n), ty, tok)
+  }
+
+  // the other side of an Option or Result is only known once it is used
+  pub fn build_some(&mut self, val: InstrIdx, tok: TokIdx) -> InstrIdx {
+    let ty = self.derive(val, |x| Some(Type::Option(Box::new(x.clone()))));
+    self.build(InstructionValue::OptionSome(val), ty, tok)
+  }
+
+  pub fn build_none(&mut self, tok: TokIdx) -> InstrIdx {
+    self.build(InstructionValue::OptionNone, Type::Undecided, tok)
+  }
+
+  pub fn build_ok(&mut self, val: InstrIdx, tok: TokIdx) -> InstrIdx {
+    self.build(InstructionValue::ResultOk(val), Type::Undecided, tok)
+  }
+
+  pub fn build_err(&mut self, val: InstrIdx, tok: TokIdx) -> InstrIdx {
+    self.build(InstructionValue::ResultErr(val), Type::Undecided, tok)
+  }
+
+  pub fn build_is_failure(&mut self, val: InstrIdx, tok: TokIdx) -> InstrIdx {
+    self.build(InstructionValue::IsFailure(val), Type::Boolean, tok)
+  }
+
+  pub fn build_unwrap(&mut self, val: InstrIdx, tok: TokIdx) -> InstrIdx {
+    let ty = self.derive(val, |x| match x {
+      Type::Option(ty) | Type::Result(ty, _) => Some(*ty.clone()),
+      _ => None,
+    });
+
+    self.build(InstructionValue::Unwrap(val), ty, tok)
+  }
+
+  // the failure is converted to the return type of the function
+  pub fn build_failure(&mut self, val: InstrIdx, tok: TokIdx) -> InstrIdx {
+    self.build(InstructionValue::Failure(val), Type::Undecided, tok)
+  }
+
+  pub fn build_alloca(&mut self, ty: Type, tok: TokIdx) -> InstrIdx {
+    let ptr = Type::Pointer(Box::new(ty.clone()));
+    self.build(InstructionValue::Alloca(ty), ptr, tok)
+  }
+
+  pub fn build_load(&mut self, ptr: InstrIdx, tok: TokIdx) -> InstrIdx {
+    let ty = self.derive(ptr, |x| match x {
+      Type::Pointer(ty) => Some(*ty.clone()),
+      _ => None,
+    });
+
+    self.build(InstructionValue::Load(ptr), ty, tok)
+  }
+
+  pub fn build_store(&mut self, ptr: InstrIdx, val: InstrIdx, tok: TokIdx) -> InstrIdx {
+    self.build(InstructionValue::Store(ptr, val), Type::Moot, tok)
+  }
+
+  pub fn build_element_addr(&mut self, ptr: InstrIdx, n: usize, tok: TokIdx) -> InstrIdx {
+    let ty = self.derive(ptr, |x| match x {
+      Type::Pointer(ty) => match **ty {
+        Type::Tuple(ref types) => types.get(n).map(|x| Type::Pointer(Box::new(x.clone()))),
+        _ => None,
+      },
+      _ => None,
+    });
+
+    self.build(InstructionValue::GetElementAddr(ptr, n), ty, tok)
+  }
+
+  // the callee is only known by its index, so its return type is passed along
+  pub fn build_call(
+    &mut self,
+    func: FuncIdx,
+    params: Vec<InstrIdx>,
+    ty: Type,
+    tok: TokIdx,
+  ) -> InstrIdx {
+    self.build(InstructionValue::Call { func, params }, ty, tok)
+  }
+
+  pub fn build_method_call(
+    &mut self,
+    method: &str,
+    params: Vec<InstrIdx>,
+    tok: TokIdx,
+  ) -> InstrIdx {
+    self.build(
+      InstructionValue::MethodCall {
+        method: method.to_string(),
+        params,
+      },
+      Type::Undecided,
+      tok,
+    )
+  }
+
+  // the operands coming from a back-edge can be added later on
+  pub fn build_phi(&mut self, vals: Vec<InstrIdx>, ty: Type, tok: TokIdx) -> InstrIdx {
+    self.build(InstructionValue::Phi(vals), ty, tok)
+  }
+
+  pub fn add_phi_operand(&mut self, phi: InstrIdx, val: InstrIdx) {
+    let InstructionValue::Phi(ref mut vals) = self.instrs[phi].val else {
+      panic!("%{} is not a phi", phi)
+    };
+
+    vals.push(val);
+  }
+
+  pub fn build_jump(&mut self, block: Label, tok: TokIdx) -> InstrIdx {
+    let jump = self.build(InstructionValue::Jump(0), Type::Moot, tok);
+    self.jumps.push((jump, vec![block]));
+    jump
+  }
+
+  // continues at @then if @cond holds, and at @otherwise if not
+  pub fn build_branch(
+    &mut self,
+    cond: InstrIdx,
+    then: Label,
+    otherwise: Label,
+    tok: TokIdx,
+  ) -> InstrIdx {
+    let branch = self.build(InstructionValue::Branch(cond, 0, 0), Type::Moot, tok);
+    self.jumps.push((branch, vec![then, otherwise]));
+    branch
+  }
+
+  pub fn build_return(&mut self, val: InstrIdx, tok: TokIdx) -> InstrIdx {
+    let ty = self.derive(val, |x| Some(x.clone()));
+    self.build(InstructionValue::Return(val), ty, tok)
+  }
+
+  pub fn build_exit(&mut self, tok: TokIdx) -> InstrIdx {
+    self.build(InstructionValue::Exit, Type::Moot, tok)
+  }
+}
+
+impl<T> Index<InstrIdx> for IrBuilder<T> {
+  type Output = Instruction<T>;
+
+  fn index(&self, idx: InstrIdx) -> &Instruction<T> {
+    &self.instrs[idx]
+  }
+}
+
+impl<T> IndexMut<InstrIdx> for IrBuilder<T> {
+  fn index_mut(&mut self, idx: InstrIdx) -> &mut Instruction<T> {
+    &mut self.instrs[idx]
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn values(body: &IrBody) -> Vec<String> {
+    body.instrs.iter().map(|x| x.val.to_string()).collect()
+  }
+
+  fn ranges(body: &IrBody) -> Vec<std::ops::Range<InstrIdx>> {
+    body.blocks.iter().map(|x| x.instrs()).collect()
+  }
+
+  #[test]
+  fn resolves_jumps_to_blocks() {
+    // i = 0, while i < n: i = i + 1, then return i
+    let mut b = IrBuilder::new();
+    let header = b.create_block();
+    let body = b.create_block();
+    let exit = b.create_block();
+
+    let n = b.build_param(0, Type::Integer, 0);
+    let zero = b.build_const_integer(0, 0);
+    let entry = b.build_assign(zero, 0);
+
+    b.set_insertion_point(header);
+    let i = b.build_phi(vec![entry], Type::Integer, 0);
+    let cond = b.build_less_than(i, n, 0);
+    b.build_branch(cond, body, exit, 0);
+
+    b.set_insertion_point(body);
+    let one = b.build_const_integer(1, 0);
+    let next = b.build_add(i, one, 0);
+    b.add_phi_operand(i, next);
+    b.build_jump(header, 0);
+
+    b.set_insertion_point(exit);
+    b.build_return(i, 0);
+
+    let body = b.finish();
+
+    // the entry is not ended by a terminator, so it jumps to the header
+    assert_eq!(
+      values(&body),
+      [
+        "Param(0)",
+        "ConstInteger(0)",
+        "Assign(%1)",
+        "Jump(bb1)",
+        "Phi(%2, %8)",
+        "LessThan(%4, %0)",
+        "Branch(%5, bb2, bb3)",
+        "ConstInteger(1)",
+        "Add(%4, %7)",
+        "Jump(bb1)",
+        "Return(%4)"
+      ]
+    );
+    assert_eq!(ranges(&body), [0..4, 4..7, 7..10, 10..11]);
+  }
+
+  #[test]
+  fn starts_a_block_after_a_terminator() {
+    let mut b = IrBuilder::new();
+
+    let x = b.build_param(0, Type::Integer, 0);
+    b.build_return(x, 0);
+    assert!(b.is_terminated());
+
+    // code following a return is never executed, but still gets a block
+    let one = b.build_const_integer(1, 0);
+    b.build_return(one, 0);
+
+    let body = b.finish();
+    assert_eq!(ranges(&body), [0..2, 2..4]);
+    assert_eq!(body.successors(0), []);
+  }
+
+  #[test]
+  fn derives_types_from_operands() {
+    let mut b = IrBuilder::new();
+
+    let x = b.build_param(0, Type::Integer, 0);
+    let y = b.build_const_float(0.5, 0);
+    let pair = b.build_tuple(vec![x, y], 0);
+    let first = b.build_tuple_get(pair, 0, 0);
+    let sum = b.build_add(first, x, 0);
+    let some = b.build_some(sum, 0);
+
+    let slot = b.build_alloca(Type::Tuple(vec![Type::Integer, Type::Floating]), 0);
+    let second = b.build_element_addr(slot, 1, 0);
+    let loaded = b.build_load(second, 0);
+
+    // nothing is known about a value that sema has not seen yet
+    let undecided = b.build_const(0, Type::Undecided, 0);
+    let unknown = b.build_add(undecided, x, 0);
+
+    let body = b.finish();
+    let ty = |idx: InstrIdx| body.instrs[idx].ty.to_string();
+
+    assert_eq!(ty(pair), "(Integer, Floating)");
+    assert_eq!(ty(first), "Integer");
+    assert_eq!(ty(sum), "Integer");
+    assert_eq!(ty(some), "Option[Integer]");
+    assert_eq!(ty(slot), "Pointer[(Integer, Floating)]");
+    assert_eq!(ty(second), "Pointer[Floating]");
+    assert_eq!(ty(loaded), "Floating");
+    assert_eq!(ty(unknown), "Undecided");
+  }
+
+  #[test]
+  #[should_panic(expected = "a jump to a block that was never entered")]
+  fn rejects_jumps_to_blocks_that_are_never_entered() {
+    let mut b = IrBuilder::new();
+    let block = b.create_block();
+
+    b.build_jump(block, 0);
+    b.finish();
+  }
+}
@@ -0,0 +1,295 @@
+// the control flow graph of a function,
+// built on top of the basic blocks of its body
+//
+// bb0:                              bb0 -> [bb2, bb1]
+//   %0 = ConstInteger(1)
+//   %1 = LessThan(%0, %0)
+//   %2 = Branch(%1, bb2, bb1)
+// bb1:                              bb1 <- [bb0] -> [bb2]
+//   %3 = Jump(bb2)
+// bb2:                              bb2 <- [bb0, bb1]
+//   %4 = Return(%0)
+//
+// the blocks and their terminators are kept by the body,
+// the graph adds the predecessors of each block and the analyses built on them,
+// so it has to be rebuilt after a pass changes the control flow
+
+use std::fmt::Display;
+
+use super::{BasicBlock, BlockIdx, InstrIdx, IrBody, IrFunction};
+
+#[derive(Debug, Clone)]
+pub struct Cfg {
+  // the blocks of the body, the first one is the entry of the function
+  blocks: Vec<BasicBlock>,
+
+  // the block every instruction belongs to
+  block_of: Vec<BlockIdx>,
+
+  successors: Vec<Vec<BlockIdx>>,
+  predecessors: Vec<Vec<BlockIdx>>,
+}
+
+impl Cfg {
+  pub fn new<T>(body: &IrBody<T>) -> Self {
+    let mut block_of = vec![0; body.instrs.len()];
+    for (idx, block) in body.blocks.iter().enumerate() {
+      block_of[block.instrs()].fill(idx);
+    }
+
+    let successors: Vec<Vec<BlockIdx>> =
+      (0..body.blocks.len()).map(|x| body.successors(x)).collect();
+
+    let mut predecessors = vec![vec![]; body.blocks.len()];
+    for (idx, succs) in successors.iter().enumerate() {
+      for succ in succs.iter().copied() {
+        predecessors[succ].push(idx);
+      }
+    }
+
+    Self {
+      blocks: body.blocks.clone(),
+      block_of,
+      successors,
+      predecessors,
+    }
+  }
+
+  pub fn entry(&self) -> BlockIdx {
+    0
+  }
+
+  pub fn blocks(&self) -> &[BasicBlock] {
+    &self.blocks
+  }
+
+  pub fn block(&self, idx: BlockIdx) -> &BasicBlock {
+    &self.blocks[idx]
+  }
+
+  // the block containing the instruction @instr
+  pub fn block_of(&self, instr: InstrIdx) -> BlockIdx {
+    self.block_of[instr]
+  }
+
+  pub fn successors(&self, idx: BlockIdx) -> &[BlockIdx] {
+    &self.successors[idx]
+  }
+
+  pub fn predecessors(&self, idx: BlockIdx) -> &[BlockIdx] {
+    &self.predecessors[idx]
+  }
+
+  // the blocks reachable from the entry, each one placed
+  // before its successors, apart from the targets of back-edges
+  pub fn reverse_postorder(&self) -> Vec<BlockIdx> {
+    // the body of an extern function has no entry
+    if self.blocks.is_empty() {
+      return vec![];
+    }
+
+    let mut visited = vec![false; self.blocks.len()];
+    let mut order = vec![];
+
+    // blocks along with the number of their successors visited so far
+    let mut stack = vec![(self.entry(), 0)];
+    visited[self.entry()] = true;
+
+    while let Some((block, next)) = stack.pop() {
+      let succs = &self.successors[block];
+
+      match succs.get(next) {
+        Some(succ) => {
+          stack.push((block, next + 1));
+
+          if !visited[*succ] {
+            visited[*succ] = true;
+            stack.push((*succ, 0));
+          }
+        }
+
+        None => order.push(block),
+      }
+    }
+
+    order.reverse();
+    order
+  }
+
+  // the immediate dominator of every block, i.e. the closest block
+  // that every path from the entry has to pass through,
+  // None for the entry and the blocks that can not be reached
+  //
+  // Cooper, Harvey and Kennedy, "A Simple, Fast Dominance Algorithm"
+  pub fn immediate_dominators(&self) -> Vec<Option<BlockIdx>> {
+    let order = self.reverse_postorder();
+
+    // the position of each reachable block in the reverse postorder
+    let mut position = vec![usize::MAX; self.blocks.len()];
+    for (n, block) in order.iter().enumerate() {
+      position[*block] = n;
+    }
+
+    let mut idoms: Vec<Option<BlockIdx>> = vec![None; self.blocks.len()];
+    if self.blocks.is_empty() {
+      return idoms;
+    }
+
+    idoms[self.entry()] = Some(self.entry());
+
+    let mut changed = true;
+    while changed {
+      changed = false;
+
+      for block in order.iter().skip(1).copied() {
+        let mut new_idom: Option<BlockIdx> = None;
+
+        for pred in self.predecessors[block].iter().copied() {
+          if idoms[pred].is_none() {
+            continue;
+          }
+
+          new_idom = Some(match new_idom {
+            None => pred,
+
+            // walk up from both blocks until they meet
+            Some(mut other) => {
+              let mut pred = pred;
+
+              while pred != other {
+                while position[pred] > position[other] {
+                  pred = idoms[pred].unwrap();
+                }
+
+                while position[other] > position[pred] {
+                  other = idoms[other].unwrap();
+                }
+              }
+
+              pred
+            }
+          });
+        }
+
+        if idoms[block] != new_idom {
+          idoms[block] = new_idom;
+          changed = true;
+        }
+      }
+    }
+
+    idoms[self.entry()] = None;
+    idoms
+  }
+
+  // whether each block can be reached from the entry
+  pub fn reachable(&self) -> Vec<bool> {
+    let mut reachable = vec![false; self.blocks.len()];
+
+    for block in self.reverse_postorder() {
+      reachable[block] = true;
+    }
+
+    reachable
+  }
+}
+
+impl<T> IrFunction<T> {
+  pub fn cfg(&self) -> Cfg {
+    Cfg::new(&self.body)
+  }
+}
+
+// bb<n> [%<start>..%<end>] <- <predecessors> -> <successors>
+impl Display for Cfg {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    let blocks = |blocks: &[BlockIdx]| {
+      blocks
+        .iter()
+        .map(|x| format!("bb{}", x))
+        .collect::<Vec<_>>()
+        .join(", ")
+    };
+
+    for (idx, block) in self.blocks.iter().enumerate() {
+      writeln!(
+        f,
+        "bb{} [%{}..%{}] <- [{}] -> [{}]",
+        idx,
+        block.start,
+        block.end,
+        blocks(&self.predecessors[idx]),
+        blocks(&self.successors[idx])
+      )?;
+    }
+
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{context::CompilerContextBuilder, ir::text};
+
+  // the cfg of the only function in @src
+  fn cfg(src: &str) -> Cfg {
+    let ctx = CompilerContextBuilder::new().filedata(src.into()).take();
+    text::parse(&ctx).unwrap().funcs[0].cfg()
+  }
+
+  #[test]
+  fn links_the_blocks_of_a_loop() {
+    let cfg = cfg(
+      "
+fn f(Integer) -> Integer:
+  bb0:
+    %0 = Param(0) : Integer
+    %1 = Jump(bb1) : Moot
+  bb1:
+    %2 = Phi(%0, %5) : Integer
+    %3 = LessThan(%2, %0) : Boolean
+    %4 = Branch(%3, bb2, bb3) : Moot
+  bb2:
+    %5 = Add(%2, %2) : Integer
+    %6 = Jump(bb1) : Moot
+  bb3:
+    %7 = Return(%2) : Integer
+",
+    );
+
+    assert_eq!(cfg.blocks().len(), 4);
+    assert_eq!(cfg.successors(0), [1]);
+    assert_eq!(cfg.successors(1), [2, 3]);
+    assert_eq!(cfg.successors(2), [1]);
+    assert!(cfg.successors(3).is_empty());
+
+    assert!(cfg.predecessors(0).is_empty());
+    assert_eq!(cfg.predecessors(1), [0, 2]);
+    assert_eq!(cfg.predecessors(2), [1]);
+    assert_eq!(cfg.predecessors(3), [1]);
+
+    assert_eq!(cfg.block_of(3), 1);
+    assert_eq!(cfg.reverse_postorder(), [0, 1, 3, 2]);
+  }
+
+  #[test]
+  fn a_branch_to_the_same_block_is_one_edge() {
+    let cfg = cfg(
+      "
+fn f(Boolean) -> Moot:
+  bb0:
+    %0 = Param(0) : Boolean
+    %1 = Branch(%0, bb1, bb1) : Moot
+  bb1:
+    %2 = Exit : Moot
+  bb2:
+    %3 = Jump(bb1) : Moot
+",
+    );
+
+    assert_eq!(cfg.successors(0), [1]);
+    assert_eq!(cfg.predecessors(1), [0, 2]);
+    assert_eq!(cfg.reachable(), [true, true, false]);
+  }
+}
@@ -0,0 +1,286 @@
+// dominance over the basic blocks of a function
+//
+// a block A dominates a block B if every path from the entry to B
+// passes through A, the closest such block is the immediate dominator of B,
+// and linking every block to it forms the dominator tree
+//
+// the dominance frontier of A are the blocks where the dominance of A ends,
+// i.e. the blocks that have a predecessor dominated by A
+// without being strictly dominated by A themselves,
+// which is where a value defined in A meets values from other paths
+//
+//        bb0
+//       /   \
+//     bb1   bb2         idom(bb1) = idom(bb2) = idom(bb3) = bb0
+//       \   /           DF(bb1) = DF(bb2) = [bb3]
+//        bb3
+//
+// blocks that can not be reached from the entry are not part of the tree
+
+use super::{cfg::Cfg, BlockIdx};
+
+#[derive(Debug, Clone)]
+pub struct DomTree {
+  idoms: Vec<Option<BlockIdx>>,
+  children: Vec<Vec<BlockIdx>>,
+  reachable: Vec<bool>,
+  entry: BlockIdx,
+}
+
+impl DomTree {
+  pub fn new(cfg: &Cfg) -> Self {
+    let idoms = cfg.immediate_dominators();
+    let reachable = cfg.reachable();
+
+    let mut children = vec![vec![]; idoms.len()];
+    for (block, idom) in idoms.iter().enumerate() {
+      if let Some(idom) = idom {
+        children[*idom].push(block);
+      }
+    }
+
+    Self {
+      idoms,
+      children,
+      reachable,
+      entry: cfg.entry(),
+    }
+  }
+
+  // None for the entry and the blocks that can not be reached
+  pub fn idom(&self, block: BlockIdx) -> Option<BlockIdx> {
+    self.idoms[block]
+  }
+
+  // the blocks immediately dominated by @block
+  pub fn children(&self, block: BlockIdx) -> &[BlockIdx] {
+    &self.children[block]
+  }
+
+  pub fn is_reachable(&self, block: BlockIdx) -> bool {
+    self.reachable[block]
+  }
+
+  // whether @a dominates @b, every block dominates itself
+  pub fn dominates(&self, a: BlockIdx, mut b: BlockIdx) -> bool {
+    loop {
+      if a == b {
+        return true;
+      }
+
+      match self.idoms[b] {
+        Some(idom) => b = idom,
+        None => return false,
+      }
+    }
+  }
+
+  pub fn strictly_dominates(&self, a: BlockIdx, b: BlockIdx) -> bool {
+    a != b && self.dominates(a, b)
+  }
+
+  // the reachable blocks, each one placed before the blocks it dominates
+  pub fn preorder(&self) -> Vec<BlockIdx> {
+    let mut order = vec![];
+    let mut stack = vec![self.entry];
+
+    while let Some(block) = stack.pop() {
+      order.push(block);
+      stack.extend(self.children[block].iter().rev());
+    }
+
+    order
+  }
+
+  // the dominance frontier of every block
+  //
+  // Cooper, Harvey and Kennedy, "A Simple, Fast Dominance Algorithm"
+  pub fn frontiers(&self, cfg: &Cfg) -> Vec<Vec<BlockIdx>> {
+    let mut frontiers: Vec<Vec<BlockIdx>> = vec![vec![]; self.idoms.len()];
+
+    for block in 0..self.idoms.len() {
+      let Some(idom) = self.idoms[block] else {
+        continue;
+      };
+
+      let preds = cfg.predecessors(block);
+      if preds.len() < 2 {
+        continue;
+      }
+
+      // walk up from every predecessor until the dominator of the join
+      for pred in preds.iter().copied().filter(|x| self.reachable[*x]) {
+        let mut runner = pred;
+
+        while runner != idom {
+          if !frontiers[runner].contains(&block) {
+            frontiers[runner].push(block);
+          }
+
+          match self.idoms[runner] {
+            Some(next) => runner = next,
+            None => break,
+          }
+        }
+      }
+    }
+
+    // a loop header is part of its own frontier through its back-edge,
+    // which the walk above can not see for the entry
+    for pred in cfg.predecessors(self.entry) {
+      if self.reachable[*pred] {
+        let mut runner = *pred;
+
+        loop {
+          if !frontiers[runner].contains(&self.entry) {
+            frontiers[runner].push(self.entry);
+          }
+
+          match self.idoms[runner] {
+            Some(next) => runner = next,
+            None => break,
+          }
+        }
+      }
+    }
+
+    frontiers
+  }
+
+  // the iterated dominance frontier of @blocks, sorted,
+  // i.e. every block where the values defined within @blocks meet
+  pub fn iterated_frontier(
+    frontiers: &[Vec<BlockIdx>],
+    blocks: impl IntoIterator<Item = BlockIdx>,
+  ) -> Vec<BlockIdx> {
+    let mut in_frontier = vec![false; frontiers.len()];
+    let mut worklist: Vec<BlockIdx> = blocks.into_iter().collect();
+
+    while let Some(block) = worklist.pop() {
+      for frontier in frontiers[block].iter().copied() {
+        if !in_frontier[frontier] {
+          in_frontier[frontier] = true;
+          worklist.push(frontier);
+        }
+      }
+    }
+
+    (0..frontiers.len()).filter(|x| in_frontier[*x]).collect()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{context::CompilerContextBuilder, ir::text};
+
+  // the cfg of the only function in @src
+  fn cfg(src: &str) -> Cfg {
+    let ctx = CompilerContextBuilder::new().filedata(src.into()).take();
+    text::parse(&ctx).unwrap().funcs[0].cfg()
+  }
+
+  #[test]
+  fn diamond() {
+    let cfg = cfg(
+      "
+fn f(Boolean) -> Boolean:
+  bb0:
+    %0 = Param(0) : Boolean
+    %1 = Branch(%0, bb2, bb1) : Moot
+  bb1:
+    %2 = ConstInteger(1) : Integer
+    %3 = Jump(bb3) : Moot
+  bb2:
+    %4 = ConstInteger(2) : Integer
+    %5 = Jump(bb3) : Moot
+  bb3:
+    %6 = Return(%0) : Boolean
+",
+    );
+    let dom = DomTree::new(&cfg);
+
+    assert_eq!(cfg.blocks().len(), 4);
+    assert_eq!(
+      (0..4).map(|x| dom.idom(x)).collect::<Vec<_>>(),
+      [None, Some(0), Some(0), Some(0)]
+    );
+    assert_eq!(dom.frontiers(&cfg), [vec![], vec![3], vec![3], vec![]]);
+
+    assert!(dom.dominates(0, 3));
+    assert!(!dom.dominates(1, 3));
+    assert!(!dom.strictly_dominates(3, 3));
+    assert_eq!(dom.preorder(), [0, 1, 2, 3]);
+  }
+
+  #[test]
+  fn loop_header() {
+    let cfg = cfg(
+      "
+fn f(Boolean) -> Integer:
+  bb0:
+    %0 = ConstInteger(0) : Integer
+    %1 = Jump(bb1) : Moot
+  bb1:
+    %2 = Param(0) : Boolean
+    %3 = Branch(%2, bb3, bb2) : Moot
+  bb2:
+    %4 = ConstInteger(1) : Integer
+    %5 = Jump(bb1) : Moot
+  bb3:
+    %6 = Return(%0) : Integer
+",
+    );
+    let dom = DomTree::new(&cfg);
+
+    assert_eq!(
+      (0..4).map(|x| dom.idom(x)).collect::<Vec<_>>(),
+      [None, Some(0), Some(1), Some(1)]
+    );
+    assert_eq!(dom.children(1), [2, 3]);
+
+    // the body and the header meet the values from before the loop
+    let frontiers = dom.frontiers(&cfg);
+    assert_eq!(frontiers, [vec![], vec![1], vec![1], vec![]]);
+    assert_eq!(DomTree::iterated_frontier(&frontiers, [2]), [1]);
+  }
+
+  #[test]
+  fn loop_at_the_entry() {
+    let cfg = cfg(
+      "
+fn f(Boolean) -> Boolean:
+  bb0:
+    %0 = Param(0) : Boolean
+    %1 = Branch(%0, bb2, bb1) : Moot
+  bb1:
+    %2 = Jump(bb0) : Moot
+  bb2:
+    %3 = Return(%0) : Boolean
+",
+    );
+    let dom = DomTree::new(&cfg);
+
+    assert_eq!(dom.frontiers(&cfg), [vec![0], vec![0], vec![]]);
+  }
+
+  #[test]
+  fn unreachable_blocks_are_left_out() {
+    let cfg = cfg(
+      "
+fn f() -> Integer:
+  bb0:
+    %0 = ConstInteger(1) : Integer
+    %1 = Return(%0) : Integer
+  bb1:
+    %2 = Return(%0) : Integer
+",
+    );
+    let dom = DomTree::new(&cfg);
+
+    assert!(!dom.is_reachable(1));
+    assert_eq!(dom.idom(1), None);
+    assert!(!dom.dominates(0, 1));
+    assert_eq!(dom.preorder(), [0]);
+  }
+}
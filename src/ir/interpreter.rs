@@ -0,0 +1,676 @@
+// executes the IR of a unit directly,
+// used to evaluate constants at compile time and to run programs
+// without going through a C compiler
+//
+// every value is computed on the fly, the instructions of a block are executed
+// in order until its terminator continues at another block or returns,
+// a Phi is written to by each of its operands when they are executed
+//
+// stack slots live in a memory shared by every call,
+// a call frees the slots it reserved once it returns
+//
+// the execution is bounded by a step limit and a recursion limit,
+// so that a diverging program can not hang the compiler,
+// and every runtime error points at the token of the failing instruction
+
+use std::{collections::HashMap, fmt::Display};
+
+use crate::{context::CompilerContext, token::TokIdx};
+
+use super::{
+  thir::{self, Type},
+  ConstIdx, InstrIdx, InstructionValue, Linkage,
+};
+
+// default maximum number of instructions executed per evaluation
+const STEP_LIMIT: usize = 1_000_000;
+
+// default maximum depth of nested calls
+const RECURSION_LIMIT: usize = 256;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+  Integer(i64),
+  Floating(f64),
+  Boolean(bool),
+  Moot,
+  Tuple(Vec<Value>),
+  Option(Option<Box<Value>>),
+  Result(Result<Box<Value>, Box<Value>>),
+
+  // a slot in the memory, and the path of tuple elements within it
+  Pointer(usize, Vec<usize>),
+}
+
+impl Value {
+  // the contents of a fresh stack slot,
+  // Moot marks the parts that have not been written yet
+  fn uninitialized(ty: &Type) -> Value {
+    match ty {
+      Type::Tuple(types) => Value::Tuple(types.iter().map(Self::uninitialized).collect()),
+      _ => Value::Moot,
+    }
+  }
+}
+
+enum ConstState {
+  Pending,
+  InProgress,
+  Done(Value),
+  Failed,
+}
+
+// an error, and the token of the instruction it occured at
+pub type RuntimeError = (TokIdx, String);
+
+pub struct Interpreter<'a> {
+  ctx: &'a CompilerContext,
+  unit: &'a thir::Unit,
+  consts: Vec<ConstState>,
+
+  // the stack slots of every running call
+  memory: Vec<Value>,
+
+  steps: usize,
+  depth: usize,
+
+  step_limit: usize,
+  recursion_limit: usize,
+}
+
+impl<'a> Interpreter<'a> {
+  pub fn new(ctx: &'a CompilerContext, unit: &'a thir::Unit) -> Self {
+    Self {
+      ctx,
+      unit,
+      consts: unit.consts.iter().map(|_| ConstState::Pending).collect(),
+      memory: vec![],
+      steps: 0,
+      depth: 0,
+      step_limit: STEP_LIMIT,
+      recursion_limit: RECURSION_LIMIT,
+    }
+  }
+
+  pub fn step_limit(mut self, step_limit: usize) -> Self {
+    self.step_limit = step_limit;
+    self
+  }
+
+  pub fn recursion_limit(mut self, recursion_limit: usize) -> Self {
+    self.recursion_limit = recursion_limit;
+    self
+  }
+
+  // runs the function called main, and returns its value
+  pub fn run_main(&mut self) -> Result<Value, RuntimeError> {
+    let unit = self.unit;
+
+    let Some(main) = unit
+      .funcs
+      .iter()
+      .find(|x| x.type_params.is_empty() && self.ctx.get_str_from_span(x.name) == "main")
+    else {
+      return Err((0, "There is no main function to run".to_string()));
+    };
+
+    self.steps = 0;
+    self.call(main, vec![])
+  }
+
+  // evaluates the initializer of a constant,
+  // the value is remembered for every later use
+  pub fn eval_const(&mut self, idx: ConstIdx, tok: TokIdx) -> Result<Value, RuntimeError> {
+    match self.consts[idx] {
+      ConstState::Done(ref value) => return Ok(value.clone()),
+
+      // already reported
+      ConstState::Failed => return Err((tok, String::new())),
+
+      ConstState::InProgress => {
+        return Err((
+          tok,
+          "A constant can not depend on its own value".to_string(),
+        ))
+      }
+
+      ConstState::Pending => (),
+    }
+
+    self.consts[idx] = ConstState::InProgress;
+
+    // a constant evaluated in the middle of a program
+    // gets a step budget of its own
+    let steps = std::mem::take(&mut self.steps);
+
+    let unit = self.unit;
+    let value = self.call(&unit.consts[idx].init, vec![]);
+
+    self.steps = steps;
+
+    self.consts[idx] = match value {
+      Ok(ref value) => ConstState::Done(value.clone()),
+      Err(_) => ConstState::Failed,
+    };
+
+    value
+  }
+
+  pub fn call(
+    &mut self,
+    function: &thir::Function,
+    params: Vec<Value>,
+  ) -> Result<Value, RuntimeError> {
+    let frame = self.memory.len();
+    let value = self.execute(function, params);
+    self.memory.truncate(frame);
+
+    value
+  }
+
+  fn execute(
+    &mut self,
+    function: &thir::Function,
+    params: Vec<Value>,
+  ) -> Result<Value, RuntimeError> {
+    let instrs = &function.body.instrs;
+
+    // every phi is written to by each of its operands
+    let mut phis: HashMap<InstrIdx, Vec<InstrIdx>> = HashMap::new();
+    for (idx, instr) in instrs.iter().enumerate() {
+      if let InstructionValue::Phi(ref vals) = instr.val {
+        for val in vals {
+          phis.entry(*val).or_default().push(idx);
+        }
+      }
+    }
+
+    let mut values: Vec<Value> = vec![Value::Moot; instrs.len()];
+    let mut pc = 0;
+
+    // the slot reserved by each Alloca of this call
+    let mut slots: HashMap<InstrIdx, usize> = HashMap::new();
+
+    while pc < instrs.len() {
+      let instr = &instrs[pc];
+      let tok = instr.tok;
+
+      self.steps += 1;
+      if self.steps > self.step_limit {
+        return Err((
+          tok,
+          format!("Evaluation exceeded the limit of {} steps", self.step_limit),
+        ));
+      }
+
+      let value = match instr.val {
+        InstructionValue::ConstInteger(i) => Value::Integer(i),
+        InstructionValue::ConstFloat(f) => Value::Floating(f),
+        InstructionValue::Param(n) => params[n].clone(),
+        InstructionValue::Const(n) => self.eval_const(n, tok)?,
+
+        InstructionValue::Assign(val) => values[val].clone(),
+
+        // the value has been written by the operand that was executed last
+        InstructionValue::Phi(_) => values[pc].clone(),
+
+        InstructionValue::Cast(val, ref ty) => match (&values[val], ty) {
+          (Value::Integer(i), Type::Floating) => Value::Floating(*i as f64),
+          (Value::Floating(f), Type::Integer) => Value::Integer(*f as i64),
+          (value, _) => value.clone(),
+        },
+
+        InstructionValue::Add(l, r)
+        | InstructionValue::Subtract(l, r)
+        | InstructionValue::Multiply(l, r)
+        | InstructionValue::Divide(l, r) => {
+          Self::binary(&instr.val, &values[l], &values[r]).map_err(|info| (tok, info))?
+        }
+
+        InstructionValue::LessThan(l, r) | InstructionValue::LessEqual(l, r) => {
+          let ordering = match (&values[l], &values[r]) {
+            (Value::Integer(l), Value::Integer(r)) => l.partial_cmp(r),
+            (Value::Floating(l), Value::Floating(r)) => l.partial_cmp(r),
+            _ => None,
+          };
+
+          Value::Boolean(match instr.val {
+            InstructionValue::LessThan(..) => ordering.is_some_and(|x| x.is_lt()),
+            _ => ordering.is_some_and(|x| x.is_le()),
+          })
+        }
+
+        InstructionValue::Tuple(ref vals) => {
+          Value::Tuple(vals.iter().map(|x| values[*x].clone()).collect())
+        }
+
+        InstructionValue::TupleGet(tuple, n) => match values[tuple] {
+          Value::Tuple(ref vals) => vals[n].clone(),
+          _ => return Err((tok, "Indexed into a value that is not a tuple".to_string())),
+        },
+
+        InstructionValue::OptionSome(val) => Value::Option(Some(Box::new(values[val].clone()))),
+        InstructionValue::OptionNone => Value::Option(None),
+        InstructionValue::ResultOk(val) => Value::Result(Ok(Box::new(values[val].clone()))),
+        InstructionValue::ResultErr(val) => Value::Result(Err(Box::new(values[val].clone()))),
+
+        InstructionValue::IsFailure(val) => Value::Boolean(matches!(
+          values[val],
+          Value::Option(None) | Value::Result(Err(_))
+        )),
+
+        InstructionValue::Unwrap(val) => match values[val] {
+          Value::Option(Some(ref value)) | Value::Result(Ok(ref value)) => *value.clone(),
+          _ => return Err((tok, "Unwrapped a None or an Err".to_string())),
+        },
+
+        InstructionValue::UnwrapOr(val, default) => match values[val] {
+          Value::Option(Some(ref value)) | Value::Result(Ok(ref value)) => *value.clone(),
+          _ => values[default].clone(),
+        },
+
+        InstructionValue::Failure(val) => match values[val] {
+          Value::Result(Err(ref err)) => Value::Result(Err(err.clone())),
+          _ => Value::Option(None),
+        },
+
+        InstructionValue::Alloca(ref ty) => {
+          let slot = *slots.entry(pc).or_insert_with(|| {
+            self.memory.push(Value::uninitialized(ty));
+            self.memory.len() - 1
+          });
+
+          Value::Pointer(slot, vec![])
+        }
+
+        InstructionValue::Load(ptr) => match self.place(&values[ptr]).map_err(|x| (tok, x))? {
+          Value::Moot if instr.ty != Type::Moot => {
+            return Err((tok, "Read a stack slot before writing to it".to_string()))
+          }
+
+          value => value.clone(),
+        },
+
+        InstructionValue::Store(ptr, val) => {
+          *self.place(&values[ptr]).map_err(|x| (tok, x))? = values[val].clone();
+          Value::Moot
+        }
+
+        InstructionValue::GetElementAddr(ptr, n) => match values[ptr] {
+          Value::Pointer(slot, ref path) => Value::Pointer(slot, [path.as_slice(), &[n]].concat()),
+          _ => return Err((tok, "Expected a pointer".to_string())),
+        },
+
+        InstructionValue::Call {
+          func,
+          params: ref args,
+        } => {
+          let unit = self.unit;
+          let callee = &unit.funcs[func];
+
+          let args: Vec<Value> = args.iter().map(|x| values[*x].clone()).collect();
+
+          if callee.linkage == Linkage::Extern {
+            let name = self.ctx.get_str_from_span(callee.name);
+
+            match Self::call_extern(name, &args) {
+              Some(value) => value,
+              None => return Err((tok, format!("Unable to call the extern function {}", name))),
+            }
+          } else {
+            if self.depth >= self.recursion_limit {
+              return Err((
+                tok,
+                format!(
+                  "Evaluation exceeded the limit of {} nested calls",
+                  self.recursion_limit
+                ),
+              ));
+            }
+
+            self.depth += 1;
+            let value = self.call(callee, args);
+            self.depth -= 1;
+
+            value?
+          }
+        }
+
+        InstructionValue::MethodCall { ref method, .. } => {
+          return Err((
+            tok,
+            format!("The method call {} was never resolved", method),
+          ))
+        }
+
+        InstructionValue::Jump(target) => {
+          pc = function.body.blocks[target].start;
+          continue;
+        }
+
+        InstructionValue::Branch(cond, then, otherwise) => {
+          let target = match values[cond] == Value::Boolean(true) {
+            true => then,
+            false => otherwise,
+          };
+
+          pc = function.body.blocks[target].start;
+          continue;
+        }
+
+        InstructionValue::Return(val) => return Ok(values[val].clone()),
+        InstructionValue::Exit => return Ok(Value::Moot),
+
+        InstructionValue::Nop => Value::Moot,
+      };
+
+      if let Some(targets) = phis.get(&pc) {
+        for phi in targets {
+          values[*phi] = value.clone();
+        }
+      }
+
+      values[pc] = value;
+      pc += 1;
+    }
+
+    Ok(Value::Moot)
+  }
+
+  // the part of the memory @ptr points to
+  fn place(&mut self, ptr: &Value) -> Result<&mut Value, String> {
+    let Value::Pointer(slot, path) = ptr else {
+      return Err("Expected a pointer".to_string());
+    };
+
+    let Some(mut place) = self.memory.get_mut(*slot) else {
+      return Err("Accessed a stack slot that is no longer alive".to_string());
+    };
+
+    for n in path {
+      place = match place {
+        Value::Tuple(vals) if *n < vals.len() => &mut vals[*n],
+        _ => return Err("Addressed an element of a value that is not a tuple".to_string()),
+      };
+    }
+
+    Ok(place)
+  }
+
+  // the functions of the C library that can be called without linking against it,
+  // None for any other extern function
+  fn call_extern(name: &str, args: &[Value]) -> Option<Value> {
+    Some(match (name, args) {
+      ("llabs" | "labs" | "abs", [Value::Integer(i)]) => Value::Integer(i.wrapping_abs()),
+
+      (_, [Value::Floating(x)]) => Value::Floating(match name {
+        "sqrt" => x.sqrt(),
+        "cbrt" => x.cbrt(),
+        "fabs" => x.abs(),
+        "floor" => x.floor(),
+        "ceil" => x.ceil(),
+        "round" => x.round(),
+        "trunc" => x.trunc(),
+        "exp" => x.exp(),
+        "log" => x.ln(),
+        "log2" => x.log2(),
+        "log10" => x.log10(),
+        "sin" => x.sin(),
+        "cos" => x.cos(),
+        "tan" => x.tan(),
+        "atan" => x.atan(),
+        _ => return None,
+      }),
+
+      (_, [Value::Floating(x), Value::Floating(y)]) => Value::Floating(match name {
+        "pow" => x.powf(*y),
+        "fmod" => x % y,
+        "atan2" => x.atan2(*y),
+        "fmin" => x.min(*y),
+        "fmax" => x.max(*y),
+        _ => return None,
+      }),
+
+      _ => return None,
+    })
+  }
+
+  fn binary(op: &InstructionValue<Type>, l: &Value, r: &Value) -> Result<Value, String> {
+    match (l, r) {
+      (Value::Integer(l), Value::Integer(r)) => {
+        let value = match op {
+          InstructionValue::Add(..) => l.checked_add(*r),
+          InstructionValue::Subtract(..) => l.checked_sub(*r),
+          InstructionValue::Multiply(..) => l.checked_mul(*r),
+          _ if *r == 0 => return Err("Division by zero".to_string()),
+          _ => l.checked_div(*r),
+        };
+
+        value
+          .map(Value::Integer)
+          .ok_or("Integer overflow".to_string())
+      }
+
+      (Value::Floating(l), Value::Floating(r)) => Ok(Value::Floating(match op {
+        InstructionValue::Add(..) => l + r,
+        InstructionValue::Subtract(..) => l - r,
+        InstructionValue::Multiply(..) => l * r,
+        _ => l / r,
+      })),
+
+      (l, r) => Err(format!("Unable to apply {} to {} and {}", op, l, r)),
+    }
+  }
+}
+
+impl Display for Value {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Value::Integer(i) => write!(f, "{}", i),
+      Value::Floating(x) => write!(f, "{:?}", x),
+      Value::Boolean(b) => write!(f, "{}", b),
+      Value::Moot => f.write_str("Moot"),
+
+      Value::Tuple(vals) => write!(
+        f,
+        "({})",
+        vals
+          .iter()
+          .map(|x| x.to_string())
+          .collect::<Vec<_>>()
+          .join(", ")
+      ),
+
+      Value::Option(Some(val)) => write!(f, "Some({})", val),
+      Value::Option(None) => f.write_str("None"),
+      Value::Result(Ok(val)) => write!(f, "Ok({})", val),
+      Value::Result(Err(err)) => write!(f, "Err({})", err),
+
+      Value::Pointer(slot, path) => write!(
+        f,
+        "&{}{}",
+        slot,
+        path.iter().map(|x| format!(".{}", x)).collect::<String>()
+      ),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    context::CompilerContextBuilder, ir::text, parser::Ast, sema::SemaContext, testing,
+    token::TokenType,
+  };
+
+  fn compile(ctx: &CompilerContext) -> (Ast, thir::Unit) {
+    let ast = testing::parse(ctx);
+    let unit = testing::check(ctx, &ast).unwrap();
+
+    (ast, unit)
+  }
+
+  #[test]
+  fn runs_recursive_functions() {
+    let ctx = CompilerContextBuilder::new()
+      .filedata(
+        "extern defn sqrt(x: Floating) -> Floating
+
+defn fib(n: Integer) -> Integer:
+  if n < 2:
+    return n
+  return fib(n - 1) + fib(n - 2)
+
+defn main() -> Integer:
+  let (a, b) = (fib(10), sqrt(16.0))
+  return a + Integer(b)"
+          .into(),
+      )
+      .take();
+
+    let (_, unit) = compile(&ctx);
+
+    assert_eq!(
+      Interpreter::new(&ctx, &unit).run_main(),
+      Ok(Value::Integer(59))
+    );
+  }
+
+  #[test]
+  fn points_runtime_errors_at_their_token() {
+    let ctx = CompilerContextBuilder::new()
+      .filedata(
+        "defn main() -> Integer:
+  let zero = 1 - 1
+  return 4 / zero"
+          .into(),
+      )
+      .take();
+
+    let (ast, unit) = compile(&ctx);
+    let (tok, info) = Interpreter::new(&ctx, &unit).run_main().unwrap_err();
+
+    assert_eq!(info, "Division by zero");
+    assert_eq!(ast.toks[tok].ty, TokenType::Solidus);
+  }
+
+  #[test]
+  fn stops_at_the_limits() {
+    let ctx = CompilerContextBuilder::new()
+      .filedata(
+        "defn forever(n: Integer) -> Integer:
+  return forever(n + 1)
+
+defn main() -> Integer:
+  while 0 < 1:
+    let x = 1
+  return 0"
+          .into(),
+      )
+      .take();
+
+    let (_, unit) = compile(&ctx);
+
+    let (_, info) = Interpreter::new(&ctx, &unit)
+      .step_limit(100)
+      .run_main()
+      .unwrap_err();
+    assert_eq!(info, "Evaluation exceeded the limit of 100 steps");
+
+    let forever = &unit.funcs[0];
+    let (_, info) = Interpreter::new(&ctx, &unit)
+      .recursion_limit(8)
+      .call(forever, vec![Value::Integer(0)])
+      .unwrap_err();
+    assert_eq!(info, "Evaluation exceeded the limit of 8 nested calls");
+  }
+
+  #[test]
+  fn runs_textual_ir() {
+    let ctx = CompilerContextBuilder::new()
+      .filedata(
+        "const TEN: Integer:
+  bb0:
+    %0 = ConstInteger(10) : Integer
+    %1 = Return(%0) : Integer
+
+fn main() -> (Option[Integer], Result[Integer, Floating], Integer):
+  bb0:
+    %0 = Const($0) : Integer
+    %1 = ConstFloat(0.5) : Floating
+    %2 = Some(%0) : Option[Integer]
+    %3 = IsFailure(%2) : Boolean
+    %4 = Branch(%3, bb2, bb1) : Moot
+  bb1:
+    %5 = Unwrap(%2) : Integer
+    %6 = Jump(bb3) : Moot
+  bb2:
+    %7 = Assign(%0) : Integer
+    %8 = Jump(bb3) : Moot
+  bb3:
+    %9 = Phi(%5, %7) : Integer
+    %10 = Err(%1) : Result[Integer, Floating]
+    %11 = None : Option[Integer]
+    %12 = UnwrapOr(%11, %9) : Integer
+    %13 = Tuple(%2, %10, %12) : (Option[Integer], Result[Integer, Floating], Integer)
+    %14 = Return(%13) : (Option[Integer], Result[Integer, Floating], Integer)
+"
+        .into(),
+      )
+      .take();
+
+    let unit = SemaContext::lower(&ctx, text::parse(&ctx).unwrap()).unwrap();
+    let value = Interpreter::new(&ctx, &unit).run_main().unwrap();
+
+    assert_eq!(value.to_string(), "(Some(10), Err(0.5), 10)");
+  }
+
+  #[test]
+  fn reads_and_writes_stack_slots() {
+    // the second element is only written when the parameter is positive
+    let ctx = CompilerContextBuilder::new()
+      .filedata(
+        "fn f(Integer) -> (Integer, Integer):
+  bb0:
+    %0 = Param(0) : Integer
+    %1 = Alloca((Integer, Integer)) : Pointer[(Integer, Integer)]
+    %2 = GetElementAddr(%1, 0) : Pointer[Integer]
+    %3 = GetElementAddr(%1, 1) : Pointer[Integer]
+    %4 = Store(%2, %0) : Moot
+    %5 = ConstInteger(0) : Integer
+    %6 = LessThan(%5, %0) : Boolean
+    %7 = Branch(%6, bb2, bb1) : Moot
+  bb1:
+    %8 = Jump(bb3) : Moot
+  bb2:
+    %9 = Store(%3, %0) : Moot
+    %10 = Jump(bb3) : Moot
+  bb3:
+    %11 = Load(%3) : Integer
+    %12 = Load(%1) : (Integer, Integer)
+    %13 = Return(%12) : (Integer, Integer)
+"
+        .into(),
+      )
+      .take();
+
+    let unit = SemaContext::lower(&ctx, text::parse(&ctx).unwrap()).unwrap();
+    let mut interpreter = Interpreter::new(&ctx, &unit);
+
+    assert_eq!(
+      interpreter
+        .call(&unit.funcs[0], vec![Value::Integer(3)])
+        .unwrap()
+        .to_string(),
+      "(3, 3)"
+    );
+
+    assert_eq!(
+      interpreter
+        .call(&unit.funcs[0], vec![Value::Integer(0)])
+        .map_err(|x| x.1),
+      Err("Read a stack slot before writing to it".to_string())
+    );
+  }
+}
@@ -0,0 +1,450 @@
+// turns variables that are assigned at several points of a function
+// into SSA values, by placing a Phi wherever different assignments meet
+//
+// let x = 1               bb0:
+// if c:                     %0 = ConstInteger(1)
+//   x = 2                   %1 = Branch(%c, bb2, bb1)
+//                         bb1:
+//                           %2 = Assign(%0)         <- operand of %7
+//                           %3 = Jump(bb3)
+//                         bb2:
+//                           %4 = ConstInteger(2)
+//                           %5 = Assign(%4)         <- operand of %7
+//                           %6 = Jump(bb3)
+// return x                bb3:
+//                           %7 = Phi(%2, %5)
+//                           %8 = Return(%7)
+//
+// the phis are placed at the iterated dominance frontier of the blocks
+// assigning a variable, as long as the variable is read afterwards,
+// and every predecessor gets an Assign at its end as the operand of the phi,
+// so that the phi is written right before control enters its block
+//
+// each assignment is removed, and each read turns into an Assign
+// of the value reaching it, the blocks that can not be reached are dropped
+
+use std::collections::HashMap;
+
+use super::{
+  builder::IrBuilder, cfg::Cfg, dom::DomTree, BlockIdx, InstrIdx, InstructionValue, IrBody, Type,
+};
+
+#[derive(Debug, Clone)]
+pub struct Variable<T = Type> {
+  pub ty: T,
+
+  // the instructions assigning the variable, along with the value they assign
+  pub defs: Vec<(InstrIdx, InstrIdx)>,
+
+  // the instructions reading the variable
+  pub uses: Vec<InstrIdx>,
+}
+
+// the value of a variable at some point, in terms of the input
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Reaching {
+  Value(InstrIdx),
+
+  // the phi of the variable at the start of a block
+  Phi(BlockIdx),
+}
+
+// where an instruction of the output comes from
+enum Emit {
+  Instr(InstrIdx),
+
+  // a use of a variable, replaced with the value reaching it
+  Use(InstrIdx, usize, Reaching),
+
+  // the phi of a variable at the start of a block
+  Phi(usize, BlockIdx),
+
+  // the operand of the phi of a variable, at the end of a predecessor
+  Operand(usize, Reaching, InstrIdx),
+}
+
+// returns an error if a variable can be read before it is assigned
+pub fn construct<T: Clone>(body: &IrBody<T>, vars: &[Variable<T>]) -> Result<IrBody<T>, String> {
+  let instrs = &body.instrs;
+
+  let cfg = Cfg::new(body);
+  let dom = DomTree::new(&cfg);
+  let frontiers = dom.frontiers(&cfg);
+
+  let mut is_def = vec![false; instrs.len()];
+
+  // the phis placed at the start of each block, by variable
+  let mut phis: Vec<Vec<usize>> = vec![vec![]; cfg.blocks().len()];
+
+  // the reaching value of each use, and of each variable at the end of each block
+  let mut reaching_use: HashMap<InstrIdx, (usize, Reaching)> = HashMap::new();
+  let mut reaching_out: Vec<Vec<Option<Reaching>>> = vec![];
+
+  for (var_idx, var) in vars.iter().enumerate() {
+    let mut assigned = vec![None; instrs.len()];
+    for (def, value) in var.defs.iter() {
+      is_def[*def] = true;
+      assigned[*def] = Some(*value);
+    }
+
+    let def_blocks: Vec<BlockIdx> = var.defs.iter().map(|x| cfg.block_of(x.0)).collect();
+
+    // the blocks the variable is read in before it gets assigned,
+    // and every block leading to those without assigning it
+    let mut live_in = vec![false; cfg.blocks().len()];
+    let mut worklist = vec![];
+
+    for u in var.uses.iter().copied() {
+      let b = cfg.block_of(u);
+      let start = cfg.block(b).start;
+
+      if !var.defs.iter().any(|(d, _)| (start..u).contains(d)) && !live_in[b] {
+        live_in[b] = true;
+        worklist.push(b);
+      }
+    }
+
+    while let Some(b) = worklist.pop() {
+      for pred in cfg.predecessors(b).iter().copied() {
+        if !live_in[pred] && !def_blocks.contains(&pred) {
+          live_in[pred] = true;
+          worklist.push(pred);
+        }
+      }
+    }
+
+    for b in DomTree::iterated_frontier(&frontiers, def_blocks.iter().copied()) {
+      if live_in[b] && dom.is_reachable(b) {
+        phis[b].push(var_idx);
+      }
+    }
+
+    // the reaching value flows down the dominator tree
+    let mut out: Vec<Option<Reaching>> = vec![None; cfg.blocks().len()];
+
+    for b in dom.preorder() {
+      let mut current = match phis[b].contains(&var_idx) {
+        true => Some(Reaching::Phi(b)),
+        false => dom.idom(b).and_then(|x| out[x]),
+      };
+
+      for idx in cfg.block(b).instrs() {
+        if var.uses.contains(&idx) {
+          match current {
+            Some(value) => reaching_use.insert(idx, (var_idx, value)),
+            None => return Err(format!("%{} reads a variable before it is assigned", idx)),
+          };
+        }
+
+        if let Some(value) = assigned[idx] {
+          current = Some(Reaching::Value(value));
+        }
+      }
+
+      out[b] = current;
+    }
+
+    reaching_out.push(out);
+  }
+
+  // lays out the output, block by block
+  let mut layout = vec![];
+  let mut new_idx: Vec<InstrIdx> = vec![0; instrs.len()];
+  let mut new_block: Vec<BlockIdx> = vec![0; cfg.blocks().len()];
+  let mut phi_idx: HashMap<(usize, BlockIdx), InstrIdx> = HashMap::new();
+
+  // the operands of every phi, once placed
+  let mut operands: HashMap<(usize, BlockIdx), Vec<InstrIdx>> = HashMap::new();
+
+  // the reachable blocks keep their order
+  let reachable = (0..cfg.blocks().len()).filter(|x| dom.is_reachable(*x));
+  for (n, b) in reachable.enumerate() {
+    new_block[b] = n;
+  }
+
+  for (b, bb) in cfg.blocks().iter().enumerate() {
+    if !dom.is_reachable(b) {
+      continue;
+    }
+
+    for var in phis[b].iter().copied() {
+      phi_idx.insert((var, b), layout.len());
+      layout.push(Emit::Phi(var, b));
+    }
+
+    // the operands go right before the terminator leaving the block
+    let leaves = bb.terminator();
+
+    for idx in bb.instrs() {
+      if idx == leaves {
+        break;
+      }
+
+      new_idx[idx] = layout.len();
+
+      // nothing refers to an assignment
+      if is_def[idx] {
+        continue;
+      }
+
+      layout.push(match reaching_use.get(&idx) {
+        Some((var, value)) => Emit::Use(idx, *var, *value),
+        None => Emit::Instr(idx),
+      });
+    }
+
+    for succ in cfg.successors(b).iter().copied() {
+      for var in phis[succ].iter().copied() {
+        let Some(value) = reaching_out[var][b] else {
+          return Err(format!(
+            "a variable is read in bb{} before it is assigned",
+            succ
+          ));
+        };
+
+        operands.entry((var, succ)).or_default().push(layout.len());
+        layout.push(Emit::Operand(var, value, leaves));
+      }
+    }
+
+    new_idx[leaves] = layout.len();
+    layout.push(Emit::Instr(leaves));
+  }
+
+  // every block ends in its terminator,
+  // so the builder starts the blocks right where they were
+  let mut builder = IrBuilder::new();
+
+  for emit in layout.iter() {
+    let resolve = |var: usize, value: Reaching| match value {
+      Reaching::Value(idx) => new_idx[idx],
+      Reaching::Phi(b) => phi_idx[&(var, b)],
+    };
+
+    match *emit {
+      Emit::Instr(idx) => {
+        let instr = &instrs[idx];
+
+        let val = match instr.val {
+          // operands left in unreachable blocks never get to write the phi
+          InstructionValue::Phi(ref vals) => InstructionValue::Phi(
+            vals
+              .iter()
+              .filter(|x| dom.is_reachable(cfg.block_of(**x)))
+              .map(|x| new_idx[*x])
+              .collect(),
+          ),
+
+          ref val => val.map_refs(|x| new_idx[x]).map_targets(|x| new_block[x]),
+        };
+
+        builder.build(val, instr.ty.clone(), instr.tok)
+      }
+
+      Emit::Use(idx, var, value) => builder.build(
+        InstructionValue::Assign(resolve(var, value)),
+        instrs[idx].ty.clone(),
+        instrs[idx].tok,
+      ),
+
+      Emit::Phi(var, b) => builder.build(
+        InstructionValue::Phi(operands.get(&(var, b)).cloned().unwrap_or_default()),
+        vars[var].ty.clone(),
+        instrs[cfg.block(b).start].tok,
+      ),
+
+      Emit::Operand(var, value, tok_idx) => builder.build(
+        InstructionValue::Assign(resolve(var, value)),
+        vars[var].ty.clone(),
+        instrs[tok_idx].tok,
+      ),
+    };
+  }
+
+  Ok(builder.finish())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    context::CompilerContextBuilder,
+    ir::{
+      interpreter::{Interpreter, Value},
+      text::{self, FunctionText},
+      verify::{verify, Stage},
+    },
+    sema::SemaContext,
+  };
+
+  // places @vars in the only function of @src, then checks the result
+  // and runs it for each of @runs
+  fn check(src: &str, vars: &[Variable], expected: &str, runs: &[(Value, Value)]) {
+    let ctx = CompilerContextBuilder::new().filedata(src.into()).take();
+    let mut unit = text::parse(&ctx).unwrap();
+
+    unit.funcs[0].body = construct(&unit.funcs[0].body, vars).unwrap();
+
+    assert_eq!(FunctionText(&ctx, &unit.funcs[0]).to_string(), expected);
+    assert_eq!(verify(&ctx, &unit, Stage::Typed), Ok(()));
+
+    let unit = SemaContext::lower(&ctx, unit).unwrap();
+
+    for (param, result) in runs {
+      let value = Interpreter::new(&ctx, &unit).call(&unit.funcs[0], vec![param.clone()]);
+      assert_eq!(value, Ok(result.clone()));
+    }
+  }
+
+  #[test]
+  fn places_a_phi_where_branches_meet() {
+    // x = 1, if the parameter holds x = 2, then return x
+    let src = "fn f(Boolean) -> Integer:
+  bb0:
+    %0 = Param(0) : Boolean
+    %1 = ConstInteger(1) : Integer
+    %2 = Nop : Moot
+    %3 = Branch(%0, bb2, bb1) : Moot
+  bb1:
+    %4 = Jump(bb3) : Moot
+  bb2:
+    %5 = ConstInteger(2) : Integer
+    %6 = Nop : Moot
+    %7 = Jump(bb3) : Moot
+  bb3:
+    %8 = ConstInteger(0) : Integer
+    %9 = Return(%8) : Integer
+";
+
+    let x = Variable {
+      ty: Type::Integer,
+      defs: vec![(2, 1), (6, 5)],
+      uses: vec![8],
+    };
+
+    let expected = "fn f(Boolean) -> Integer:
+  bb0:
+    %0 = Param(0) : Boolean
+    %1 = ConstInteger(1) : Integer
+    %2 = Branch(%0, bb2, bb1) : Moot
+  bb1:
+    %3 = Assign(%1) : Integer
+    %4 = Jump(bb3) : Moot
+  bb2:
+    %5 = ConstInteger(2) : Integer
+    %6 = Assign(%5) : Integer
+    %7 = Jump(bb3) : Moot
+  bb3:
+    %8 = Phi(%3, %6) : Integer
+    %9 = Assign(%8) : Integer
+    %10 = Return(%9) : Integer
+";
+
+    check(
+      src,
+      &[x],
+      expected,
+      &[
+        (Value::Boolean(true), Value::Integer(2)),
+        (Value::Boolean(false), Value::Integer(1)),
+      ],
+    );
+  }
+
+  #[test]
+  fn places_a_phi_at_the_loop_header() {
+    // i = 0, while i < n: i = i + 1, then return i
+    let src = "fn count(Integer) -> Integer:
+  bb0:
+    %0 = Param(0) : Integer
+    %1 = ConstInteger(0) : Integer
+    %2 = Nop : Moot
+    %3 = Jump(bb1) : Moot
+  bb1:
+    %4 = ConstInteger(0) : Integer
+    %5 = LessThan(%4, %0) : Boolean
+    %6 = Branch(%5, bb2, bb3) : Moot
+  bb2:
+    %7 = ConstInteger(0) : Integer
+    %8 = ConstInteger(1) : Integer
+    %9 = Add(%7, %8) : Integer
+    %10 = Nop : Moot
+    %11 = Jump(bb1) : Moot
+  bb3:
+    %12 = ConstInteger(0) : Integer
+    %13 = Return(%12) : Integer
+";
+
+    let i = Variable {
+      ty: Type::Integer,
+      defs: vec![(2, 1), (10, 9)],
+      uses: vec![4, 7, 12],
+    };
+
+    let expected = "fn count(Integer) -> Integer:
+  bb0:
+    %0 = Param(0) : Integer
+    %1 = ConstInteger(0) : Integer
+    %2 = Assign(%1) : Integer
+    %3 = Jump(bb1) : Moot
+  bb1:
+    %4 = Phi(%2, %11) : Integer
+    %5 = Assign(%4) : Integer
+    %6 = LessThan(%5, %0) : Boolean
+    %7 = Branch(%6, bb2, bb3) : Moot
+  bb2:
+    %8 = Assign(%4) : Integer
+    %9 = ConstInteger(1) : Integer
+    %10 = Add(%8, %9) : Integer
+    %11 = Assign(%10) : Integer
+    %12 = Jump(bb1) : Moot
+  bb3:
+    %13 = Assign(%4) : Integer
+    %14 = Return(%13) : Integer
+";
+
+    check(
+      src,
+      &[i],
+      expected,
+      &[
+        (Value::Integer(5), Value::Integer(5)),
+        (Value::Integer(-1), Value::Integer(0)),
+      ],
+    );
+  }
+
+  #[test]
+  fn rejects_reads_before_assignments() {
+    let ctx = CompilerContextBuilder::new()
+      .filedata(
+        "fn f(Boolean) -> Integer:
+  bb0:
+    %0 = Param(0) : Boolean
+    %1 = Branch(%0, bb2, bb1) : Moot
+  bb1:
+    %2 = ConstInteger(1) : Integer
+    %3 = Nop : Moot
+    %4 = Jump(bb2) : Moot
+  bb2:
+    %5 = ConstInteger(0) : Integer
+    %6 = Return(%5) : Integer
+"
+        .into(),
+      )
+      .take();
+
+    let unit = text::parse(&ctx).unwrap();
+
+    let x = Variable {
+      ty: Type::Integer,
+      defs: vec![(3, 2)],
+      uses: vec![5],
+    };
+
+    assert_eq!(
+      construct(&unit.funcs[0].body, &[x]).err(),
+      Some("a variable is read in bb2 before it is assigned".to_string())
+    );
+  }
+}
@@ -0,0 +1,948 @@
+// a textual form of the IR that can be printed and read back,
+// used to write IR by hand, e.g. to test the optimizer passes
+//
+// include "math.h"
+// newtype Meters = Floating
+//
+// trait Show:
+//   show(T0) -> Moot
+//
+// impl #0 for Integer: @1
+//
+// const LIMIT: Integer:
+//   bb0:
+//     %0 = ConstInteger(10) : Integer
+//     %1 = Return(%0) : Integer
+//
+// fn sqrt(Floating) -> Floating extern
+//
+// ## doubles x, if it is positive
+// fn double(Integer) -> Integer export inline:
+//   bb0:
+//     %0 = Param(0) : Integer
+//     %1 = ConstInteger(0) : Integer
+//     %2 = LessThan(%1, %0) : Boolean
+//     %3 = Branch(%2, bb1, bb2) : Moot
+//   bb1:
+//     %4 = Add(%0, %0) : Integer
+//     %5 = Return(%4) : Integer
+//   bb2:
+//     %6 = Return(%0) : Integer
+//
+// functions, constants and traits are referred to by their index,
+// as @n, $n and #n, instructions by their id, as %n, and blocks as bbn,
+// every block is labeled and ends with its terminator
+//
+// a function header carries the type arguments of an instantiated
+// template in [], the trait bounds of its type parameters in <>,
+// e.g. fn max<T0: #0 + #1>(T0, T0) -> T0,
+// and ends with its linkage and the flags const, inline and method(<impl>)
+//
+// there are no tokens to point diagnostics at, so everything parsed
+// refers to the token 0, and names are spans into the parsed text
+
+use std::{fmt::Display, str::FromStr};
+
+use crate::{
+  context::CompilerContext,
+  token::{Span, TokIdx},
+};
+
+use super::{
+  builder::IrBuilder, InstructionValue, IrBody, IrConst, IrFunction, IrImpl, IrMethodSig,
+  IrNewtype, IrTrait, IrUnit, Linkage, Type,
+};
+
+// THIR is printed the same way, and parses back as the unit it was lowered from
+pub struct FunctionText<'a, T = Type>(pub &'a CompilerContext, pub &'a IrFunction<T>);
+
+impl<'a, T: Display> Display for FunctionText<'a, T> {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    let FunctionText(ctx, func) = self;
+
+    for doc in func.docs.iter() {
+      writeln!(f, "## {}", ctx.get_str_from_span(*doc))?;
+    }
+
+    write!(f, "fn {}", ctx.get_str_from_span(func.name))?;
+
+    if !func.type_args.is_empty() {
+      write!(f, "[{}]", join(&func.type_args))?;
+    }
+
+    if !func.type_params.is_empty() {
+      let params = func
+        .type_params
+        .iter()
+        .enumerate()
+        .map(|(n, bounds)| match bounds.is_empty() {
+          true => format!("T{}", n),
+          false => format!(
+            "T{}: {}",
+            n,
+            bounds
+              .iter()
+              .map(|x| format!("#{}", x))
+              .collect::<Vec<_>>()
+              .join(" + ")
+          ),
+        })
+        .collect::<Vec<_>>();
+
+      write!(f, "<{}>", params.join(", "))?;
+    }
+
+    write!(f, "({}) -> {}", join(&func.params), func.return_type)?;
+
+    match func.linkage {
+      Linkage::Internal => (),
+      Linkage::Extern => f.write_str(" extern")?,
+      Linkage::Export => f.write_str(" export")?,
+    }
+
+    if func.is_const {
+      f.write_str(" const")?;
+    }
+
+    if func.inline {
+      f.write_str(" inline")?;
+    }
+
+    if let Some(imp) = func.method_of {
+      write!(f, " method({})", imp)?;
+    }
+
+    if func.body.instrs.is_empty() {
+      return writeln!(f);
+    }
+
+    writeln!(f, ":")?;
+    write_body(f, &func.body)
+  }
+}
+
+pub struct UnitText<'a, T = Type>(pub &'a CompilerContext, pub &'a IrUnit<T>);
+
+impl<'a, T: Display> Display for UnitText<'a, T> {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    let UnitText(ctx, unit) = self;
+
+    // every item is followed by an empty line
+    for header in unit.includes.iter() {
+      writeln!(f, "include \"{}\"\n", ctx.get_str_from_span(*header))?;
+    }
+
+    for newtype in unit.newtypes.iter() {
+      writeln!(
+        f,
+        "newtype {} = {}\n",
+        ctx.get_str_from_span(newtype.name),
+        newtype.ty
+      )?;
+    }
+
+    for tr in unit.traits.iter() {
+      writeln!(f, "trait {}:", ctx.get_str_from_span(tr.name))?;
+
+      for method in tr.methods.iter() {
+        writeln!(
+          f,
+          "  {}({}) -> {}",
+          ctx.get_str_from_span(method.name),
+          join(&method.params),
+          method.return_type
+        )?;
+      }
+
+      writeln!(f)?;
+    }
+
+    for imp in unit.impls.iter() {
+      let methods = imp
+        .methods
+        .iter()
+        .map(|x| format!("@{}", x))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+      writeln!(f, "impl #{} for {}: {}\n", imp.trait_idx, imp.ty, methods)?;
+    }
+
+    for constant in unit.consts.iter() {
+      writeln!(
+        f,
+        "const {}: {}:",
+        ctx.get_str_from_span(constant.name),
+        constant.ty
+      )?;
+      write_body(f, &constant.init.body)?;
+      writeln!(f)?;
+    }
+
+    for func in unit.funcs.iter() {
+      writeln!(f, "{}", FunctionText(ctx, func))?;
+    }
+
+    Ok(())
+  }
+}
+
+fn join(types: &[impl Display]) -> String {
+  types
+    .iter()
+    .map(|x| x.to_string())
+    .collect::<Vec<_>>()
+    .join(", ")
+}
+
+fn write_body(f: &mut std::fmt::Formatter<'_>, body: &IrBody<impl Display>) -> std::fmt::Result {
+  for (n, block) in body.blocks.iter().enumerate() {
+    writeln!(f, "  bb{}:", n)?;
+
+    for idx in block.instrs() {
+      let instr = &body.instrs[idx];
+      writeln!(f, "    %{} = {} : {}", idx, instr.val, instr.ty)?;
+    }
+  }
+
+  Ok(())
+}
+
+// parses the input of @ctx as textual IR
+pub fn parse(ctx: &CompilerContext) -> Result<IrUnit, String> {
+  TextParser {
+    src: ctx.get_input_str(),
+    pos: 0,
+    line: 1,
+  }
+  .parse_unit()
+}
+
+// the token every parsed instruction and item refers to
+const TOK: TokIdx = 0;
+
+struct TextParser<'a> {
+  src: &'a str,
+  pos: usize,
+
+  // for error messages, starting at 1
+  line: usize,
+}
+
+impl<'a> TextParser<'a> {
+  fn error<T>(&self, msg: impl Display) -> Result<T, String> {
+    Err(format!("line {}: {}", self.line, msg))
+  }
+
+  fn rest(&self) -> &'a str {
+    &self.src[self.pos..]
+  }
+
+  fn skip_spaces(&mut self) {
+    let rest = self.rest();
+    self.pos += rest.len() - rest.trim_start_matches([' ', '\t']).len();
+  }
+
+  fn at_line_end(&mut self) -> bool {
+    self.skip_spaces();
+    self.rest().is_empty() || self.rest().starts_with(['\n', '\r'])
+  }
+
+  // moves past the end of the current line,
+  // which must not contain anything but whitespace
+  fn end_line(&mut self) -> Result<(), String> {
+    if !self.at_line_end() {
+      let word = self.peek_word();
+      return self.error(format!("Unexpected {:?}", word));
+    }
+
+    self.pos += self.rest().find('\n').map_or(self.rest().len(), |x| x + 1);
+    self.line += 1;
+
+    Ok(())
+  }
+
+  // skips empty lines and // comments
+  fn skip_empty_lines(&mut self) {
+    loop {
+      let line = self.rest().split('\n').next().unwrap().trim();
+
+      if self.rest().is_empty() || !(line.is_empty() || line.starts_with("//")) {
+        return;
+      }
+
+      self.pos += self.rest().find('\n').map_or(self.rest().len(), |x| x + 1);
+      self.line += 1;
+    }
+  }
+
+  // whether the next line is indented, i.e. belongs to the current item
+  fn at_indented_line(&mut self) -> bool {
+    self.skip_empty_lines();
+    self.rest().starts_with([' ', '\t'])
+  }
+
+  fn eat(&mut self, s: &str) -> bool {
+    self.skip_spaces();
+
+    if self.rest().starts_with(s) {
+      self.pos += s.len();
+      true
+    } else {
+      false
+    }
+  }
+
+  fn expect(&mut self, s: &str) -> Result<(), String> {
+    if !self.eat(s) {
+      let word = self.peek_word();
+      return self.error(format!("Expected {:?}, found {:?}", s, word));
+    }
+
+    Ok(())
+  }
+
+  fn peek_word(&mut self) -> &'a str {
+    self.skip_spaces();
+
+    let rest = self.rest();
+    let len = rest
+      .find(|x: char| x.is_whitespace() || "(),:[]<>".contains(x))
+      .unwrap_or(rest.len());
+
+    &rest[..len.max(rest.chars().next().map_or(0, |x| x.len_utf8()))]
+  }
+
+  fn word(&mut self) -> Result<Span, String> {
+    self.skip_spaces();
+
+    let rest = self.rest();
+    let len = rest
+      .find(|x: char| !(x.is_alphanumeric() || x == '_'))
+      .unwrap_or(rest.len());
+
+    if len == 0 {
+      let word = self.peek_word();
+      return self.error(format!("Expected a name, found {:?}", word));
+    }
+
+    self.pos += len;
+
+    Ok(Span {
+      start: self.pos - len,
+      end: self.pos,
+    })
+  }
+
+  fn text(&self, span: Span) -> &'a str {
+    &self.src[span.start..span.end]
+  }
+
+  fn number<T: FromStr>(&mut self) -> Result<T, String> {
+    let word = self.peek_word();
+
+    match word.parse() {
+      Ok(n) => {
+        self.pos += word.len();
+        Ok(n)
+      }
+
+      Err(_) => self.error(format!("Expected a number, found {:?}", word)),
+    }
+  }
+
+  // a number with a prefix, e.g. %3
+  fn reference(&mut self, sigil: &str) -> Result<usize, String> {
+    self.expect(sigil)?;
+    self.number()
+  }
+
+  // a comma separated list of @f, ended by @close
+  fn list<T>(
+    &mut self,
+    close: &str,
+    mut f: impl FnMut(&mut Self) -> Result<T, String>,
+  ) -> Result<Vec<T>, String> {
+    let mut out = vec![];
+
+    if self.eat(close) {
+      return Ok(out);
+    }
+
+    loop {
+      out.push(f(self)?);
+
+      if self.eat(close) {
+        return Ok(out);
+      }
+
+      self.expect(",")?;
+    }
+  }
+
+  // the parameters of a call, each one preceded by a comma
+  fn params(&mut self) -> Result<Vec<usize>, String> {
+    let mut params = vec![];
+
+    while self.eat(",") {
+      params.push(self.reference("%")?);
+    }
+
+    Ok(params)
+  }
+
+  fn parse_type(&mut self) -> Result<Type, String> {
+    if self.eat("(") {
+      return Ok(Type::Tuple(self.list(")", Self::parse_type)?));
+    }
+
+    let span = self.word()?;
+
+    Ok(match self.text(span) {
+      "Undecided" => Type::Undecided,
+      "Invalid" => Type::Invalid,
+      "Floating" => Type::Floating,
+      "Integer" => Type::Integer,
+      "Boolean" => Type::Boolean,
+      "Moot" => Type::Moot,
+
+      "Option" => {
+        self.expect("[")?;
+        let ty = self.parse_type()?;
+        self.expect("]")?;
+
+        Type::Option(Box::new(ty))
+      }
+
+      "Pointer" => {
+        self.expect("[")?;
+        let ty = self.parse_type()?;
+        self.expect("]")?;
+
+        Type::Pointer(Box::new(ty))
+      }
+
+      "Result" => {
+        self.expect("[")?;
+        let ok = self.parse_type()?;
+        self.expect(",")?;
+        let err = self.parse_type()?;
+        self.expect("]")?;
+
+        Type::Result(Box::new(ok), Box::new(err))
+      }
+
+      name => match name.strip_prefix('T').map(str::parse) {
+        Some(Ok(n)) => Type::Generic(n),
+        _ => Type::Newtype(name.to_string()),
+      },
+    })
+  }
+
+  fn parse_value(&mut self) -> Result<InstructionValue, String> {
+    use InstructionValue as V;
+
+    let span = self.word()?;
+    let name = self.text(span);
+
+    match name {
+      "Nop" => return Ok(V::Nop),
+      "None" => return Ok(V::OptionNone),
+      "Exit" => return Ok(V::Exit),
+      _ => (),
+    }
+
+    self.expect("(")?;
+
+    let val = match name {
+      "ConstInteger" => V::ConstInteger(self.number()?),
+      "ConstFloat" => V::ConstFloat(self.number()?),
+      "Param" => V::Param(self.number()?),
+      "Const" => V::Const(self.reference("$")?),
+
+      "Assign" => V::Assign(self.reference("%")?),
+      "Some" => V::OptionSome(self.reference("%")?),
+      "Ok" => V::ResultOk(self.reference("%")?),
+      "Err" => V::ResultErr(self.reference("%")?),
+      "IsFailure" => V::IsFailure(self.reference("%")?),
+      "Unwrap" => V::Unwrap(self.reference("%")?),
+      "Failure" => V::Failure(self.reference("%")?),
+      "Load" => V::Load(self.reference("%")?),
+      "Jump" => V::Jump(self.reference("bb")?),
+      "Return" => V::Return(self.reference("%")?),
+
+      "Alloca" => V::Alloca(self.parse_type()?),
+
+      "Cast" => {
+        let val = self.reference("%")?;
+        self.expect(",")?;
+        V::Cast(val, self.parse_type()?)
+      }
+
+      "TupleGet" => {
+        let val = self.reference("%")?;
+        self.expect(",")?;
+        V::TupleGet(val, self.number()?)
+      }
+
+      "GetElementAddr" => {
+        let ptr = self.reference("%")?;
+        self.expect(",")?;
+        V::GetElementAddr(ptr, self.number()?)
+      }
+
+      "Branch" => {
+        let cond = self.reference("%")?;
+        self.expect(",")?;
+        let then = self.reference("bb")?;
+        self.expect(",")?;
+        V::Branch(cond, then, self.reference("bb")?)
+      }
+
+      "Add" | "Subtract" | "Multiply" | "Divide" | "LessThan" | "LessEqual" | "UnwrapOr"
+      | "Store" => {
+        let l = self.reference("%")?;
+        self.expect(",")?;
+        let r = self.reference("%")?;
+
+        match name {
+          "Add" => V::Add(l, r),
+          "Subtract" => V::Subtract(l, r),
+          "Multiply" => V::Multiply(l, r),
+          "Divide" => V::Divide(l, r),
+          "LessThan" => V::LessThan(l, r),
+          "LessEqual" => V::LessEqual(l, r),
+          "UnwrapOr" => V::UnwrapOr(l, r),
+          _ => V::Store(l, r),
+        }
+      }
+
+      // the lists close the parenthesis themselves
+      "Phi" => return Ok(V::Phi(self.list(")", |x| x.reference("%"))?)),
+      "Tuple" => return Ok(V::Tuple(self.list(")", |x| x.reference("%"))?)),
+
+      "Call" => V::Call {
+        func: self.reference("@")?,
+        params: self.params()?,
+      },
+
+      "MethodCall" => {
+        let method = self.word()?;
+
+        V::MethodCall {
+          method: self.text(method).to_string(),
+          params: self.params()?,
+        }
+      }
+
+      _ => return self.error(format!("Unknown instruction {}", name)),
+    };
+
+    self.expect(")")?;
+    Ok(val)
+  }
+
+  // the indented lines of blocks and their instructions following an item
+  fn parse_body(&mut self) -> Result<IrBody, String> {
+    let mut builder = IrBuilder::new();
+    let mut blocks = 0;
+
+    while self.at_indented_line() {
+      // bb<n>: starts the next block, once the one before it has ended
+      if self.eat("bb") {
+        let n: usize = self.number()?;
+        if n != blocks {
+          return self.error(format!("Expected bb{}, found bb{}", blocks, n));
+        }
+
+        if n > 0 && !builder.is_terminated() {
+          return self.error(format!("bb{} does not end in a terminator", n - 1));
+        }
+
+        self.expect(":")?;
+        self.end_line()?;
+
+        if n > 0 {
+          let label = builder.create_block();
+          builder.set_insertion_point(label);
+        }
+
+        blocks += 1;
+        continue;
+      }
+
+      if blocks == 0 || builder.is_terminated() {
+        let word = self.peek_word();
+        return self.error(format!("Expected bb{}, found {:?}", blocks, word));
+      }
+
+      let idx = self.reference("%")?;
+      if idx != builder.position() {
+        return self.error(format!("Expected %{}, found %{}", builder.position(), idx));
+      }
+
+      self.expect("=")?;
+      let val = self.parse_value()?;
+      self.expect(":")?;
+      let ty = self.parse_type()?;
+      self.end_line()?;
+
+      builder.build(val, ty, TOK);
+    }
+
+    if blocks > 0 && !builder.is_terminated() {
+      return self.error(format!("bb{} does not end in a terminator", blocks - 1));
+    }
+
+    Ok(builder.finish())
+  }
+
+  fn parse_function(&mut self, docs: Vec<Span>) -> Result<IrFunction, String> {
+    let name = self.word()?;
+
+    let type_args = match self.eat("[") {
+      true => self.list("]", Self::parse_type)?,
+      false => vec![],
+    };
+
+    let mut type_params = vec![];
+    if self.eat("<") {
+      let mut idx = 0;
+
+      type_params = self.list(">", |x| {
+        let ty = x.parse_type()?;
+        if !matches!(ty, Type::Generic(n) if n == idx) {
+          return x.error(format!("Expected T{}, found {}", idx, ty));
+        }
+        idx += 1;
+
+        let mut bounds = vec![];
+        if x.eat(":") {
+          bounds.push(x.reference("#")?);
+
+          while x.eat("+") {
+            bounds.push(x.reference("#")?);
+          }
+        }
+
+        Ok(bounds)
+      })?;
+    }
+
+    self.expect("(")?;
+    let params = self.list(")", Self::parse_type)?;
+    self.expect("->")?;
+    let return_type = self.parse_type()?;
+
+    let mut func = IrFunction {
+      name,
+      params,
+      return_type,
+      type_params,
+      type_args,
+      method_of: None,
+      linkage: Linkage::Internal,
+      is_const: false,
+      inline: false,
+      docs,
+      body: IrBody::empty(),
+    };
+
+    while !self.at_line_end() && !self.eat(":") {
+      let flag = self.word()?;
+
+      match self.text(flag) {
+        "extern" => func.linkage = Linkage::Extern,
+        "export" => func.linkage = Linkage::Export,
+        "const" => func.is_const = true,
+        "inline" => func.inline = true,
+
+        "method" => {
+          self.expect("(")?;
+          func.method_of = Some(self.number()?);
+          self.expect(")")?;
+        }
+
+        flag => return self.error(format!("Unknown function flag {}", flag)),
+      }
+    }
+
+    self.end_line()?;
+    func.body = self.parse_body()?;
+
+    Ok(func)
+  }
+
+  fn parse_unit(mut self) -> Result<IrUnit, String> {
+    let mut unit = IrUnit {
+      funcs: vec![],
+      traits: vec![],
+      impls: vec![],
+      newtypes: vec![],
+      consts: vec![],
+      includes: vec![],
+    };
+
+    let mut docs = vec![];
+
+    loop {
+      self.skip_empty_lines();
+
+      if self.rest().is_empty() {
+        break;
+      }
+
+      if self.at_indented_line() {
+        return self.error("Unexpected indentation");
+      }
+
+      // ## <text>, the documentation of the next function
+      if self.eat("##") {
+        self.skip_spaces();
+
+        let len = self.rest().find(['\r', '\n']).unwrap_or(self.rest().len());
+        docs.push(Span {
+          start: self.pos,
+          end: self.pos + len,
+        });
+
+        self.pos += len;
+        self.end_line()?;
+        continue;
+      }
+
+      let keyword = self.word()?;
+
+      if self.text(keyword) != "fn" && !docs.is_empty() {
+        return self.error("Documentation can only be applied to functions");
+      }
+
+      match self.text(keyword) {
+        "fn" => {
+          let func = self.parse_function(std::mem::take(&mut docs))?;
+          unit.funcs.push(func);
+        }
+
+        "include" => {
+          self.expect("\"")?;
+
+          let Some(len) = self.rest().find('"') else {
+            return self.error("Unterminated string");
+          };
+
+          unit.includes.push(Span {
+            start: self.pos,
+            end: self.pos + len,
+          });
+
+          self.pos += len + 1;
+          self.end_line()?;
+        }
+
+        "newtype" => {
+          let name = self.word()?;
+          self.expect("=")?;
+          let ty = self.parse_type()?;
+          self.end_line()?;
+
+          unit.newtypes.push(IrNewtype { name, ty });
+        }
+
+        "trait" => {
+          let name = self.word()?;
+          self.expect(":")?;
+          self.end_line()?;
+
+          let mut methods = vec![];
+          while self.at_indented_line() {
+            let name = self.word()?;
+            self.expect("(")?;
+            let params = self.list(")", Self::parse_type)?;
+            self.expect("->")?;
+            let return_type = self.parse_type()?;
+            self.end_line()?;
+
+            methods.push(IrMethodSig {
+              name,
+              params,
+              return_type,
+            });
+          }
+
+          unit.traits.push(IrTrait {
+            name,
+            methods,
+            tok: TOK,
+          });
+        }
+
+        "impl" => {
+          let trait_idx = self.reference("#")?;
+          self.expect("for")?;
+          let ty = self.parse_type()?;
+          self.expect(":")?;
+
+          let mut methods = vec![];
+          if !self.at_line_end() {
+            methods.push(self.reference("@")?);
+
+            while self.eat(",") {
+              methods.push(self.reference("@")?);
+            }
+          }
+          self.end_line()?;
+
+          unit.impls.push(IrImpl {
+            trait_idx,
+            ty,
+            methods,
+            tok: TOK,
+          });
+        }
+
+        "const" => {
+          let name = self.word()?;
+          self.expect(":")?;
+          let ty = self.parse_type()?;
+          self.expect(":")?;
+          self.end_line()?;
+
+          let body = self.parse_body()?;
+
+          unit.consts.push(IrConst {
+            name,
+            ty: ty.clone(),
+            init: IrFunction {
+              name,
+              params: vec![],
+              return_type: ty,
+              type_params: vec![],
+              type_args: vec![],
+              method_of: None,
+              linkage: Linkage::Internal,
+              is_const: true,
+              inline: false,
+              docs: vec![],
+              body,
+            },
+            tok: TOK,
+          });
+        }
+
+        keyword => return self.error(format!("Unexpected {:?}", keyword)),
+      }
+    }
+
+    if !docs.is_empty() {
+      return self.error("Documentation can only be applied to functions");
+    }
+
+    Ok(unit)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::context::CompilerContextBuilder;
+
+  fn round_trip(src: &str) -> Result<String, String> {
+    let ctx = CompilerContextBuilder::new().filedata(src.into()).take();
+    let unit = parse(&ctx)?;
+
+    Ok(UnitText(&ctx, &unit).to_string())
+  }
+
+  #[test]
+  fn prints_what_it_parsed() {
+    let src = r#"include "math.h"
+
+newtype Meters = Floating
+
+trait Show:
+  show(T0) -> Moot
+  pair(T0, (Integer, Meters)) -> Result[Option[T0], Moot]
+
+impl #0 for Integer: @1, @2
+
+const LIMIT: Integer:
+  bb0:
+    %0 = ConstInteger(-10) : Integer
+    %1 = Return(%0) : Integer
+
+fn sqrt(Floating) -> Floating extern
+
+## shows x
+## twice
+fn show<T0: #0 + #1, T1>(T0, T1) -> Moot inline method(0):
+  bb0:
+    %0 = Param(0) : T0
+    %1 = MethodCall(show, %0) : Moot
+    %2 = Call(@0, %0) : Moot
+    %3 = Nop : Moot
+    %4 = Exit : Moot
+
+fn main[Integer, Meters]() -> Integer export const:
+  bb0:
+    %0 = ConstFloat(1.5) : Floating
+    %1 = Cast(%0, Integer) : Integer
+    %2 = Const($0) : Integer
+    %3 = LessThan(%1, %2) : Boolean
+    %4 = Branch(%3, bb1, bb2) : Moot
+  bb1:
+    %5 = Assign(%1) : Integer
+    %6 = Jump(bb3) : Moot
+  bb2:
+    %7 = Assign(%2) : Integer
+    %8 = Jump(bb3) : Moot
+  bb3:
+    %9 = Phi(%5, %7) : Integer
+    %10 = Tuple(%9, %0) : (Integer, Floating)
+    %11 = TupleGet(%10, 1) : Floating
+    %12 = Some(%9) : Option[Integer]
+    %13 = None : Option[Integer]
+    %14 = Ok(%9) : Result[Integer, Moot]
+    %15 = Err(%0) : Result[Integer, Floating]
+    %16 = IsFailure(%12) : Boolean
+    %17 = Unwrap(%12) : Integer
+    %18 = UnwrapOr(%13, %17) : Integer
+    %19 = Failure(%15) : Result[Moot, Floating]
+    %20 = Subtract(%17, %18) : Integer
+    %21 = Multiply(%20, %20) : Integer
+    %22 = Divide(%21, %17) : Integer
+    %23 = LessEqual(%22, %21) : Boolean
+    %24 = Add(%22, %21) : Integer
+    %25 = Return(%24) : Integer
+
+"#;
+
+    assert_eq!(round_trip(src).unwrap(), src);
+  }
+
+  #[test]
+  fn skips_comments_and_empty_lines() {
+    let src = "// a comment\n\n\nfn f() -> Integer:\n  // inside\n  bb0:\n    %0 = ConstInteger(1) : Integer\n\n    %1 = Return(%0) : Integer\n";
+
+    assert_eq!(
+      round_trip(src).unwrap(),
+      "fn f() -> Integer:\n  bb0:\n    %0 = ConstInteger(1) : Integer\n    %1 = Return(%0) : Integer\n\n"
+    );
+  }
+
+  #[test]
+  fn reports_the_line_of_an_error() {
+    let err = round_trip(
+      "fn f() -> Integer:\n  bb0:\n    %0 = ConstInteger(1) : Integer\n    %2 = Return(%0) : Integer\n",
+    );
+    assert_eq!(err.unwrap_err(), "line 4: Expected %1, found %2");
+
+    let err = round_trip("fn f() -> Integer:\n  bb0:\n    %0 = Frobnicate(%0) : Integer\n");
+    assert_eq!(err.unwrap_err(), "line 3: Unknown instruction Frobnicate");
+
+    let err = round_trip("## docs\nnewtype M = Integer\n");
+    assert_eq!(
+      err.unwrap_err(),
+      "line 2: Documentation can only be applied to functions"
+    );
+  }
+}
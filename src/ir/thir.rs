@@ -0,0 +1,168 @@
+// the typed high level representation (THIR),
+// what sema hands on to the optimizers, the interpreter and the emitters
+//
+// THIR is made out of the same instructions as the IR sema works on,
+// but its types can not be undecided or invalid, refer to a type parameter
+// or name a newtype, so the passes working on it never have to expect them
+//
+//   %2 = Add(%0, %1) : Undecided    ->    can not be lowered
+//   %2 = Add(%0, %1) : Meters       ->    %2 = Add(%0, %1) : Floating
+//
+// sema lowers a unit once it is done with it, see sema::erasure
+
+use std::{convert::Infallible, fmt::Display};
+
+use super::{IrBody, IrConst, IrFunction, IrType, IrUnit};
+
+pub type Unit = IrUnit<Type>;
+pub type Function = IrFunction<Type>;
+pub type Body = IrBody<Type>;
+pub type Const = IrConst<Type>;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Type {
+  // a 64-bit floating point number
+  Floating,
+
+  // a 64-bit signed integer
+  Integer,
+
+  // the result of a comparison
+  Boolean,
+
+  // equivalent to a void value
+  Moot,
+
+  // a fixed size list of values, e.g. (Integer, Floating)
+  Tuple(Vec<Type>),
+
+  // Option[T], either Some(T) or None
+  Option(Box<Type>),
+
+  // Result[T, E], either Ok(T) or Err(E)
+  Result(Box<Type>, Box<Type>),
+
+  // the address of a stack slot, or of an element within one
+  Pointer(Box<Type>),
+}
+
+impl Type {
+  // the types a compound type is built out of,
+  // e.g. the elements of a tuple
+  pub fn components(&self) -> Vec<&Type> {
+    match self {
+      Type::Tuple(types) => types.iter().collect(),
+      Type::Option(ty) => vec![ty],
+      Type::Result(ok, err) => vec![ok, err],
+      Type::Pointer(ty) => vec![ty],
+      _ => vec![],
+    }
+  }
+}
+
+impl IrType for Type {
+  fn is_settled(&self) -> bool {
+    true
+  }
+
+  fn is_pointer(&self) -> bool {
+    matches!(self, Type::Pointer(_))
+  }
+
+  fn is_moot(&self) -> bool {
+    *self == Type::Moot
+  }
+}
+
+// lowers a type that sema is done with,
+// fails with the part of it that has no place in THIR
+impl TryFrom<&super::Type> for Type {
+  type Error = super::Type;
+
+  fn try_from(ty: &super::Type) -> Result<Self, Self::Error> {
+    use super::Type as Hir;
+
+    let lower = |ty: &Hir| Type::try_from(ty).map(Box::new);
+
+    Ok(match ty {
+      Hir::Floating => Type::Floating,
+      Hir::Integer => Type::Integer,
+      Hir::Boolean => Type::Boolean,
+      Hir::Moot => Type::Moot,
+
+      Hir::Tuple(types) => Type::Tuple(types.iter().map(Type::try_from).collect::<Result<_, _>>()?),
+      Hir::Option(ty) => Type::Option(lower(ty)?),
+      Hir::Result(ok, err) => Type::Result(lower(ok)?, lower(err)?),
+      Hir::Pointer(ty) => Type::Pointer(lower(ty)?),
+
+      Hir::Undecided | Hir::Invalid | Hir::Generic(_) | Hir::Newtype(_) => return Err(ty.clone()),
+    })
+  }
+}
+
+// every THIR type is also a type sema understands
+impl From<&Type> for super::Type {
+  fn from(ty: &Type) -> Self {
+    use super::Type as Hir;
+
+    match ty {
+      Type::Floating => Hir::Floating,
+      Type::Integer => Hir::Integer,
+      Type::Boolean => Hir::Boolean,
+      Type::Moot => Hir::Moot,
+
+      Type::Tuple(types) => Hir::Tuple(types.iter().map(Hir::from).collect()),
+      Type::Option(ty) => Hir::Option(Box::new(ty.as_ref().into())),
+      Type::Result(ok, err) => {
+        Hir::Result(Box::new(ok.as_ref().into()), Box::new(err.as_ref().into()))
+      }
+      Type::Pointer(ty) => Hir::Pointer(Box::new(ty.as_ref().into())),
+    }
+  }
+}
+
+impl From<Unit> for IrUnit {
+  fn from(unit: Unit) -> Self {
+    let Ok(unit) = unit.map_types(|ty| Ok::<_, Infallible>(ty.into()));
+    unit
+  }
+}
+
+impl Display for Type {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{}", super::Type::from(self))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::ir::Type as Hir;
+
+  #[test]
+  fn lowers_settled_types() {
+    let ty = Hir::Result(
+      Box::new(Hir::Tuple(vec![Hir::Integer, Hir::Floating])),
+      Box::new(Hir::Pointer(Box::new(Hir::Moot))),
+    );
+
+    let lowered = Type::try_from(&ty).unwrap();
+
+    assert_eq!(
+      lowered.to_string(),
+      "Result[(Integer, Floating), Pointer[Moot]]"
+    );
+    assert_eq!(Hir::from(&lowered), ty);
+  }
+
+  #[test]
+  fn rejects_unsettled_types() {
+    let ty = Hir::Option(Box::new(Hir::Tuple(vec![Hir::Integer, Hir::Undecided])));
+    assert_eq!(Type::try_from(&ty), Err(Hir::Undecided));
+
+    let ty = Hir::Newtype("Meters".to_string());
+    assert_eq!(Type::try_from(&ty), Err(ty));
+
+    assert_eq!(Type::try_from(&Hir::Generic(0)), Err(Hir::Generic(0)));
+  }
+}
@@ -0,0 +1,424 @@
+// checks that a unit is well-formed,
+// so that a pass breaking the IR is caught right after it ran
+// instead of somewhere down the line in a later pass or the emitter
+//
+// - operands refer to instructions that come before them
+//   and are defined in a block dominating the use,
+//   apart from the operands of a Phi which may come from a back-edge
+// - a Phi has one operand for every predecessor that can be reached,
+//   not counting the operands that can not be reached themselves
+// - the blocks cover the instructions in order, each one ending
+//   in its only terminator, and jumps stay within the function
+// - a function returning a value can not reach an Exit,
+//   which sema reports for the source, see sema::returns
+// - calls pass as many parameters as the callee takes
+// - once sema is done, no types are left undecided or invalid,
+//   and memory is only ever accessed through pointers
+
+use crate::{context::CompilerContext, diagnostic::DiagnosticLevel};
+
+use super::{cfg::Cfg, dom::DomTree, InstructionValue, IrBody, IrFunction, IrType, IrUnit};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+  // the types may still be undecided, e.g. right after ast2ir
+  Untyped,
+
+  // sema has decided the type of every instruction
+  Typed,
+}
+
+// returns every problem found within @unit
+pub fn verify<T: IrType>(
+  ctx: &CompilerContext,
+  unit: &IrUnit<T>,
+  stage: Stage,
+) -> Result<(), Vec<String>> {
+  let mut errors = vec![];
+
+  let funcs = unit.funcs.iter().chain(unit.consts.iter().map(|x| &x.init));
+
+  for func in funcs {
+    let name = ctx.get_str_from_span(func.name);
+
+    errors.extend(
+      verify_function(ctx, unit, func, stage)
+        .into_iter()
+        .map(|x| format!("in {}: {}", name, x)),
+    );
+  }
+
+  match errors.is_empty() {
+    true => Ok(()),
+    false => Err(errors),
+  }
+}
+
+// verifies @unit in debug builds,
+// panics naming @pass if it left the IR malformed
+//
+// a unit that sema reported an error for is never emitted,
+// and is allowed to be left half-typed and inconsistent
+pub fn check<T: IrType>(ctx: &CompilerContext, unit: &IrUnit<T>, pass: &str, stage: Stage) {
+  let failed = ctx
+    .get_diagnostics()
+    .iter()
+    .any(|x| matches!(x.level, DiagnosticLevel::Error));
+
+  if !cfg!(debug_assertions) || failed {
+    return;
+  }
+
+  if let Err(errors) = verify(ctx, unit, stage) {
+    panic!("the IR is malformed after {}:\n{}", pass, errors.join("\n"));
+  }
+}
+
+fn verify_function<T: IrType>(
+  ctx: &CompilerContext,
+  unit: &IrUnit<T>,
+  func: &IrFunction<T>,
+  stage: Stage,
+) -> Vec<String> {
+  let mut errors = vec![];
+  let instrs = &func.body.instrs;
+
+  if instrs.is_empty() {
+    return errors;
+  }
+
+  // malformed blocks can not be turned into a graph
+  errors.extend(verify_blocks(&func.body));
+  if !errors.is_empty() {
+    return errors;
+  }
+
+  let cfg = Cfg::new(&func.body);
+  let dom = DomTree::new(&cfg);
+  let reachable = cfg.reachable();
+
+  // templates only get typed through their instantiations
+  let typed = stage == Stage::Typed && func.type_params.is_empty();
+
+  if typed {
+    for ty in func.params.iter().chain(std::iter::once(&func.return_type)) {
+      if !ty.is_settled() {
+        errors.push(format!("the signature has the unsettled type {}", ty));
+      }
+    }
+  }
+
+  for (idx, instr) in instrs.iter().enumerate() {
+    let block = cfg.block_of(idx);
+
+    if typed && reachable[block] && !instr.ty.is_settled() {
+      errors.push(format!("%{} has the unsettled type {}", idx, instr.ty));
+    }
+
+    for operand in instr.val.operands() {
+      if operand >= instrs.len() {
+        errors.push(format!("%{} uses %{}, which does not exist", idx, operand));
+        continue;
+      }
+
+      if matches!(instrs[operand].val, InstructionValue::Nop) {
+        errors.push(format!(
+          "%{} uses %{}, which has been removed",
+          idx, operand
+        ));
+        continue;
+      }
+
+      if matches!(instr.val, InstructionValue::Phi(_)) || !reachable[block] {
+        continue;
+      }
+
+      if operand >= idx || !dom.dominates(cfg.block_of(operand), block) {
+        errors.push(format!(
+          "%{} uses %{}, which does not dominate it",
+          idx, operand
+        ));
+      }
+    }
+
+    match instr.val {
+      InstructionValue::Phi(ref vals) if reachable[block] => {
+        let preds = cfg
+          .predecessors(block)
+          .iter()
+          .filter(|x| reachable[**x])
+          .count();
+
+        // operands left in unreachable code never get to write the phi
+        let operands = vals
+          .iter()
+          .filter(|x| **x < instrs.len() && reachable[cfg.block_of(**x)])
+          .count();
+
+        if operands != preds {
+          errors.push(format!(
+            "%{} has {} operands, but its block has {} predecessors",
+            idx, operands, preds
+          ));
+        }
+      }
+
+      InstructionValue::Load(ptr)
+      | InstructionValue::Store(ptr, _)
+      | InstructionValue::GetElementAddr(ptr, _)
+        if typed && ptr < instrs.len() && !instrs[ptr].ty.is_pointer() =>
+      {
+        errors.push(format!(
+          "%{} accesses memory through %{}, which is not a pointer",
+          idx, ptr
+        ));
+      }
+
+      InstructionValue::Param(n) if n >= func.params.len() => {
+        errors.push(format!(
+          "%{} reads the parameter {}, but there are only {}",
+          idx,
+          n,
+          func.params.len()
+        ));
+      }
+
+      InstructionValue::Const(n) if n >= unit.consts.len() => {
+        errors.push(format!(
+          "%{} reads the constant ${}, which does not exist",
+          idx, n
+        ));
+      }
+
+      InstructionValue::Call { func, ref params } => match unit.funcs.get(func) {
+        Some(callee) if callee.params.len() != params.len() => errors.push(format!(
+          "%{} passes {} parameters to @{}, which takes {}",
+          idx,
+          params.len(),
+          func,
+          callee.params.len()
+        )),
+
+        Some(_) => (),
+        None => errors.push(format!("%{} calls @{}, which does not exist", idx, func)),
+      },
+
+      InstructionValue::MethodCall {
+        ref method,
+        ref params,
+      } => {
+        let arities: Vec<usize> = unit
+          .traits
+          .iter()
+          .flat_map(|x| x.methods.iter())
+          .filter(|x| ctx.get_str_from_span(x.name) == method)
+          .map(|x| x.params.len())
+          .collect();
+
+        if !arities.is_empty() && !arities.contains(&params.len()) {
+          errors.push(format!(
+            "%{} passes {} parameters to the method {}, which takes {:?}",
+            idx,
+            params.len(),
+            method,
+            arities
+          ));
+        }
+      }
+
+      _ => (),
+    }
+  }
+
+  // falling off the end of the function without a value
+  let exit = (0..cfg.blocks().len())
+    .any(|x| reachable[x] && matches!(func.body.terminator(x).val, InstructionValue::Exit));
+
+  if exit && !func.return_type.is_moot() && func.type_params.is_empty() {
+    errors.push(format!(
+      "the end can be reached without a Return, but the function returns {}",
+      func.return_type
+    ));
+  }
+
+  errors
+}
+
+fn verify_blocks<T>(body: &IrBody<T>) -> Vec<String> {
+  let mut errors = vec![];
+  let instrs = &body.instrs;
+
+  // where the next block has to start
+  let mut next = 0;
+
+  for (b, block) in body.blocks.iter().enumerate() {
+    if block.start != next {
+      errors.push(format!(
+        "bb{} starts at %{} instead of %{}",
+        b, block.start, next
+      ));
+    }
+
+    if block.end <= block.start || block.end > instrs.len() {
+      errors.push(format!("bb{} covers %{}..%{}", b, block.start, block.end));
+      return errors;
+    }
+
+    next = block.end;
+
+    if !instrs[block.terminator()].val.is_terminator() {
+      errors.push(format!("bb{} does not end in a terminator", b));
+    }
+
+    for idx in block.instrs() {
+      let val = &instrs[idx].val;
+
+      if val.is_terminator() && idx != block.terminator() {
+        errors.push(format!("%{} is a terminator in the middle of bb{}", idx, b));
+      }
+
+      for target in val.successors() {
+        if target >= body.blocks.len() {
+          errors.push(format!(
+            "%{} jumps to bb{}, which does not exist",
+            idx, target
+          ));
+        }
+      }
+    }
+  }
+
+  if next != instrs.len() {
+    errors.push(format!("%{} is not part of any block", next));
+  }
+
+  errors
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{context::CompilerContextBuilder, ir::text};
+
+  fn errors(src: &str) -> Vec<String> {
+    let ctx = CompilerContextBuilder::new().filedata(src.into()).take();
+    let unit = text::parse(&ctx).unwrap();
+
+    verify(&ctx, &unit, Stage::Typed).err().unwrap_or_default()
+  }
+
+  #[test]
+  fn accepts_loops_and_branches() {
+    let src = "
+fn f(Integer) -> Integer:
+  bb0:
+    %0 = Param(0) : Integer
+    %1 = ConstInteger(1) : Integer
+    %2 = Assign(%0) : Integer
+    %3 = Jump(bb1) : Moot
+  bb1:
+    %4 = Phi(%2, %9) : Integer
+    %5 = LessThan(%4, %1) : Boolean
+    %6 = Branch(%5, bb2, bb3) : Moot
+  bb2:
+    %7 = ConstInteger(2) : Integer
+    %8 = Call(@0, %7) : Integer
+    %9 = Add(%4, %8) : Integer
+    %10 = Jump(bb1) : Moot
+  bb3:
+    %11 = Return(%4) : Integer
+";
+
+    assert_eq!(errors(src), Vec::<String>::new());
+  }
+
+  #[test]
+  fn rejects_operands_that_do_not_dominate() {
+    let src = "
+fn f(Boolean) -> Integer:
+  bb0:
+    %0 = Param(0) : Boolean
+    %1 = Branch(%0, bb2, bb1) : Moot
+  bb1:
+    %2 = ConstInteger(1) : Integer
+    %3 = Jump(bb2) : Moot
+  bb2:
+    %4 = Return(%2) : Integer
+";
+
+    assert_eq!(
+      errors(src),
+      ["in f: %4 uses %2, which does not dominate it"]
+    );
+  }
+
+  #[test]
+  fn rejects_mismatched_phis_and_calls() {
+    let src = "
+fn f(Integer) -> Integer:
+  bb0:
+    %0 = Param(0) : Integer
+    %1 = Call(@0, %0, %0) : Integer
+    %2 = Phi(%0, %1) : Integer
+    %3 = Return(%2) : Integer
+";
+
+    assert_eq!(
+      errors(src),
+      [
+        "in f: %1 passes 2 parameters to @0, which takes 1",
+        "in f: %2 has 2 operands, but its block has 0 predecessors"
+      ]
+    );
+  }
+
+  #[test]
+  fn rejects_unsettled_types_and_missing_returns() {
+    let src = "
+fn f() -> Integer:
+  bb0:
+    %0 = ConstInteger(1) : Undecided
+    %1 = Tuple(%0) : (Invalid)
+    %2 = Exit : Moot
+";
+
+    assert_eq!(
+      errors(src),
+      [
+        "in f: %0 has the unsettled type Undecided",
+        "in f: %1 has the unsettled type (Invalid)",
+        "in f: the end can be reached without a Return, but the function returns Integer"
+      ]
+    );
+  }
+
+  #[test]
+  fn rejects_malformed_blocks() {
+    let ctx = CompilerContextBuilder::new()
+      .filedata(
+        "fn f() -> Integer:
+  bb0:
+    %0 = ConstInteger(1) : Integer
+    %1 = Jump(bb1) : Moot
+  bb1:
+    %2 = Return(%0) : Integer
+"
+        .into(),
+      )
+      .take();
+
+    // merges both blocks, leaving the jump in the middle of the only one
+    let mut unit = text::parse(&ctx).unwrap();
+    let body = &mut unit.funcs[0].body;
+    body.blocks[0].end = 3;
+    body.blocks.pop();
+
+    assert_eq!(
+      verify(&ctx, &unit, Stage::Typed),
+      Err(vec![
+        "in f: %1 is a terminator in the middle of bb0".to_string(),
+        "in f: %1 jumps to bb1, which does not exist".to_string()
+      ])
+    );
+  }
+}
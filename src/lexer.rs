@@ -1,29 +1,80 @@
 use crate::{
   context::CompilerContext,
+  diagnostic::{Diagnostic, DiagnosticLevel},
   token::{Span, Token, TokenType},
 };
 
 pub struct Lexer<'a> {
+  ctx: &'a CompilerContext,
   input: &'a str,
   idx: usize,
+  toks: Vec<Token>,
+
+  // widths of the indentation levels that are currently open,
+  // the outermost level always has a width of 0
+  indents: Vec<usize>,
+
+  // the whitespace character the first indented line used,
+  // every other line is expected to use the same one
+  indent_char: Option<char>,
+
+  // number of parentheses and brackets that have not been closed yet,
+  // newlines within them do not end the line
+  nesting: usize,
 }
 
 impl<'a> Lexer<'a> {
   pub fn new(ctx: &'a CompilerContext) -> Self {
     Self {
+      ctx,
       input: ctx.get_input_str(),
       idx: 0,
+      toks: vec![],
+      indents: vec![0],
+      indent_char: None,
+      nesting: 0,
     }
   }
 
+  // @idx is a byte offset into the input, so it can be used for spans directly
   fn _current_char(&self) -> Option<char> {
-    self.input.chars().nth(self.idx)
+    self.input[self.idx..].chars().next()
+  }
+
+  // formats an error at the byte offset @pos,
+  // lexer errors end lexing so they are not pushed as diagnostics
+  fn error(&self, pos: usize, info: &str) -> String {
+    Diagnostic {
+      tokidx: self.toks.len(),
+      level: DiagnosticLevel::Error,
+      info: info.to_string(),
+    }
+    .display_at(self.ctx, pos)
+  }
+
+  // moves past the current character, however many bytes it takes up
+  fn _advance(&mut self) {
+    if let Some(ch) = self._current_char() {
+      self.idx += ch.len_utf8();
+    }
   }
 
   fn _skip_whitespace(&mut self) {
     while let Some(ch) = self._current_char() {
-      if ch != ' ' {
-        break;
+      match ch {
+        ' ' | '\t' => (),
+
+        // an expression can span multiple lines within parentheses,
+        // including comments in between
+        '\n' if self.nesting > 0 => (),
+        '#' if self.nesting > 0 => {
+          while self._current_char().is_some_and(|x| x != '\n') {
+            self._advance();
+          }
+          continue;
+        }
+
+        _ => break,
       }
 
       self.idx += 1;
@@ -31,56 +82,162 @@ impl<'a> Lexer<'a> {
   }
 
   // assume the \n has already been lexed
-  fn _lex_indent<'b>(&'b mut self) -> Result<Token, String> {
+  fn _lex_indent(&mut self) -> Result<Token, String> {
     if self.input[self.idx..].trim_start().starts_with('#') {
       self._skip_whitespace();
-      let mut len: usize = 0;
+
+      // ## documents the declaration that follows
+      let ty = if self.input[self.idx..].starts_with("##") {
+        self.idx += 2;
+        if self._current_char() == Some(' ') {
+          self.idx += 1;
+        }
+
+        TokenType::DocComment
+      } else {
+        TokenType::Comment
+      };
+
+      let start = self.idx;
       while let Some(x) = self._current_char() {
         if x == '\n' {
           break;
         }
 
-        len += 1;
-        self.idx += 1;
+        self._advance();
       }
       Ok(Token {
-        ty: TokenType::Comment,
+        ty,
         span: Span {
-          start: self.idx - len,
+          start,
           end: self.idx,
         },
       })
     } else {
-      self.idx += 1;
-      let mut ind: usize = 0;
+      let mut start = self.idx;
+      let mut width: usize = 0;
+      let (mut tabs, mut spaces) = (false, false);
 
       while let Some(ch) = self._current_char() {
-        // ignore empty line
-        if ch == '\n' {
-          ind = 0;
-          self.idx += 1;
-          continue;
-        }
+        match ch {
+          // ignore empty line
+          '\n' => {
+            width = 0;
+            (tabs, spaces) = (false, false);
+            start = self.idx + 1;
+          }
 
-        if ch != ' ' {
-          break;
+          ' ' => {
+            width += 1;
+            spaces = true;
+          }
+
+          // a tab advances to the next multiple of the tab width
+          '\t' => {
+            let tab_width = self.ctx.get_tab_width();
+            width += tab_width - width % tab_width;
+            tabs = true;
+          }
+
+          _ => break,
         }
 
-        ind += 1;
         self.idx += 1;
       }
 
+      let span = Span {
+        start,
+        end: self.idx,
+      };
+
+      self.check_indent_chars(tabs, spaces, span)?;
+      self.push_layout(width, span)?;
+
       Ok(Token {
         ty: TokenType::Indentation,
-        span: Span {
-          start: self.idx - ind,
-          end: self.idx,
-        },
+        span,
       })
     }
   }
 
-  fn _lex<'b>(&'b mut self) -> Result<Token, String> {
+  // rejects indentation mixing tabs and spaces,
+  // within a line or between the lines of the file,
+  // as the width of a tab decides which block the line belongs to
+  fn check_indent_chars(&mut self, tabs: bool, spaces: bool, span: Span) -> Result<(), String> {
+    let info = match (tabs, spaces) {
+      (false, false) => return Ok(()),
+
+      (true, true) => "Indentation mixes tabs and spaces".to_string(),
+
+      _ => {
+        let ch = if tabs { '\t' } else { ' ' };
+
+        match self.indent_char {
+          None => {
+            self.indent_char = Some(ch);
+            return Ok(());
+          }
+
+          Some(expected) if expected == ch => return Ok(()),
+
+          Some(_) => format!(
+            "Indentation uses {}, but the file was indented with {} before",
+            if tabs { "tabs" } else { "spaces" },
+            if tabs { "spaces" } else { "tabs" },
+          ),
+        }
+      }
+    };
+
+    Err(self.error(
+      span.end,
+      &format!(
+        "{}, tabs are counted as {} columns",
+        info,
+        self.ctx.get_tab_width()
+      ),
+    ))
+  }
+
+  // opens or closes indentation levels for a line that is @width columns wide,
+  // pushing an indent or dedent tokens in front of its indentation
+  fn push_layout(&mut self, width: usize, span: Span) -> Result<(), String> {
+    let layout = Span {
+      start: span.end,
+      end: span.end,
+    };
+
+    let current = *self.indents.last().unwrap();
+
+    if width > current {
+      self.indents.push(width);
+      self.toks.push(Token {
+        ty: TokenType::Indent,
+        span: layout,
+      });
+
+      return Ok(());
+    }
+
+    while width < *self.indents.last().unwrap() {
+      self.indents.pop();
+      self.toks.push(Token {
+        ty: TokenType::Dedent,
+        span: layout,
+      });
+    }
+
+    if width != *self.indents.last().unwrap() {
+      return Err(self.error(
+        span.end,
+        "Unindent does not match any outer indentation level",
+      ));
+    }
+
+    Ok(())
+  }
+
+  fn _lex(&mut self) -> Result<Token, String> {
     self._skip_whitespace();
 
     match self
@@ -92,6 +249,24 @@ impl<'a> Lexer<'a> {
         self._lex_indent()
       }
 
+      // a trailing backslash continues the line on the next one
+      '\\' => {
+        self.idx += 1;
+
+        // only skip the blanks, within parentheses
+        // skipping whitespace would move past the newline as well
+        while matches!(self._current_char(), Some(' ' | '\t')) {
+          self.idx += 1;
+        }
+
+        if self._current_char() != Some('\n') {
+          return Err(self.error(self.idx, "Expected the end of the line after a \\"));
+        }
+        self.idx += 1;
+
+        self._lex()
+      }
+
       '+' => {
         self.idx += 1;
         Ok(Token {
@@ -163,8 +338,98 @@ impl<'a> Lexer<'a> {
         })
       }
 
+      '"' => {
+        let start = self.idx;
+        self.idx += 1;
+
+        while let Some(ch) = self._current_char() {
+          if ch == '"' || ch == '\n' {
+            break;
+          }
+          self._advance();
+        }
+
+        if self._current_char() != Some('"') {
+          return Err(self.error(start, "Unterminated string literal"));
+        }
+        self.idx += 1;
+
+        Ok(Token {
+          ty: TokenType::String,
+          span: Span {
+            start,
+            end: self.idx,
+          },
+        })
+      }
+
+      x @ ('<' | '>') => {
+        self.idx += 1;
+
+        let (ty, len) = match (x == '<', self._current_char() == Some('=')) {
+          (true, false) => (TokenType::Less, 1),
+          (true, true) => (TokenType::LessEqual, 2),
+          (false, false) => (TokenType::Greater, 1),
+          (false, true) => (TokenType::GreaterEqual, 2),
+        };
+        self.idx += len - 1;
+
+        Ok(Token {
+          ty,
+          span: Span {
+            start: self.idx - len,
+            end: self.idx,
+          },
+        })
+      }
+
+      '@' => {
+        self.idx += 1;
+        Ok(Token {
+          ty: TokenType::At,
+          span: Span {
+            start: self.idx - 1,
+            end: self.idx,
+          },
+        })
+      }
+
+      '?' => {
+        self.idx += 1;
+        Ok(Token {
+          ty: TokenType::Question,
+          span: Span {
+            start: self.idx - 1,
+            end: self.idx,
+          },
+        })
+      }
+
+      ',' => {
+        self.idx += 1;
+        Ok(Token {
+          ty: TokenType::Comma,
+          span: Span {
+            start: self.idx - 1,
+            end: self.idx,
+          },
+        })
+      }
+
+      '=' => {
+        self.idx += 1;
+        Ok(Token {
+          ty: TokenType::Equal,
+          span: Span {
+            start: self.idx - 1,
+            end: self.idx,
+          },
+        })
+      }
+
       '(' => {
         self.idx += 1;
+        self.nesting += 1;
         Ok(Token {
           ty: TokenType::LeftParanthesis,
           span: Span {
@@ -176,6 +441,7 @@ impl<'a> Lexer<'a> {
 
       ')' => {
         self.idx += 1;
+        self.nesting = self.nesting.saturating_sub(1);
         Ok(Token {
           ty: TokenType::RightParanthesis,
           span: Span {
@@ -185,39 +451,101 @@ impl<'a> Lexer<'a> {
         })
       }
 
-      x if x.is_digit(10) => {
-        let mut len = 0;
+      '.' => {
+        self.idx += 1;
+
+        // a single dot accesses a method, e.g. x.len()
+        if self._current_char() != Some('.') {
+          return Ok(Token {
+            ty: TokenType::Dot,
+            span: Span {
+              start: self.idx - 1,
+              end: self.idx,
+            },
+          });
+        }
+        self.idx += 1;
+
+        if self._current_char() == Some('=') {
+          self.idx += 1;
+          Ok(Token {
+            ty: TokenType::DotDotEqual,
+            span: Span {
+              start: self.idx - 3,
+              end: self.idx,
+            },
+          })
+        } else {
+          Ok(Token {
+            ty: TokenType::DotDot,
+            span: Span {
+              start: self.idx - 2,
+              end: self.idx,
+            },
+          })
+        }
+      }
+
+      '[' => {
+        self.idx += 1;
+        self.nesting += 1;
+        Ok(Token {
+          ty: TokenType::LeftBracket,
+          span: Span {
+            start: self.idx - 1,
+            end: self.idx,
+          },
+        })
+      }
+
+      ']' => {
+        self.idx += 1;
+        self.nesting = self.nesting.saturating_sub(1);
+        Ok(Token {
+          ty: TokenType::RightBracket,
+          span: Span {
+            start: self.idx - 1,
+            end: self.idx,
+          },
+        })
+      }
+
+      x if x.is_ascii_digit() => {
+        let start = self.idx;
 
         while let Some(ch) = self._current_char() {
-          if !ch.is_digit(10) && ch != '.' {
+          // only treat a '.' as a decimal point if a digit follows it,
+          // otherwise it belongs to a range operator, e.g. 0..10
+          let is_decimal_point =
+            ch == '.' && self.input[self.idx + 1..].starts_with(|x: char| x.is_ascii_digit());
+
+          if !ch.is_ascii_digit() && !is_decimal_point {
             break;
           }
 
-          len += 1;
           self.idx += 1;
         }
 
         Ok(Token {
           ty: TokenType::Number,
           span: Span {
-            start: self.idx - len,
+            start,
             end: self.idx,
           },
         })
       }
 
       x if x.is_alphabetic() => {
-        let mut len = 0;
+        let start = self.idx;
         while let Some(ch) = self._current_char() {
           if !ch.is_alphanumeric() && ch != '_' {
             break;
           }
-          len += 1;
-          self.idx += 1;
+          self._advance();
         }
 
         let span = Span {
-          start: self.idx - len,
+          start,
           end: self.idx,
         };
 
@@ -227,6 +555,22 @@ impl<'a> Lexer<'a> {
           ty: match slice {
             "return" => TokenType::Return,
             "defn" => TokenType::Defn,
+            "trait" => TokenType::Trait,
+            "implements" => TokenType::Implements,
+            "alias" => TokenType::Alias,
+            "newtype" => TokenType::Newtype,
+            "extern" => TokenType::Extern,
+            "export" => TokenType::Export,
+            "const" => TokenType::Const,
+            "include" => TokenType::Include,
+            "let" => TokenType::Let,
+            "for" => TokenType::For,
+            "in" => TokenType::In,
+            "step" => TokenType::Step,
+            "if" => TokenType::If,
+            "else" => TokenType::Else,
+            "while" => TokenType::While,
+            "do" => TokenType::Do,
 
             "Integer" => TokenType::Integer,
             "Floating" => TokenType::Floating,
@@ -239,31 +583,140 @@ impl<'a> Lexer<'a> {
         })
       }
 
-      _ => Err(format!(
-        "Fallthrough in lexer function: <{}>",
-        self._current_char().unwrap()
-      )),
+      ch => Err(self.error(self.idx, &format!("Unexpected character {:?}", ch))),
     }
   }
 
   pub fn lex(mut self) -> Result<Vec<Token>, String> {
-    let mut toks = vec![];
-
     // lex a single indentation
-    toks.push(self._lex_indent()?);
+    let tok = self._lex_indent()?;
+    self.toks.push(tok);
 
     while self.idx < self.input.len() {
-      toks.push(self._lex()?);
+      let tok = self._lex()?;
+      self.toks.push(tok);
+    }
+
+    if self.nesting > 0 {
+      return Err(self.error(
+        self.input.len(),
+        "Unclosed parenthesis or bracket at the end of the input",
+      ));
     }
 
-    toks.push(Token {
-      ty: TokenType::EOF,
-      span: Span {
-        start: self.input.len(),
-        end: self.input.len(),
-      },
+    // close every level that is still open
+    let end = Span {
+      start: self.input.len(),
+      end: self.input.len(),
+    };
+    self.push_layout(0, end)?;
+
+    self.toks.push(Token {
+      ty: TokenType::Eof,
+      span: end,
     });
 
-    Ok(toks)
+    Ok(self.toks)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::context::CompilerContextBuilder;
+
+  // the type and text of every token, leaving out the layout
+  fn lex(input: &str) -> Result<Vec<(TokenType, String)>, String> {
+    let ctx = CompilerContextBuilder::new().filedata(input.into()).take();
+
+    Ok(
+      Lexer::new(&ctx)
+        .lex()?
+        .into_iter()
+        .filter(|x| !matches!(x.ty, TokenType::Indentation | TokenType::Eof))
+        .map(|x| (x.ty, ctx.get_str_from_span(x.span).to_string()))
+        .collect(),
+    )
+  }
+
+  #[test]
+  fn lexes_numbers_after_multibyte_characters() {
+    assert_eq!(
+      lex("\"ünïcödé\" 1.5 2..3").unwrap(),
+      vec![
+        (TokenType::String, "\"ünïcödé\"".to_string()),
+        (TokenType::Number, "1.5".to_string()),
+        (TokenType::Number, "2".to_string()),
+        (TokenType::DotDot, "..".to_string()),
+        (TokenType::Number, "3".to_string()),
+      ]
+    );
+
+    assert_eq!(
+      lex("# crème brûlée\nnaïve").unwrap(),
+      vec![
+        (TokenType::Comment, "# crème brûlée".to_string()),
+        (TokenType::Identifier, "naïve".to_string()),
+      ]
+    );
+  }
+
+  #[test]
+  fn continues_lines_after_a_backslash() {
+    let expected = vec![
+      (TokenType::Identifier, "f".to_string()),
+      (TokenType::LeftParanthesis, "(".to_string()),
+      (TokenType::Number, "1".to_string()),
+      (TokenType::Comma, ",".to_string()),
+      (TokenType::Number, "2".to_string()),
+      (TokenType::RightParanthesis, ")".to_string()),
+    ];
+
+    assert_eq!(lex("f(1, \\\n  2)").unwrap(), expected);
+    assert_eq!(lex("f(1, \\  \n  2)").unwrap(), expected);
+    assert_eq!(lex("f(1,\n  2)").unwrap(), expected);
+
+    assert!(lex("f(1, \\ 2)")
+      .unwrap_err()
+      .ends_with("Expected the end of the line after a \\"));
+  }
+
+  #[test]
+  fn rejects_mixed_indentation() {
+    assert!(lex("a\n\t b")
+      .unwrap_err()
+      .contains("Indentation mixes tabs and spaces"));
+
+    assert!(lex("a\n  b\n\tc")
+      .unwrap_err()
+      .contains("Indentation uses tabs, but the file was indented with spaces before"));
+
+    // every line may use tabs, as long as all of them do
+    assert!(lex("a\n\tb\n\t\tc").is_ok());
+  }
+
+  #[test]
+  fn emits_indents_and_dedents() {
+    let ctx = CompilerContextBuilder::new()
+      .filedata("a:\n  b:\n    c\nd".into())
+      .take();
+
+    let types: Vec<_> = Lexer::new(&ctx)
+      .lex()
+      .unwrap()
+      .into_iter()
+      .map(|x| x.ty)
+      .filter(|x| matches!(x, TokenType::Indent | TokenType::Dedent))
+      .collect();
+
+    assert_eq!(
+      types,
+      vec![
+        TokenType::Indent,
+        TokenType::Indent,
+        TokenType::Dedent,
+        TokenType::Dedent
+      ]
+    );
   }
 }
@@ -1,14 +1,19 @@
 #![allow(dead_code)]
 // ^ remove this later
 
-use std::rc::Rc;
+use std::fmt::Display;
 
-use context::CompilerContextBuilder;
-use parser::Parser;
+use context::{CompilerContext, CompilerContextBuilder};
+use parser::{Ast, Parser};
 
 use crate::{
-  ast2ir::IrEmitter, emitter::Emitter, emitters::ir2c_emitter, ir::IrFuncDisplay,
-  optimizers::optimize, sema::SemaContext,
+  ast2ir::IrEmitter,
+  diagnostic::{Diagnostic, DiagnosticLevel},
+  emitter::Emitter,
+  emitters::ir2c_emitter,
+  ir::{interpreter::Interpreter, IrFuncDisplay, IrUnit},
+  optimizers::optimize,
+  sema::SemaContext,
 };
 
 mod ast2ir;
@@ -22,17 +27,33 @@ mod node;
 mod optimizers;
 mod parser;
 mod sema;
+#[cfg(test)]
+mod testing;
 mod token;
 
+fn print_functions<T: Display>(ctx: &CompilerContext, unit: &IrUnit<T>) {
+  for func in unit.funcs.iter() {
+    println!("{}", IrFuncDisplay(ctx, func));
+    println!("{}", func.cfg());
+  }
+}
+
 fn main() {
-  let mut args = std::env::args();
+  let args: Vec<String> = std::env::args().skip(1).collect();
+
+  // --run interprets main once the unit compiled
+  let (flags, files): (Vec<_>, Vec<_>) = args.iter().partition(|x| x.starts_with("--"));
+  let run = flags.iter().any(|x| *x == "--run");
 
-  if args.len() != 2 {
-    println!("Expected an input file-name");
+  if let Some(flag) = flags.iter().find(|x| **x != "--run") {
+    println!("Unknown option {flag}");
     std::process::exit(1);
   }
 
-  let filename = args.nth(1).unwrap();
+  let [filename] = files[..] else {
+    println!("Expected an input file-name, usage: yacwir [--run] <file>");
+    std::process::exit(1);
+  };
 
   let Ok(filedata) = std::fs::read_to_string(filename.clone()) else {
     println!("Failed to read file {filename}");
@@ -44,25 +65,71 @@ fn main() {
     .verbose(true)
     .take();
 
-  let ast = Parser::new(&ctx).unwrap().parse().unwrap();
+  // textual IR skips the frontend, and is handed to the backend as it is
+  let (ast, ir) = if filename.ends_with(".ir") {
+    let ir = match ir::text::parse(&ctx) {
+      Ok(ir) => ir,
+
+      Err(info) => {
+        println!("{}", info);
+        std::process::exit(1);
+      }
+    };
 
-  for (i, t) in ast.toks.iter().enumerate() {
-    if i % 5 == 4 {
-      println!();
+    // unlike the output of the passes, hand-written IR is verified in every build
+    if let Err(errors) = ir::verify::verify(&ctx, &ir, ir::verify::Stage::Typed) {
+      for error in errors {
+        println!("{}", error);
+      }
+      std::process::exit(1);
     }
-    print!("{} ", t.ty);
-  }
-  println!();
 
-  println!("{:?}", ast.nodes);
-  println!("FUNCS: {:?}", ast.funcs);
+    match SemaContext::lower(&ctx, ir) {
+      Ok(ir) => (Ast::default(), Ok(ir)),
+
+      Err(info) => {
+        println!("{}", info);
+        std::process::exit(1);
+      }
+    }
+  } else {
+    let ast = match Parser::new(&ctx).and_then(|x| x.parse()) {
+      Ok(ast) => ast,
+
+      Err(info) => {
+        println!("{}", info);
+        std::process::exit(1);
+      }
+    };
+
+    for (i, t) in ast.toks.iter().enumerate() {
+      if i % 5 == 4 {
+        println!();
+      }
+      print!("{} ", t.ty);
+    }
+    println!();
+
+    println!("{:?}", ast.nodes);
+    println!("FUNCS: {:?}", ast.funcs);
 
-  let ir_out = IrEmitter::emit(&ast).unwrap();
-  let ir = SemaContext::run(&ctx, ir_out);
-  // let ir = optimize(&ctx, ir);
+    // a unit that failed to emit has nothing to print,
+    // its error is reported along with the other diagnostics
+    let ir = match IrEmitter::emit(&ctx, &ast) {
+      Ok(ir_out) => SemaContext::run(&ctx, ir_out)
+        .map(|x| optimize(&ctx, x))
+        .map_err(Some),
+      Err(_) => Err(None),
+    };
 
-  for func in ir.funcs.iter() {
-    println!("{}\n", IrFuncDisplay(&ctx, func));
+    (ast, ir)
+  };
+
+  // a unit that failed to typecheck is printed as far as sema got with it
+  match ir {
+    Ok(ref ir) => print_functions(&ctx, ir),
+    Err(Some(ref ir)) => print_functions(&ctx, ir.as_ref()),
+    Err(None) => (),
   }
 
   // print any diagnostics
@@ -70,7 +137,384 @@ fn main() {
     println!("{}", diagnostic.display(&ctx, &ast.toks));
   }
 
+  // the ir can not be emitted if it failed to typecheck
+  let Ok(ir) = ir else {
+    std::process::exit(1);
+  };
+
+  // run the program in-process as well,
+  // the value of main becomes the exit code of the compiled C
+  if run {
+    match Interpreter::new(&ctx, &ir).run_main() {
+      Ok(value) => println!("main returned {}", value),
+
+      // textual IR has no tokens to point at
+      Err((_, info)) if ast.toks.is_empty() => println!("Runtime error: {}", info),
+
+      Err((tokidx, info)) => {
+        let diagnostic = Diagnostic {
+          tokidx,
+          level: DiagnosticLevel::Error,
+          info: format!("Runtime error: {}", info),
+        };
+
+        println!("{}", diagnostic.display(&ctx, &ast.toks));
+      }
+    }
+  }
+
   // let asm = X86Emitter::emit(&ctx, &ir_out).unwrap();
   let asm = ir2c_emitter::Ir2CEmitterContext::emit(&ctx, &ast, ir).unwrap();
-  println!("\n==== ASM OUTPUT ====\n{}", asm);
+
+  // exported functions are declared in a header next to the input file
+  if let Some(header) = asm.header {
+    let path = std::path::Path::new(&filename).with_extension("h");
+
+    if std::fs::write(&path, header).is_err() {
+      println!("Failed to write header {}", path.display());
+      std::process::exit(1);
+    }
+  }
+
+  println!("\n==== ASM OUTPUT ====\n{}", asm.source);
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{ir::interpreter::Value, testing::compile};
+
+  #[test]
+  fn runs_range_loops() {
+    let compiled = compile(
+      "defn first_square_over(limit: Integer) -> Integer:
+  for i in 3..=19 step 4:
+    if i * i > limit:
+      return i
+  return 0
+
+defn main() -> Integer:
+  for i in 5..5:
+    return 1
+  return first_square_over(50) * 100 + first_square_over(360) + first_square_over(1000)",
+    );
+
+    assert!(compiled.diagnostics.is_empty());
+    assert_eq!(compiled.value, Some(Value::Integer(1119)));
+  }
+
+  #[test]
+  fn returns_and_destructures_tuples() {
+    let compiled = compile(
+      "defn divmod(a: Integer, b: Integer) -> (Integer, Integer):
+  return (a / b, a - a / b * b)
+
+defn main() -> Integer:
+  let (q, r) = divmod(47, 5)
+  let ((a, b), c) = ((1, 2), 3)
+  return q * 100 + r * 10 + a + b + c",
+    );
+
+    assert!(compiled.diagnostics.is_empty());
+    assert_eq!(compiled.value, Some(Value::Integer(926)));
+  }
+
+  #[test]
+  fn instantiates_generic_functions() {
+    let compiled = compile(
+      "defn max[T](a: T, b: T) -> T:
+  return if a < b: b else: a
+
+defn main() -> Integer:
+  return max(4, 9) + Integer(max(1.5, 0.5) * 2.0)",
+    );
+
+    assert!(compiled.diagnostics.is_empty());
+    assert_eq!(compiled.value, Some(Value::Integer(12)));
+
+    // one function for each instantiation, and none for the template
+    let (source, _) = compiled.files.unwrap();
+    assert!(source.contains("long long max__Integer(long long PARAM0, long long PARAM1) {"));
+    assert!(source.contains("double max__Floating(double PARAM0, double PARAM1) {"));
+    assert!(!source.contains("max("));
+  }
+
+  #[test]
+  fn dispatches_trait_methods_statically() {
+    let compiled = compile(
+      "trait Shape:
+  defn area(self) -> Integer
+  defn scale(self, k: Integer) -> Self
+
+implements Shape for (Integer, Integer):
+  defn area(self) -> Integer:
+    let (w, h) = self
+    return w * h
+  defn scale(self, k: Integer) -> Self:
+    let (w, h) = self
+    return (w * k, h * k)
+
+implements Shape for Integer:
+  defn area(self) -> Integer:
+    return self * self
+  defn scale(self, k: Integer) -> Integer:
+    return self * k
+
+defn total[T: Shape](x: T, y: T) -> Integer:
+  return x.area() + y.scale(2).area()
+
+defn main() -> Integer:
+  let r = (2, 3)
+  return total(r, (1, 1)) + total(1, 2) + r.scale(2).area()",
+    );
+
+    assert!(compiled.diagnostics.is_empty());
+    assert_eq!(compiled.value, Some(Value::Integer(51)));
+  }
+
+  #[test]
+  fn keeps_newtypes_apart_from_the_types_they_wrap() {
+    let compiled = compile(
+      "alias Meters = Floating
+newtype UserId = Integer
+
+defn dist(a: Meters, b: Floating) -> Meters:
+  return a + b
+
+defn main() -> Integer:
+  let id = UserId(4)
+  return Integer(id) * 10 + Integer(dist(1.5, 2.0))",
+    );
+
+    assert!(compiled.diagnostics.is_empty());
+    assert_eq!(compiled.value, Some(Value::Integer(43)));
+
+    let compiled = compile(
+      "newtype UserId = Integer
+
+defn take(x: UserId) -> Integer:
+  return Integer(x)
+
+defn main() -> Integer:
+  return take(5)",
+    );
+
+    assert_eq!(
+      compiled.diagnostics,
+      ["Expected an argument of type UserId, but found Integer"]
+    );
+    assert_eq!(compiled.value, None);
+  }
+
+  #[test]
+  fn propagates_none_and_errors() {
+    let compiled = compile(
+      "defn half(x: Integer) -> Result[Integer, Integer]:
+  if x < 0:
+    return Err(x)
+  return Ok(x / 2)
+
+defn quarter(x: Integer) -> Result[Integer, Integer]:
+  let h = half(x)?
+  return Ok(half(h)?)
+
+defn positive(x: Integer) -> Option[Integer]:
+  if x < 1:
+    return None
+  return Some(x)
+
+defn double(x: Integer) -> Option[Integer]:
+  let y = positive(x)?
+  return Some(y * 2)
+
+defn main() -> Integer:
+  let a = quarter(40).unwrap_or(0) + quarter(0 - 3).unwrap_or(100)
+  return a + double(3).unwrap_or(0) + double(0).unwrap_or(1000)",
+    );
+
+    assert!(compiled.diagnostics.is_empty());
+    assert_eq!(compiled.value, Some(Value::Integer(1116)));
+  }
+
+  #[test]
+  fn calls_extern_functions() {
+    let compiled = compile(
+      "include \"math.h\"
+include \"stdlib.h\"
+
+extern defn sqrt(x: Floating) -> Floating
+extern defn llabs(x: Integer) -> Integer
+
+defn hyp(a: Floating, b: Floating) -> Floating:
+  return sqrt(a * a + b * b)
+
+defn main() -> Integer:
+  return Integer(hyp(3.0, 4.0)) + llabs(0 - 7)",
+    );
+
+    assert!(compiled.diagnostics.is_empty());
+    assert_eq!(compiled.value, Some(Value::Integer(12)));
+
+    // the extern functions are only declared, and defined by the headers
+    let (source, _) = compiled.files.unwrap();
+    assert!(source.starts_with("#include \"math.h\"\n#include \"stdlib.h\"\n"));
+    assert!(source.contains("extern double sqrt(double PARAM0);"));
+    assert!(!source.contains("sqrt(double PARAM0) {"));
+  }
+
+  #[test]
+  fn declares_exported_functions_in_a_header() {
+    let compiled = compile(
+      "export defn inc(x: Integer) -> Integer:
+  return x + 1
+
+defn helper(x: Integer) -> Integer:
+  return x * 2
+
+defn main() -> Integer:
+  return helper(inc(4))",
+    );
+
+    assert!(compiled.diagnostics.is_empty());
+    assert_eq!(compiled.value, Some(Value::Integer(10)));
+
+    // only the exported function is visible to C code linking against the unit
+    let (source, header) = compiled.files.unwrap();
+    assert!(source.contains("extern long long inc(long long PARAM0) {"));
+    assert!(source.contains("static long long helper(long long PARAM0) {"));
+
+    let header = header.unwrap();
+    assert!(header.contains("extern long long inc(long long PARAM0);"));
+    assert!(!header.contains("helper"));
+    assert!(!header.contains("main"));
+  }
+
+  #[test]
+  fn evaluates_constants_at_compile_time() {
+    let compiled = compile(
+      "const defn fib(n: Integer) -> Integer:
+  return if n < 2: n else: fib(n - 1) + fib(n - 2)
+
+const F: Integer = fib(12)
+
+defn main() -> Integer:
+  return F",
+    );
+
+    assert!(compiled.diagnostics.is_empty());
+    assert_eq!(compiled.value, Some(Value::Integer(144)));
+
+    // main reads the value the constant was evaluated to
+    let (source, _) = compiled.files.unwrap();
+    assert!(source.contains("TEMP0 = (long long)144;\nreturn TEMP0;"));
+
+    let compiled = compile(
+      "const A: Integer = B + 1
+const B: Integer = A
+const C: Integer = 1 / 0
+
+defn main() -> Integer:
+  return A + C",
+    );
+
+    assert_eq!(
+      compiled.diagnostics,
+      [
+        "A constant can not depend on its own value",
+        "Division by zero"
+      ]
+    );
+    assert_eq!(compiled.value, None);
+  }
+
+  #[test]
+  fn scopes_variables_to_their_block() {
+    let compiled = compile(
+      "defn spin(n: Integer) -> Integer:
+  while n <= 10:
+    do:
+      let n = 5
+      return n + 1
+  return 0
+
+defn main() -> Integer:
+  let x = 2
+  do:
+    let x = 40
+    let y = x
+  return x + spin(3)",
+    );
+
+    assert_eq!(
+      compiled.diagnostics,
+      [
+        "n shadows a variable of an enclosing block",
+        "x shadows a variable of an enclosing block"
+      ]
+    );
+    assert_eq!(compiled.value, Some(Value::Integer(8)));
+  }
+
+  #[test]
+  fn evaluates_if_expressions() {
+    let compiled = compile(
+      "defn sign(x: Integer) -> Integer:
+  return if x < 0: 0 - 1 else if x > 0: 1 else: 0
+
+defn pick(x: Integer) -> Option[Integer]:
+  let o = if x > 10:
+    let doubled = x * 2
+    Some(doubled)
+  else:
+    None
+  return o
+
+defn early(x: Integer) -> Integer:
+  let v = if x < 5:
+    return 100
+  else:
+    x * 3
+  return v
+
+defn main() -> Integer:
+  let a = sign(0 - 7) + sign(9) * 10 + sign(0) * 100
+  let b = pick(20).unwrap_or(0) + pick(3).unwrap_or(7)
+  return a + b + early(1) + early(10)",
+    );
+
+    assert!(compiled.diagnostics.is_empty());
+    assert_eq!(compiled.value, Some(Value::Integer(186)));
+  }
+
+  #[test]
+  fn documents_and_deprecates_functions() {
+    let compiled = compile(
+      "## the area of a square
+## with sides of length x
+@inline
+@export
+defn area(x: Integer) -> Integer:
+  return x * x
+
+@deprecated(\"use area instead\")
+defn old_area(x: Integer) -> Integer:
+  return area(x)
+
+defn main() -> Integer:
+  return old_area(3)",
+    );
+
+    assert_eq!(
+      compiled.diagnostics,
+      ["old_area is deprecated: use area instead"]
+    );
+    assert_eq!(compiled.value, Some(Value::Integer(9)));
+
+    // the docs are carried over to the declaration in the header
+    let (_, header) = compiled.files.unwrap();
+    assert!(header.unwrap().contains(
+      "// the area of a square
+// with sides of length x
+extern long long area(long long PARAM0);"
+    ));
+  }
 }
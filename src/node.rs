@@ -22,15 +22,59 @@ pub enum NodeData {
   Multiply(Binary),
   Divide(Binary),
 
+  // comparisons, resulting in a Boolean
+  LessThan(Binary),
+  LessEqual(Binary),
+  GreaterThan(Binary),
+  GreaterEqual(Binary),
+
+  Integer(i64),
   Floating(f64),
 
   Identifier(Span),
 
+  // a list of values, e.g. (1, 2.0)
+  Tuple(Vec<NodeIdx>),
+
+  Call(Call),
+
+  // a call to a trait method, e.g. x.len()
+  MethodCall(MethodCall),
+
   FunctionDef(FunctionDef),
+
+  TraitDef(TraitDef),
+  Implements(Implements),
+
+  // alias <name> = <type> or newtype <name> = <type>
+  TypeDef(TypeDef),
+
+  ConstDef(ConstDef),
+
+  // extern defn <name>(<params>) -> <type>,
+  // a function defined outside of yacwir
+  Extern(MethodSig),
+
   Block(Vec<NodeIdx>),
 
+  // bind a value to a pattern
+  Let(Let),
+
+  // loop over an integer range
+  For(For),
+
+  // if <cond>: <block> [else: <block>]
+  If(If),
+
+  // loop as long as a condition holds
+  While(While),
+
   // return a value
   Return(NodeIdx),
+
+  // <expr>?, returns early from the function
+  // if an Option or Result holds None or an Err
+  Try(NodeIdx),
 }
 
 #[derive(Debug)]
@@ -39,14 +83,184 @@ pub struct Binary {
   pub right: NodeIdx,
 }
 
+#[derive(Debug)]
+pub struct Call {
+  // name of the called function
+  pub name: Span,
+
+  pub args: Vec<Argument>,
+}
+
+// an argument passed to a call,
+// either by position or by the name of the parameter, e.g. port=80
+#[derive(Debug)]
+pub struct Argument {
+  pub name: Option<Span>,
+  pub value: NodeIdx,
+}
+
+#[derive(Debug)]
+pub struct MethodCall {
+  // the value the method is called on
+  pub receiver: NodeIdx,
+
+  pub method: Span,
+
+  pub args: Vec<NodeIdx>,
+}
+
+// the left hand side of a let binding
+#[derive(Debug)]
+pub enum Pattern {
+  Name(Span),
+
+  // destructures a tuple, e.g. let (q, r) = ...
+  Tuple(Vec<Pattern>),
+}
+
+#[derive(Debug)]
+pub struct Let {
+  pub pattern: Pattern,
+  pub value: NodeIdx,
+}
+
+// if <cond>:
+//   <block>
+// else:
+//   <block>
+#[derive(Debug)]
+pub struct If {
+  pub cond: NodeIdx,
+  pub then: NodeIdx,
+
+  // a block, or another If for an else if chain
+  pub otherwise: Option<NodeIdx>,
+}
+
+// while <cond>:
+//   <block>
+#[derive(Debug)]
+pub struct While {
+  pub cond: NodeIdx,
+  pub body: NodeIdx,
+}
+
+// for <var> in <start>..<end> step <step>:
+//   <body>
+#[derive(Debug)]
+pub struct For {
+  // name of the induction variable
+  pub var: Span,
+
+  pub start: NodeIdx,
+  pub end: NodeIdx,
+
+  // true for ranges that include their end, e.g. 0..=n
+  pub inclusive: bool,
+
+  // defaults to 1 when not specified
+  pub step: Option<NodeIdx>,
+
+  // index to a block of nodes
+  pub body: NodeIdx,
+}
+
 #[derive(Debug)]
 pub struct FunctionDef {
   pub name: Span,
 
+  // type parameters, e.g. T in defn max[T](...)
+  pub type_params: Vec<TypeParam>,
+
+  pub params: ParameterDeclList,
+
   pub return_type: Type,
 
   // index to a block of nodes
   pub exec: NodeIdx,
+
+  // exported functions are visible to C code linking against the unit
+  pub exported: bool,
+
+  // const functions can be evaluated at compile time
+  pub is_const: bool,
+
+  // the ## comments before the function, one span per line
+  pub docs: Vec<Span>,
+
+  // the attributes written before the function, e.g. @inline
+  pub attributes: Vec<Attribute>,
+}
+
+// @<name>[(<arguments>)], written on the lines before a declaration
+#[derive(Debug, Clone)]
+pub enum Attribute {
+  // a hint to inline the function into its callers
+  Inline,
+
+  // same as export defn
+  Export,
+
+  // calls to the function are warned about,
+  // with an optional message without the quotes,
+  // e.g. @deprecated("use area instead")
+  Deprecated(Option<Span>),
+}
+
+// const <name>: <type> = <expr>,
+// a value computed at compile time
+#[derive(Debug)]
+pub struct ConstDef {
+  pub name: Span,
+  pub ty: Type,
+  pub value: NodeIdx,
+}
+
+#[derive(Debug)]
+pub struct TypeParam {
+  pub name: Span,
+
+  // names of the traits the type has to implement,
+  // e.g. [T: Len + Shape]
+  pub bounds: Vec<Span>,
+}
+
+// the signature of a method declared by a trait
+#[derive(Debug)]
+pub struct MethodSig {
+  pub name: Span,
+  pub params: ParameterDeclList,
+  pub return_type: Type,
+}
+
+// trait <name>:
+//   defn <method>(self, ...) -> <type>
+#[derive(Debug)]
+pub struct TraitDef {
+  pub name: Span,
+  pub methods: Vec<MethodSig>,
+}
+
+// implements <trait> for <type>:
+//   defn <method>(self, ...) -> <type>:
+//     <block>
+#[derive(Debug)]
+pub struct Implements {
+  pub trait_name: Span,
+  pub ty: Type,
+
+  // indices to FunctionDef nodes
+  pub methods: Vec<NodeIdx>,
+}
+
+#[derive(Debug)]
+pub struct TypeDef {
+  pub name: Span,
+  pub ty: Type,
+
+  // a newtype is distinct from the type it wraps,
+  // while an alias is just another name for it
+  pub distinct: bool,
 }
 
 #[derive(Debug)]
@@ -56,9 +270,35 @@ pub enum Type {
   Integer,
   Floating,
   Moot,
+
+  // e.g. (Integer, Floating)
+  Tuple(Vec<Type>),
+
+  // Option[T]
+  Option(Box<Type>),
+
+  // Result[T, E]
+  Result(Box<Type>, Box<Type>),
+
+  // a type referred to by name, e.g. a type parameter
+  Named(Span),
+
+  // the type of the receiver of a method,
+  // e.g. the type of an untyped self parameter
+  Receiver,
+}
+
+#[derive(Debug)]
+pub struct Parameter {
+  pub name: Span,
+  pub ty: Type,
+
+  // the value passed when a call leaves out the parameter,
+  // e.g. 80 in port: Integer = 80
+  pub default: Option<NodeIdx>,
 }
 
-pub type ParameterDeclList<'a> = Vec<(&'a str, Option<Type>)>;
+pub type ParameterDeclList = Vec<Parameter>;
 
 // impl Node {
 //   fn display(&self, ctx: &CompilerContext, indentation: usize, buffer: &mut String) {
@@ -1,93 +1,171 @@
 use crate::{
   context::CompilerContext,
-  ir::{Instruction, InstructionValue, IrBlock, IrFunction, IrUnit},
+  ir::{
+    builder::IrBuilder,
+    thir::{self, Type},
+    InstructionValue, IrFunction, IrUnit,
+  },
 };
 
 use super::OptimizerPass;
 
 pub struct Pass<'a> {
   ctx: &'a CompilerContext,
-  unit: IrUnit,
+  unit: thir::Unit,
 }
 
 impl<'a> OptimizerPass<'a> for Pass<'a> {
-  fn transform(ctx: &'a CompilerContext, unit: IrUnit) -> IrUnit {
+  fn transform(ctx: &'a CompilerContext, unit: thir::Unit) -> thir::Unit {
     Self { ctx, unit }.inner_transform()
   }
 }
 
 impl<'a> Pass<'a> {
   // constants that are optimized away are still left in
-  // the irblock, as they will be removed in dead code analysis
-  fn transform_block(&self, from_block: &IrBlock) -> IrBlock {
-    let mut to_block: Vec<Instruction> = vec![];
+  // the body, as they will be removed in dead code analysis
+  fn transform_body(&self, from_body: &thir::Body) -> thir::Body {
+    let mut to_body = IrBuilder::new();
 
-    for instr in from_block.0.iter() {
+    for instr in from_body.instrs.iter() {
       match &instr.val {
         bin @ InstructionValue::Add(left, right)
         | bin @ InstructionValue::Subtract(left, right)
         | bin @ InstructionValue::Multiply(left, right)
         | bin @ InstructionValue::Divide(left, right) => {
-          // we need to read from to_block,
+          // we need to read from to_body,
           // as we are depending on previous optimizations
           // e.g. nested binary operations that can reduce to a constant
-          let lval = to_block.get(*left).unwrap();
-          let rval = to_block.get(*right).unwrap();
+          let lval = &to_body[*left];
+          let rval = &to_body[*right];
 
           match (&lval.val, &rval.val) {
-            (InstructionValue::ConstInteger(li), InstructionValue::ConstInteger(ri)) => to_block
-              .push(Instruction {
-                tok: instr.tok,
-                ty: instr.ty,
-                val: InstructionValue::ConstInteger(match bin {
-                  InstructionValue::Add(..) => li + ri,
-                  InstructionValue::Subtract(..) => li - ri,
-                  InstructionValue::Multiply(..) => li * ri,
-                  InstructionValue::Divide(..) => li / ri,
-                  _ => unreachable!(),
-                }),
-              }),
+            (InstructionValue::ConstInteger(li), InstructionValue::ConstInteger(ri)) => {
+              let val = match bin {
+                InstructionValue::Add(..) => li.checked_add(*ri),
+                InstructionValue::Subtract(..) => li.checked_sub(*ri),
+                InstructionValue::Multiply(..) => li.checked_mul(*ri),
+                InstructionValue::Divide(..) => li.checked_div(*ri),
+                _ => unreachable!(),
+              };
+
+              // an overflow or a division by zero is left for the program to run into
+              match val {
+                Some(val) => to_body.build(
+                  InstructionValue::ConstInteger(val),
+                  Type::Integer,
+                  instr.tok,
+                ),
+                None => to_body.build(instr.val.clone(), instr.ty.clone(), instr.tok),
+              };
+            }
 
             (InstructionValue::ConstFloat(li), InstructionValue::ConstFloat(ri)) => {
-              to_block.push(Instruction {
-                tok: instr.tok,
-                ty: instr.ty,
-                val: InstructionValue::ConstFloat(match bin {
-                  InstructionValue::Add(..) => li + ri,
-                  InstructionValue::Subtract(..) => li - ri,
-                  InstructionValue::Multiply(..) => li * ri,
-                  InstructionValue::Divide(..) => li / ri,
-                  _ => unreachable!(),
-                }),
-              })
+              let val = match bin {
+                InstructionValue::Add(..) => li + ri,
+                InstructionValue::Subtract(..) => li - ri,
+                InstructionValue::Multiply(..) => li * ri,
+                InstructionValue::Divide(..) => li / ri,
+                _ => unreachable!(),
+              };
+
+              to_body.build(InstructionValue::ConstFloat(val), Type::Floating, instr.tok);
             }
 
-            // if neither are constants, we cant do anything, just return
-            _ => break,
+            // if either is not a constant, we cant do anything,
+            // the instruction is kept as it is
+            _ => {
+              to_body.build(instr.val.clone(), instr.ty.clone(), instr.tok);
+            }
           }
         }
 
-        _ => to_block.push(instr.clone()),
+        _ => {
+          to_body.build(instr.val.clone(), instr.ty.clone(), instr.tok);
+        }
       }
     }
 
-    IrBlock(to_block)
+    to_body.finish()
   }
 
-  fn transform_function(&self, func: &IrFunction) -> IrFunction {
+  fn transform_function(&self, func: &thir::Function) -> thir::Function {
     IrFunction {
       name: func.name.to_owned(),
-      instrs: self.transform_block(&func.instrs),
+      params: func.params.clone(),
+      return_type: func.return_type.clone(),
+      type_params: func.type_params.clone(),
+      type_args: func.type_args.clone(),
+      method_of: func.method_of,
+      linkage: func.linkage,
+      is_const: func.is_const,
+      inline: func.inline,
+      docs: func.docs.clone(),
+      body: self.transform_body(&func.body),
     }
   }
 
-  fn inner_transform(self) -> IrUnit {
+  fn inner_transform(self) -> thir::Unit {
     let mut funcs = vec![];
 
     for func in self.unit.funcs.iter() {
       funcs.push(self.transform_function(func));
     }
 
-    IrUnit { funcs }
+    IrUnit { funcs, ..self.unit }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{
+    context::CompilerContextBuilder,
+    ir::text::{self, FunctionText},
+    optimizers::OptimizerPass,
+    sema::SemaContext,
+  };
+
+  #[test]
+  fn folds_constants_and_keeps_the_rest() {
+    let ctx = CompilerContextBuilder::new()
+      .filedata(
+        "fn f(Integer) -> Integer:
+  bb0:
+    %0 = Param(0) : Integer
+    %1 = ConstInteger(6) : Integer
+    %2 = ConstInteger(0) : Integer
+    %3 = ConstInteger(9223372036854775807) : Integer
+    %4 = Multiply(%1, %1) : Integer
+    %5 = Add(%4, %0) : Integer
+    %6 = Subtract(%4, %1) : Integer
+    %7 = Divide(%1, %2) : Integer
+    %8 = Add(%3, %1) : Integer
+    %9 = Add(%5, %6) : Integer
+    %10 = Return(%9) : Integer
+"
+        .into(),
+      )
+      .take();
+
+    let unit = SemaContext::lower(&ctx, text::parse(&ctx).unwrap()).unwrap();
+    let unit = super::Pass::transform(&ctx, unit);
+
+    // the division by zero and the overflow are left for the program to run into
+    assert_eq!(
+      FunctionText(&ctx, &unit.funcs[0]).to_string(),
+      "fn f(Integer) -> Integer:
+  bb0:
+    %0 = Param(0) : Integer
+    %1 = ConstInteger(6) : Integer
+    %2 = ConstInteger(0) : Integer
+    %3 = ConstInteger(9223372036854775807) : Integer
+    %4 = ConstInteger(36) : Integer
+    %5 = Add(%4, %0) : Integer
+    %6 = ConstInteger(30) : Integer
+    %7 = Divide(%1, %2) : Integer
+    %8 = Add(%3, %1) : Integer
+    %9 = Add(%5, %6) : Integer
+    %10 = Return(%9) : Integer
+"
+    );
   }
 }
@@ -1,18 +1,230 @@
-use crate::{context::CompilerContext, ir::IrUnit};
+use crate::{
+  context::CompilerContext,
+  ir::{
+    builder::IrBuilder,
+    cfg::Cfg,
+    thir::{self, Type},
+    InstructionValue, IrBody, IrConst, IrFunction, IrUnit,
+  },
+};
 
 use super::OptimizerPass;
 
 // performs dead code analysis on an entire unit,
 pub struct Pass<'a> {
   ctx: &'a CompilerContext,
-  unit: IrUnit,
+  unit: thir::Unit,
 }
 
-impl<'a> Pass<'a> {}
+impl<'a> Pass<'a> {
+  // instructions that are live regardless of their value being used,
+  // the terminators keep their blocks alive
+  fn is_root(val: &InstructionValue<Type>) -> bool {
+    val.is_terminator()
+      || matches!(
+        val,
+        InstructionValue::Store(..)
+          // calls may have side effects
+          | InstructionValue::Call { .. }
+          | InstructionValue::MethodCall { .. }
+      )
+  }
+
+  // dead instructions are replaced with a Nop,
+  // the holes are removed by the defrag pass afterwards,
+  // along with the blocks that can not be reached
+  fn transform_body(&self, body: &thir::Body) -> thir::Body {
+    let instrs = &body.instrs;
+
+    let cfg = Cfg::new(body);
+    let reachable = cfg.reachable();
+    let is_reachable = |idx| reachable[cfg.block_of(idx)];
+
+    // a phi can only be written by the operands that are ever executed
+    let vals: Vec<InstructionValue<Type>> = instrs
+      .iter()
+      .map(|instr| match instr.val {
+        InstructionValue::Phi(ref vals) => {
+          InstructionValue::Phi(vals.iter().copied().filter(|x| is_reachable(*x)).collect())
+        }
+
+        ref val => val.clone(),
+      })
+      .collect();
+
+    // the live-set, built backwards from the roots
+    // through the values they reference
+    let mut live = vec![false; instrs.len()];
+    let mut worklist: Vec<usize> = (0..instrs.len())
+      .filter(|x| is_reachable(*x) && Self::is_root(&vals[*x]))
+      .collect();
+
+    while let Some(idx) = worklist.pop() {
+      if live[idx] {
+        continue;
+      }
+
+      live[idx] = true;
+      worklist.extend(vals[idx].operands().into_iter().filter(|x| !live[*x]));
+    }
+
+    let mut builder = IrBuilder::new();
+
+    for ((instr, val), live) in instrs.iter().zip(vals).zip(live) {
+      match live {
+        true => builder.build(val, instr.ty.clone(), instr.tok),
+        false => builder.build(InstructionValue::Nop, Type::Moot, instr.tok),
+      };
+    }
+
+    // the instructions are replaced one for one, so the blocks stay where they were,
+    // including the unreachable ones that lost their terminator
+    IrBody {
+      blocks: body.blocks.clone(),
+      ..builder.finish()
+    }
+  }
+
+  fn transform_function(&self, func: &thir::Function) -> thir::Function {
+    IrFunction {
+      body: self.transform_body(&func.body),
+      ..func.clone()
+    }
+  }
+
+  fn inner_transform(self) -> thir::Unit {
+    let funcs = self
+      .unit
+      .funcs
+      .iter()
+      .map(|x| self.transform_function(x))
+      .collect();
+
+    let consts = self
+      .unit
+      .consts
+      .iter()
+      .map(|x| IrConst {
+        init: self.transform_function(&x.init),
+        ..x.clone()
+      })
+      .collect();
+
+    IrUnit {
+      funcs,
+      consts,
+      ..self.unit
+    }
+  }
+}
 
 impl<'a> OptimizerPass<'a> for Pass<'a> {
-  fn transform(_ctx: &'a CompilerContext, _unit: IrUnit) -> IrUnit {
-    unimplemented!()
+  fn transform(ctx: &'a CompilerContext, unit: thir::Unit) -> thir::Unit {
+    Self { ctx, unit }.inner_transform()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{
+    context::CompilerContextBuilder,
+    ir::{
+      interpreter::{Interpreter, Value},
+      text::{self, FunctionText, UnitText},
+      verify::{verify, Stage},
+    },
+    optimizers::{defrag, OptimizerPass},
+    sema::SemaContext,
+  };
+
+  #[test]
+  fn removes_dead_code() {
+    let ctx = CompilerContextBuilder::new()
+      .filedata(include_str!("../../tests/ir/dead_code.ir").into())
+      .take();
+
+    let unit = SemaContext::lower(&ctx, text::parse(&ctx).unwrap()).unwrap();
+    let unit = defrag::Pass::transform(&ctx, super::Pass::transform(&ctx, unit));
+
+    assert_eq!(
+      UnitText(&ctx, &unit).to_string(),
+      include_str!("../../tests/ir/dead_code.expected.ir")
+    );
+  }
+
+  #[test]
+  fn keeps_stores_and_loops() {
+    // counts down to 0, storing the counter on every iteration
+    let ctx = CompilerContextBuilder::new()
+      .filedata(
+        "fn f(Integer) -> Moot:
+  bb0:
+    %0 = Param(0) : Integer
+    %1 = Alloca(Integer) : Pointer[Integer]
+    %2 = ConstInteger(1) : Integer
+    %3 = Jump(bb1) : Moot
+  bb1:
+    %4 = Phi(%0, %8) : Integer
+    %5 = Store(%1, %4) : Moot
+    %6 = Multiply(%4, %4) : Integer
+    %7 = LessThan(%2, %4) : Boolean
+    %8 = Subtract(%4, %2) : Integer
+    %9 = Branch(%7, bb1, bb2) : Moot
+  bb2:
+    %10 = Exit : Moot
+"
+        .into(),
+      )
+      .take();
+
+    let unit = SemaContext::lower(&ctx, text::parse(&ctx).unwrap()).unwrap();
+    let unit = defrag::Pass::transform(&ctx, super::Pass::transform(&ctx, unit));
+
+    assert_eq!(verify(&ctx, &unit, Stage::Typed), Ok(()));
+    assert_eq!(
+      FunctionText(&ctx, &unit.funcs[0]).to_string(),
+      "fn f(Integer) -> Moot:
+  bb0:
+    %0 = Param(0) : Integer
+    %1 = Alloca(Integer) : Pointer[Integer]
+    %2 = ConstInteger(1) : Integer
+    %3 = Jump(bb1) : Moot
+  bb1:
+    %4 = Phi(%0, %7) : Integer
+    %5 = Store(%1, %4) : Moot
+    %6 = LessThan(%2, %4) : Boolean
+    %7 = Subtract(%4, %2) : Integer
+    %8 = Branch(%6, bb1, bb2) : Moot
+  bb2:
+    %9 = Exit : Moot
+"
+    );
+  }
+
+  #[test]
+  fn keeps_the_results() {
+    let ctx = CompilerContextBuilder::new()
+      .filedata(include_str!("../../tests/ir/dead_code.ir").into())
+      .take();
+
+    let lower = || SemaContext::lower(&ctx, text::parse(&ctx).unwrap()).unwrap();
+
+    let before = lower();
+    let after = defrag::Pass::transform(&ctx, super::Pass::transform(&ctx, lower()));
+
+    let runs = [
+      (0, vec![Value::Integer(0)], Value::Integer(1)),
+      (0, vec![Value::Integer(4)], Value::Integer(5)),
+      (1, vec![], Value::Integer(2)),
+      (2, vec![], Value::Integer(4)),
+    ];
+
+    for unit in [&before, &after] {
+      for (func, params, expected) in runs.iter() {
+        let value = Interpreter::new(&ctx, unit).call(&unit.funcs[*func], params.clone());
+        assert_eq!(value, Ok(expected.clone()));
+      }
+    }
   }
 }
 
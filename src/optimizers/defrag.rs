@@ -1,22 +1,56 @@
 // defragmenter
+//
+// removes the Nop instructions that earlier passes left in place
+// of the instructions they removed, so every function is flat again
 
-use crate::{context::CompilerContext, ir::IrUnit};
+use crate::{
+  context::CompilerContext,
+  ir::{flatten, thir, IrConst, IrFunction, IrUnit},
+};
 
 use super::OptimizerPass;
 
-struct Pass<'a> {
+pub struct Pass<'a> {
   ctx: &'a CompilerContext,
-  unit: IrUnit,
+  unit: thir::Unit,
 }
 
 impl<'a> OptimizerPass<'a> for Pass<'a> {
-  fn transform(ctx: &'a CompilerContext, unit: IrUnit) -> IrUnit {
+  fn transform(ctx: &'a CompilerContext, unit: thir::Unit) -> thir::Unit {
     Self { ctx, unit }.defrag()
   }
 }
 
 impl<'a> Pass<'a> {
-  fn defrag(self) -> IrUnit {
-    unimplemented!()
+  fn defrag_function(func: thir::Function) -> thir::Function {
+    IrFunction {
+      body: flatten(func.body),
+      ..func
+    }
+  }
+
+  fn defrag(self) -> thir::Unit {
+    let funcs = self
+      .unit
+      .funcs
+      .into_iter()
+      .map(Self::defrag_function)
+      .collect();
+
+    let consts = self
+      .unit
+      .consts
+      .into_iter()
+      .map(|x| IrConst {
+        init: Self::defrag_function(x.init),
+        ..x
+      })
+      .collect();
+
+    IrUnit {
+      funcs,
+      consts,
+      ..self.unit
+    }
   }
 }
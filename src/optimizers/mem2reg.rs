@@ -0,0 +1,200 @@
+// promotes stack slots back to SSA values
+//
+// a slot is promoted when it is only ever loaded from and stored to,
+// i.e. its address is never passed on, stored or used to address an element,
+// every store then becomes an assignment of the variable and every load a read,
+// see ssa::construct for where the phis are placed
+//
+//   %0 = Alloca(Integer) : Pointer[Integer]      %0 = Nop : Moot
+//   %1 = ConstInteger(1) : Integer               %1 = ConstInteger(1) : Integer
+//   %2 = Store(%0, %1) : Moot              ->    %2 = Assign(%1) : Integer
+//   %3 = Load(%0) : Integer                      %3 = Return(%2) : Integer
+//   %4 = Return(%3) : Integer
+//
+// the removed slot is left behind as a Nop for the defrag pass,
+// a slot that may be read before it is written is kept in memory
+//
+// the frontend keeps its variables as SSA values and never emits
+// Alloca, Load or Store, so only textual IR gives this pass anything to do
+
+use crate::{
+  context::CompilerContext,
+  ir::{
+    builder::IrBuilder,
+    ssa::{self, Variable},
+    thir::{self, Type},
+    InstrIdx, InstructionValue, IrConst, IrFunction, IrUnit,
+  },
+};
+
+use super::OptimizerPass;
+
+pub struct Pass<'a> {
+  ctx: &'a CompilerContext,
+  unit: thir::Unit,
+}
+
+impl<'a> Pass<'a> {
+  // the slot reserved by @alloca as a variable,
+  // None if its address escapes
+  fn variable(body: &thir::Body, alloca: InstrIdx, ty: &Type) -> Option<Variable<Type>> {
+    let mut var = Variable {
+      ty: ty.clone(),
+      defs: vec![],
+      uses: vec![],
+    };
+
+    for (idx, instr) in body.instrs.iter().enumerate() {
+      if !instr.val.operands().contains(&alloca) {
+        continue;
+      }
+
+      match instr.val {
+        InstructionValue::Load(_) => var.uses.push(idx),
+        InstructionValue::Store(_, val) if val != alloca => var.defs.push((idx, val)),
+        _ => return None,
+      }
+    }
+
+    Some(var)
+  }
+
+  // promotes the first slot that can be promoted, None if there is none
+  fn promote_one(body: &thir::Body) -> Option<thir::Body> {
+    for (idx, instr) in body.instrs.iter().enumerate() {
+      let InstructionValue::Alloca(ref ty) = instr.val else {
+        continue;
+      };
+
+      let Some(var) = Self::variable(body, idx, ty) else {
+        continue;
+      };
+
+      // the slot itself is left behind as a Nop
+      let mut builder = IrBuilder::new();
+      for (n, instr) in body.instrs.iter().enumerate() {
+        match n == idx {
+          true => builder.build(InstructionValue::Nop, Type::Moot, instr.tok),
+          false => builder.build(instr.val.clone(), instr.ty.clone(), instr.tok),
+        };
+      }
+
+      if let Ok(body) = ssa::construct(&builder.finish(), &[var]) {
+        return Some(body);
+      }
+    }
+
+    None
+  }
+
+  // the indices shift with every promotion,
+  // so the slots are looked for again after each one
+  fn transform_body(&self, body: &thir::Body) -> thir::Body {
+    let mut body = body.clone();
+
+    while let Some(promoted) = Self::promote_one(&body) {
+      body = promoted;
+    }
+
+    body
+  }
+
+  fn transform_function(&self, func: &thir::Function) -> thir::Function {
+    IrFunction {
+      body: self.transform_body(&func.body),
+      ..func.clone()
+    }
+  }
+
+  fn inner_transform(self) -> thir::Unit {
+    let funcs = self
+      .unit
+      .funcs
+      .iter()
+      .map(|x| self.transform_function(x))
+      .collect();
+
+    let consts = self
+      .unit
+      .consts
+      .iter()
+      .map(|x| IrConst {
+        init: self.transform_function(&x.init),
+        ..x.clone()
+      })
+      .collect();
+
+    IrUnit {
+      funcs,
+      consts,
+      ..self.unit
+    }
+  }
+}
+
+impl<'a> OptimizerPass<'a> for Pass<'a> {
+  fn transform(ctx: &'a CompilerContext, unit: thir::Unit) -> thir::Unit {
+    Self { ctx, unit }.inner_transform()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{
+    context::CompilerContextBuilder,
+    ir::{
+      interpreter::{Interpreter, Value},
+      text::{self, UnitText},
+      verify::{verify, Stage},
+    },
+    optimizers::{defrag, OptimizerPass},
+    sema::SemaContext,
+  };
+
+  #[test]
+  fn promotes_slots_that_do_not_escape() {
+    let ctx = CompilerContextBuilder::new()
+      .filedata(include_str!("../../tests/ir/mem2reg.ir").into())
+      .take();
+
+    let unit = SemaContext::lower(&ctx, text::parse(&ctx).unwrap()).unwrap();
+    let unit = defrag::Pass::transform(&ctx, super::Pass::transform(&ctx, unit));
+
+    assert_eq!(verify(&ctx, &unit, Stage::Typed), Ok(()));
+    assert_eq!(
+      UnitText(&ctx, &unit).to_string(),
+      include_str!("../../tests/ir/mem2reg.expected.ir")
+    );
+  }
+
+  #[test]
+  fn keeps_the_results() {
+    let ctx = CompilerContextBuilder::new()
+      .filedata(include_str!("../../tests/ir/mem2reg.ir").into())
+      .take();
+
+    let lower = || SemaContext::lower(&ctx, text::parse(&ctx).unwrap()).unwrap();
+
+    let before = lower();
+    let after = super::Pass::transform(&ctx, lower());
+
+    let runs = [
+      (0, Value::Integer(4), "6"),
+      (0, Value::Integer(0), "0"),
+      (
+        1,
+        Value::Tuple(vec![Value::Integer(1), Value::Integer(2)]),
+        "(2, 1)",
+      ),
+      (2, Value::Boolean(false), "1"),
+      (2, Value::Boolean(true), "0"),
+    ];
+
+    for unit in [&before, &after] {
+      for (func, param, expected) in runs.iter() {
+        let value = Interpreter::new(&ctx, unit).call(&unit.funcs[*func], vec![param.clone()]);
+        assert_eq!(value.unwrap().to_string(), *expected);
+      }
+    }
+  }
+}
@@ -1,13 +1,25 @@
-use crate::{context::CompilerContext, ir::IrUnit};
+use crate::{
+  context::CompilerContext,
+  ir::{
+    thir,
+    verify::{self, Stage},
+  },
+};
 
 pub mod constant_folding;
 pub mod dead_code;
 pub mod defrag;
+pub mod mem2reg;
 
 /*
 
 // order of optimizations:
 
+- mem2reg
+  turns the stack slots into values first,
+    so that the other passes can see through them,
+    only textual IR has any, see mem2reg.rs
+
 - constant folding
 
 - dead code analysis
@@ -16,38 +28,107 @@ pub mod defrag;
     dead code (e.g. removing the need of two constants) and then
     leaving the work to another function
 
+- defragmenting
+  removes the holes dead code analysis leaves behind
+
 */
 
 pub trait OptimizerPass<'a> {
   // apply an optimizing transform upon unit and return it
-  fn transform(ctx: &'a CompilerContext, unit: IrUnit) -> IrUnit;
+  fn transform(ctx: &'a CompilerContext, unit: thir::Unit) -> thir::Unit;
 }
 
 // a list of different optimizer passes that can be appled to
 // the IR
 #[derive(Clone)]
 pub struct OptimizerFlags {
+  mem2reg: bool,
   const_folding: bool,
+  dead_code: bool,
 }
 
 impl Default for OptimizerFlags {
   fn default() -> Self {
     Self {
+      mem2reg: true,
       const_folding: true,
+      dead_code: true,
     }
   }
 }
 
 // eats @unit, and transforms it into a new IrUnit with optimizations
 // applied to the code
-pub fn optimize(ctx: &CompilerContext, unit: IrUnit) -> IrUnit {
+pub fn optimize(ctx: &CompilerContext, mut unit: thir::Unit) -> thir::Unit {
   let flags = ctx.get_optimizer_flags();
 
-  let unit = if flags.const_folding {
-    constant_folding::Pass::transform(ctx, unit)
-  } else {
-    unit
-  };
+  if flags.mem2reg {
+    unit = mem2reg::Pass::transform(ctx, unit);
+    verify::check(ctx, &unit, "mem2reg", Stage::Typed);
+  }
+
+  if flags.const_folding {
+    unit = constant_folding::Pass::transform(ctx, unit);
+    verify::check(ctx, &unit, "constant folding", Stage::Typed);
+  }
+
+  if flags.dead_code {
+    // the unreachable blocks are left without their terminator
+    // until the defrag pass removes them
+    unit = dead_code::Pass::transform(ctx, unit);
+    unit = defrag::Pass::transform(ctx, unit);
+    verify::check(ctx, &unit, "dead code analysis", Stage::Typed);
+  }
 
   unit
 }
+
+#[cfg(test)]
+mod tests {
+  use crate::{
+    ir::{
+      interpreter::{Interpreter, Value},
+      text::FunctionText,
+    },
+    testing,
+  };
+
+  #[test]
+  fn folds_and_removes_the_dead_code_of_a_program() {
+    let ctx = testing::context(
+      "defn main() -> Integer:
+  let unused = 6 * 7
+  let x = 2 * 3 + 4
+  if x < 5:
+    return 0
+  return x",
+    );
+
+    let unit = testing::check(&ctx, &testing::parse(&ctx)).unwrap();
+    let unit = super::optimize(&ctx, unit);
+
+    assert!(ctx.get_diagnostics().is_empty());
+    // the unused product, the folded operands
+    // and the Exit after the last return are gone
+    assert_eq!(
+      FunctionText(&ctx, &unit.funcs[0]).to_string(),
+      "fn main() -> Integer:
+  bb0:
+    %0 = ConstInteger(10) : Integer
+    %1 = Assign(%0) : Integer
+    %2 = ConstInteger(5) : Integer
+    %3 = LessThan(%1, %2) : Boolean
+    %4 = Branch(%3, bb1, bb2) : Moot
+  bb1:
+    %5 = ConstInteger(0) : Integer
+    %6 = Return(%5) : Integer
+  bb2:
+    %7 = Return(%1) : Integer
+"
+    );
+    assert_eq!(
+      Interpreter::new(&ctx, &unit).run_main(),
+      Ok(Value::Integer(10))
+    );
+  }
+}
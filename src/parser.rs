@@ -1,10 +1,16 @@
 use crate::{
   context::CompilerContext,
+  diagnostic::{Diagnostic, DiagnosticLevel},
   lexer::Lexer,
-  node::{Binary, FunctionDef, Node, NodeData, NodeIdx, ParameterDeclList, Type},
-  token::{Token, TokenType},
+  node::{
+    Argument, Attribute, Binary, Call, ConstDef, For, FunctionDef, If, Implements, Let, MethodCall,
+    MethodSig, Node, NodeData, NodeIdx, Parameter, ParameterDeclList, Pattern, TraitDef, Type,
+    TypeDef, TypeParam, While,
+  },
+  token::{Span, Token, TokenType},
 };
 
+#[derive(Default, Debug)]
 pub struct Ast {
   pub toks: Vec<Token>,
   pub nodes: Vec<Node>,
@@ -12,6 +18,24 @@ pub struct Ast {
   // list of indices into self.nodes
   // guaranteed to be FunctionDef nodes
   pub funcs: Vec<NodeIdx>,
+
+  // guaranteed to be TraitDef nodes
+  pub traits: Vec<NodeIdx>,
+
+  // guaranteed to be Implements nodes
+  pub impls: Vec<NodeIdx>,
+
+  // guaranteed to be TypeDef nodes
+  pub types: Vec<NodeIdx>,
+
+  // guaranteed to be Extern nodes
+  pub externs: Vec<NodeIdx>,
+
+  // guaranteed to be ConstDef nodes
+  pub consts: Vec<NodeIdx>,
+
+  // names of the C headers to include, without the quotes
+  pub includes: Vec<Span>,
 }
 
 pub struct Parser<'a> {
@@ -19,6 +43,12 @@ pub struct Parser<'a> {
   nodes: Vec<Node>,
   toks: Vec<Token>,
   funcs: Vec<NodeIdx>,
+  traits: Vec<NodeIdx>,
+  impls: Vec<NodeIdx>,
+  types: Vec<NodeIdx>,
+  externs: Vec<NodeIdx>,
+  consts: Vec<NodeIdx>,
+  includes: Vec<Span>,
   tokidx: usize,
 }
 
@@ -28,28 +58,54 @@ impl<'a> Parser<'a> {
       ctx,
       nodes: vec![],
       funcs: vec![],
+      traits: vec![],
+      impls: vec![],
+      types: vec![],
+      externs: vec![],
+      consts: vec![],
+      includes: vec![],
       toks: Lexer::new(ctx).lex()?,
       tokidx: 0,
     })
   }
 
-  fn expect(&mut self, expected_type: TokenType) -> Result<Token, String> {
-    // skip any comments
+  fn skip_comments(&mut self) {
     while let Some(Token {
-      ty: TokenType::Comment,
+      ty: TokenType::Comment | TokenType::DocComment,
       ..
     }) = self.current_tok()
     {
       self.tokidx += 1;
     }
+  }
+
+  // consumes the next token if it is of the type @ty
+  fn accept(&mut self, ty: TokenType) -> bool {
+    self.skip_comments();
+
+    if self.current_tok().is_some_and(|x| x.ty == ty) {
+      self.tokidx += 1;
+      true
+    } else {
+      false
+    }
+  }
+
+  fn expect(&mut self, expected_type: TokenType) -> Result<Token, String> {
+    self.skip_comments();
 
     let Some(tok) = self.current_tok() else {
-      return Err("Ran out of characters to expect".to_string())
+      return Err("Ran out of characters to expect".to_string());
     };
 
     if tok.ty == expected_type {
       self.tokidx += 1;
       Ok(tok)
+    } else if tok.ty == TokenType::Indent {
+      // only the line after a colon can open a block
+      Err(
+        "Unexpected indentation, the line is indented further than the line before it".to_string(),
+      )
     } else {
       Err(format!(
         "Expected token {}, but found {}",
@@ -60,7 +116,7 @@ impl<'a> Parser<'a> {
 
   fn next_tok(&mut self) -> Token {
     while let Token {
-      ty: TokenType::Comment,
+      ty: TokenType::Comment | TokenType::DocComment,
       ..
     } = self.toks[self.tokidx]
     {
@@ -77,10 +133,10 @@ impl<'a> Parser<'a> {
   }
 
   fn current_tok(&self) -> Option<Token> {
-    self.toks.get(self.tokidx.clone()).map(|x| x.clone())
+    self.toks.get(self.tokidx).cloned()
   }
 
-  fn parse_factor(&mut self) -> Result<NodeIdx, String> {
+  fn parse_atom(&mut self) -> Result<NodeIdx, String> {
     match self
       .current_tok()
       .ok_or("expected token while parsing factor")?
@@ -89,27 +145,83 @@ impl<'a> Parser<'a> {
         ty: TokenType::Number,
         span,
       } => {
+        let tokidx = self.tokidx;
         self.tokidx += 1;
 
-        Ok(
-          self.push_node(Node {
-            data: NodeData::Floating(
-              self
-                .ctx
-                .get_str_from_span(span)
-                .parse::<f64>()
-                .ok()
-                .ok_or("error while trying to parse a number".to_owned())?,
-            ),
-            tok: self.tokidx,
-          }),
-        )
+        let text = self.ctx.get_str_from_span(span);
+
+        // numbers without a decimal point are integers
+        let data = if text.contains('.') {
+          NodeData::Floating(
+            text
+              .parse::<f64>()
+              .ok()
+              .ok_or("error while trying to parse a number".to_owned())?,
+          )
+        } else {
+          NodeData::Integer(
+            text
+              .parse::<i64>()
+              .ok()
+              .ok_or("error while trying to parse an integer".to_owned())?,
+          )
+        };
+
+        Ok(self.push_node(Node { data, tok: tokidx }))
+      }
+
+      Token {
+        ty: TokenType::Identifier,
+        span,
+      } => {
+        let tokidx = self.tokidx;
+        self.tokidx += 1;
+
+        // an identifier followed by a paranthesis is a function call
+        if let Some(Token {
+          ty: TokenType::LeftParanthesis,
+          ..
+        }) = self.current_tok()
+        {
+          let args = self.parse_argument_list()?;
+
+          return Ok(self.push_node(Node {
+            data: NodeData::Call(Call { name: span, args }),
+            tok: tokidx,
+          }));
+        }
+
+        Ok(self.push_node(Node {
+          data: NodeData::Identifier(span),
+          tok: tokidx,
+        }))
+      }
+
+      Token {
+        ty: TokenType::If, ..
+      } => self.parse_if(),
+
+      // conversions to the builtin types, e.g. Integer(id)
+      Token {
+        ty: TokenType::Integer | TokenType::Floating,
+        span,
+      } => {
+        let tokidx = self.tokidx;
+        self.tokidx += 1;
+
+        let args = self.parse_argument_list()?;
+
+        Ok(self.push_node(Node {
+          data: NodeData::Call(Call { name: span, args }),
+          tok: tokidx,
+        }))
       }
 
       Token {
         ty: TokenType::LeftParanthesis,
         ..
       } => {
+        let tokidx = self.tokidx;
         self.tokidx += 1;
 
         if self
@@ -118,15 +230,52 @@ impl<'a> Parser<'a> {
           .ty
           == TokenType::RightParanthesis
         {
+          self.tokidx += 1;
           return Ok(self.push_node(Node {
             data: NodeData::Moot,
-            tok: self.tokidx,
+            tok: tokidx,
           }));
-        } else {
-          let out = self.parse_expr()?;
+        }
+
+        let first = self.parse_expr()?;
+
+        // a comma turns the paranthesized expression into a tuple
+        if let Some(Token {
+          ty: TokenType::Comma,
+          ..
+        }) = self.current_tok()
+        {
+          let mut values = vec![first];
+
+          while let Some(Token {
+            ty: TokenType::Comma,
+            ..
+          }) = self.current_tok()
+          {
+            self.tokidx += 1;
+
+            // allow a trailing comma, e.g. (1,)
+            if let Some(Token {
+              ty: TokenType::RightParanthesis,
+              ..
+            }) = self.current_tok()
+            {
+              break;
+            }
+
+            values.push(self.parse_expr()?);
+          }
+
           self.expect(TokenType::RightParanthesis)?;
-          Ok(out)
+
+          return Ok(self.push_node(Node {
+            data: NodeData::Tuple(values),
+            tok: tokidx,
+          }));
         }
+
+        self.expect(TokenType::RightParanthesis)?;
+        Ok(first)
       }
 
       _ => Err(format!(
@@ -136,6 +285,47 @@ impl<'a> Parser<'a> {
     }
   }
 
+  // an atom followed by any amount of method calls and ? operators,
+  // e.g. x.scale(2).area() or parse(x)?
+  fn parse_factor(&mut self) -> Result<NodeIdx, String> {
+    let mut receiver = self.parse_atom()?;
+
+    loop {
+      let tokidx = self.tokidx;
+
+      receiver = match self.current_tok().map(|x| x.ty) {
+        Some(TokenType::Dot) => {
+          self.tokidx += 1;
+
+          let method = self.expect(TokenType::Identifier)?;
+          let args = self.parse_expr_list(TokenType::RightParanthesis)?;
+
+          self.push_node(Node {
+            data: NodeData::MethodCall(MethodCall {
+              receiver,
+              method: method.span,
+              args,
+            }),
+            tok: tokidx + 1,
+          })
+        }
+
+        Some(TokenType::Question) => {
+          self.tokidx += 1;
+
+          self.push_node(Node {
+            data: NodeData::Try(receiver),
+            tok: tokidx,
+          })
+        }
+
+        _ => break,
+      };
+    }
+
+    Ok(receiver)
+  }
+
   fn parse_term(&mut self) -> Result<NodeIdx, String> {
     let mut left = self.parse_factor()?;
 
@@ -164,7 +354,7 @@ impl<'a> Parser<'a> {
     Ok(left)
   }
 
-  fn parse_expr(&mut self) -> Result<NodeIdx, String> {
+  fn parse_sum(&mut self) -> Result<NodeIdx, String> {
     let mut left = self.parse_term()?;
 
     while let Some(Token {
@@ -191,188 +381,1673 @@ impl<'a> Parser<'a> {
     Ok(left)
   }
 
-  fn parse_return(&mut self) -> Result<NodeIdx, String> {
-    let root_tokidx = self.tokidx;
+  // a comparison does not chain, e.g. a < b < c is rejected
+  fn parse_expr(&mut self) -> Result<NodeIdx, String> {
+    let left = self.parse_sum()?;
 
-    let Some(Token{ty: TokenType::Return, ..}) = self.current_tok() else {
-      return Err("Expected a return token while parsing return.".to_owned()); 
+    let Some(Token {
+      ty:
+        ty @ (TokenType::Less | TokenType::LessEqual | TokenType::Greater | TokenType::GreaterEqual),
+      ..
+    }) = self.current_tok()
+    else {
+      return Ok(left);
     };
 
+    let tokidx = self.tokidx;
     self.tokidx += 1;
 
-    let ret_val = self.parse_expr()?;
+    let right = self.parse_sum()?;
+    let binary = Binary { left, right };
+
     Ok(self.push_node(Node {
-      data: NodeData::Return(ret_val),
-      tok: root_tokidx,
+      data: match ty {
+        TokenType::Less => NodeData::LessThan(binary),
+        TokenType::LessEqual => NodeData::LessEqual(binary),
+        TokenType::Greater => NodeData::GreaterThan(binary),
+        TokenType::GreaterEqual => NodeData::GreaterEqual(binary),
+
+        _ => unreachable!(),
+      },
+      tok: tokidx,
     }))
   }
 
-  fn parse_expr_statement(&mut self) -> Result<NodeIdx, String> {
-    if let Some(Token {
-      ty: TokenType::Return,
-      ..
-    }) = self.current_tok()
+  // the paranthesized arguments of a call, each one optionally named,
+  // e.g. (1, port=80)
+  fn parse_argument_list(&mut self) -> Result<Vec<Argument>, String> {
+    _ = self.expect(TokenType::LeftParanthesis)?;
+
+    let mut out = vec![];
+
+    while self.current_tok().ok_or("Ran out of tokens in a list")?.ty != TokenType::RightParanthesis
     {
-      self.parse_return()
-    } else {
-      self.parse_expr()
+      let name = match (self.current_tok(), self.toks.get(self.tokidx + 1)) {
+        (
+          Some(Token {
+            ty: TokenType::Identifier,
+            span,
+          }),
+          Some(Token {
+            ty: TokenType::Equal,
+            ..
+          }),
+        ) => {
+          self.tokidx += 2;
+          Some(span)
+        }
+
+        _ => None,
+      };
+
+      out.push(Argument {
+        name,
+        value: self.parse_expr()?,
+      });
+
+      if self.current_tok().ok_or("Ran out of tokens in a list")?.ty != TokenType::RightParanthesis
+      {
+        _ = self.expect(TokenType::Comma)?;
+      }
     }
+
+    _ = self.expect(TokenType::RightParanthesis)?;
+
+    Ok(out)
   }
 
-  fn parse_block(&mut self) -> Result<NodeIdx, String> {
-    let root_tokidx = self.tokidx;
+  fn parse_expr_list(&mut self, terminator: TokenType) -> Result<Vec<NodeIdx>, String> {
+    _ = self.expect(TokenType::LeftParanthesis)?;
 
-    // get indentation
-    let base_indentation = self.expect(TokenType::Indentation)?;
-    let inden_len = base_indentation.span.len();
+    let mut out = vec![];
 
-    let mut toks = vec![];
+    while self.current_tok().ok_or("Ran out of tokens in a list")?.ty != terminator {
+      out.push(self.parse_expr()?);
 
-    'l: loop {
-      toks.push(self.parse_expr_statement()?);
+      if self.current_tok().ok_or("Ran out of tokens in a list")?.ty != terminator {
+        _ = self.expect(TokenType::Comma)?;
+      }
+    }
 
-      let new_ind: usize = match self.current_tok() {
-        Some(Token {
-          ty: TokenType::Indentation,
-          span,
-        }) => span.len(),
+    _ = self.expect(terminator)?;
 
-        // break out of parsing loop on EOF
-        Some(Token {
-          ty: TokenType::EOF, ..
-        }) => break 'l,
+    Ok(out)
+  }
 
-        _ => return Err("expected an indentation while parsing block".into()),
-      };
+  fn parse_pattern(&mut self) -> Result<Pattern, String> {
+    match self.next_tok() {
+      Token {
+        ty: TokenType::Identifier,
+        span,
+      } => Ok(Pattern::Name(span)),
 
-      if new_ind < inden_len {
-        break;
-      } else if new_ind > inden_len {
-        return Err("somehow tried to parse a larger indentation while parsing a block".into());
+      Token {
+        ty: TokenType::LeftParanthesis,
+        ..
+      } => {
+        let mut patterns = vec![];
+
+        while self
+          .current_tok()
+          .ok_or("Ran out of tokens in a pattern")?
+          .ty
+          != TokenType::RightParanthesis
+        {
+          patterns.push(self.parse_pattern()?);
+
+          if self
+            .current_tok()
+            .ok_or("Ran out of tokens in a pattern")?
+            .ty
+            != TokenType::RightParanthesis
+          {
+            _ = self.expect(TokenType::Comma)?;
+          }
+        }
+
+        _ = self.expect(TokenType::RightParanthesis)?;
+
+        Ok(Pattern::Tuple(patterns))
       }
 
-      self.tokidx += 1;
+      tok => Err(format!("Expected a pattern, but found {}", tok.ty)),
     }
+  }
+
+  // let <pattern> = <expr>
+  fn parse_let(&mut self) -> Result<NodeIdx, String> {
+    let root_tokidx = self.tokidx;
+
+    _ = self.expect(TokenType::Let)?;
+    let pattern = self.parse_pattern()?;
+    _ = self.expect(TokenType::Equal)?;
+    let value = self.parse_expr()?;
 
     Ok(self.push_node(Node {
-      data: NodeData::Block(toks),
+      data: NodeData::Let(Let { pattern, value }),
       tok: root_tokidx,
     }))
   }
 
-  fn parse_parameter_declaration(&mut self) -> Result<ParameterDeclList, String> {
-    _ = self.expect(TokenType::LeftParanthesis)?;
-    _ = self.expect(TokenType::RightParanthesis)?;
+  fn parse_return(&mut self) -> Result<NodeIdx, String> {
+    let root_tokidx = self.tokidx;
+
+    let Some(Token {
+      ty: TokenType::Return,
+      ..
+    }) = self.current_tok()
+    else {
+      return Err("Expected a return token while parsing return.".to_owned());
+    };
 
-    // TODO: implement function parameters
+    self.tokidx += 1;
 
-    Ok(vec![])
+    let ret_val = self.parse_expr()?;
+    Ok(self.push_node(Node {
+      data: NodeData::Return(ret_val),
+      tok: root_tokidx,
+    }))
   }
 
-  fn parse_function(&mut self) -> Result<NodeIdx, String> {
-    let tokidx = self.tokidx;
+  // for <identifier> in <expr>(..|..=)<expr> [step <expr>]:
+  //   <block>
+  fn parse_for(&mut self) -> Result<NodeIdx, String> {
+    let root_tokidx = self.tokidx;
 
-    _ = self.expect(TokenType::Defn)?;
-    let name = self.expect(TokenType::Identifier)?;
-    let _params = self.parse_parameter_declaration()?;
+    _ = self.expect(TokenType::For)?;
+    let var = self.expect(TokenType::Identifier)?;
+    _ = self.expect(TokenType::In)?;
 
-    let return_type = match self.next_tok().ty {
-      TokenType::ThinArrow => {
-        let out = match self.next_tok().ty {
-          TokenType::Integer => Type::Integer,
-          TokenType::Floating => Type::Floating,
-          TokenType::Moot => Type::Moot,
-          _ => return Err("invalid token while trying to parse a return type".to_string()),
-        };
+    let start = self.parse_expr()?;
 
-        self.expect(TokenType::Colon)?;
-        out
-      }
+    let inclusive = match self.next_tok().ty {
+      TokenType::DotDot => false,
+      TokenType::DotDotEqual => true,
+      _ => return Err("Expected a range operator (.. or ..=) in a for loop".to_string()),
+    };
 
-      TokenType::Colon => Type::Moot,
+    let end = self.parse_expr()?;
 
-      _ => {
-        return Err(
-          "Expected either a return-type-arrow or a colon after a function header".to_string(),
-        )
-      }
+    let step = if let Some(Token {
+      ty: TokenType::Step,
+      ..
+    }) = self.current_tok()
+    {
+      self.tokidx += 1;
+      Some(self.parse_expr()?)
+    } else {
+      None
     };
 
-    let exec = self.parse_block()?;
+    _ = self.expect(TokenType::Colon)?;
 
-    // if the name is "main",
-    // store into the pre-allocated 0 idx,
+    let body = self.parse_block()?;
 
-    if self.ctx.get_str_from_span(name.span) == "main" {
-      self.nodes[0] = Node {
-        data: NodeData::FunctionDef(FunctionDef {
-          name: name.span,
-          exec,
-          return_type,
-        }),
-        tok: tokidx,
-      };
-      Ok(0)
-    } else {
-      Ok(self.push_node(Node {
-        data: NodeData::FunctionDef(FunctionDef {
-          name: name.span,
-          exec,
-          return_type,
-        }),
-        tok: tokidx,
-      }))
-    }
+    Ok(self.push_node(Node {
+      data: NodeData::For(For {
+        var: var.span,
+        start,
+        end,
+        inclusive,
+        step,
+        body,
+      }),
+      tok: root_tokidx,
+    }))
   }
 
-  // top level function declarations,
-  // e.g. functions, global variables, import declarations
-  fn parse_toplevel(&mut self) -> Result<(), String> {
-    while let Token {
-      ty: TokenType::Indentation,
-      // only expect identation slices that are of indentation 0
-      span,
-    } = self.next_tok()
+  // the body of an if, either an indented block
+  // or a single statement on the same line, e.g. if x < 0: 0
+  fn parse_branch(&mut self) -> Result<NodeIdx, String> {
+    if let Some(Token {
+      ty: TokenType::Indent,
+      ..
+    }) = self.current_tok()
     {
-      if span.len() != 0 {
-        return Err(
-          "Expected an indentation of level 0 when parsing top level declarations".to_string(),
-        );
-      };
-      let func = self.parse_function()?;
-      self.funcs.push(func);
+      return self.parse_block();
     }
 
-    Ok(())
+    let tokidx = self.tokidx;
+    let statement = self.parse_expr_statement()?;
+
+    Ok(self.push_node(Node {
+      data: NodeData::Block(vec![statement]),
+      tok: tokidx,
+    }))
   }
 
-  pub fn parse(mut self) -> Result<Ast, String> {
-    // reserve some space for the main function
-    _ = self.push_node(Node {
-      data: NodeData::Add(Binary { left: 0, right: 0 }),
-      tok: 0,
-    });
+  // if <expr>:
+  //   <block>
+  // [else:
+  //   <block>]
+  //
+  // also used as an expression, whose value is the value of the branch taken
+  // e.g. let sign = if x < 0: 0 - 1 else: 1
+  fn parse_if(&mut self) -> Result<NodeIdx, String> {
+    let root_tokidx = self.tokidx;
 
-    self.parse_toplevel()?;
+    _ = self.expect(TokenType::If)?;
+    let cond = self.parse_expr()?;
+    _ = self.expect(TokenType::Colon)?;
 
-    Ok(Ast {
-      toks: self.toks,
-      nodes: self.nodes,
-      funcs: self.funcs,
-    })
+    let then = self.parse_branch()?;
+    self.skip_comments();
+
+    // the else either follows on the same line,
+    // or continues the if on a line of the same indentation
+    let has_else = match (self.current_tok(), self.toks.get(self.tokidx + 1)) {
+      (
+        Some(Token {
+          ty: TokenType::Else,
+          ..
+        }),
+        _,
+      ) => {
+        self.tokidx += 1;
+        true
+      }
+
+      (
+        Some(Token {
+          ty: TokenType::Indentation,
+          ..
+        }),
+        Some(Token {
+          ty: TokenType::Else,
+          ..
+        }),
+      ) => {
+        self.tokidx += 2;
+        true
+      }
+
+      _ => false,
+    };
+
+    let otherwise = if !has_else {
+      None
+    } else if let Some(Token {
+      ty: TokenType::If, ..
+    }) = self.current_tok()
+    {
+      // else if <expr>:
+      Some(self.parse_if()?)
+    } else {
+      _ = self.expect(TokenType::Colon)?;
+      Some(self.parse_branch()?)
+    };
+
+    Ok(self.push_node(Node {
+      data: NodeData::If(If {
+        cond,
+        then,
+        otherwise,
+      }),
+      tok: root_tokidx,
+    }))
   }
-}
 
-// impl<'a> Ast<'a> {
-//   fn inner_display(&self, indentation: usize, out: &mut String) {
-//     match self {
+  // while <expr>:
+  //   <block>
+  fn parse_while(&mut self) -> Result<NodeIdx, String> {
+    let root_tokidx = self.tokidx;
 
-//     }
-//   }
-// }
+    _ = self.expect(TokenType::While)?;
+    let cond = self.parse_expr()?;
+    _ = self.expect(TokenType::Colon)?;
 
-// impl<'a> Display for Ast<'a> {
-//   fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-//     let out = m
-//   }
-// }
+    let body = self.parse_block()?;
+
+    Ok(self.push_node(Node {
+      data: NodeData::While(While { cond, body }),
+      tok: root_tokidx,
+    }))
+  }
+
+  // do:
+  //   <block>
+  // a block of its own, only used to scope variables
+  fn parse_do(&mut self) -> Result<NodeIdx, String> {
+    _ = self.expect(TokenType::Do)?;
+    _ = self.expect(TokenType::Colon)?;
+
+    self.parse_block()
+  }
+
+  fn parse_expr_statement(&mut self) -> Result<NodeIdx, String> {
+    match self.current_tok() {
+      Some(Token {
+        ty: TokenType::Return,
+        ..
+      }) => self.parse_return(),
+
+      Some(Token {
+        ty: TokenType::For, ..
+      }) => self.parse_for(),
+
+      Some(Token {
+        ty: TokenType::Let, ..
+      }) => self.parse_let(),
+
+      Some(Token {
+        ty: TokenType::If, ..
+      }) => self.parse_if(),
+
+      Some(Token {
+        ty: TokenType::While,
+        ..
+      }) => self.parse_while(),
+
+      Some(Token {
+        ty: TokenType::Do, ..
+      }) => self.parse_do(),
+
+      _ => self.parse_expr(),
+    }
+  }
+
+  // <indent> (<indentation> <statement>)+ <dedent>
+  fn parse_block(&mut self) -> Result<NodeIdx, String> {
+    let root_tokidx = self.tokidx;
+
+    if !self.accept(TokenType::Indent) {
+      return Err("expected an indented block".into());
+    }
+
+    let mut toks = vec![];
+
+    loop {
+      _ = self.expect(TokenType::Indentation)?;
+      toks.push(self.parse_expr_statement()?);
+
+      if self.accept(TokenType::Dedent) {
+        break;
+      }
+    }
+
+    Ok(self.push_node(Node {
+      data: NodeData::Block(toks),
+      tok: root_tokidx,
+    }))
+  }
+
+  // [<type>, ...]
+  fn parse_type_arguments(&mut self) -> Result<Vec<Type>, String> {
+    _ = self.expect(TokenType::LeftBracket)?;
+
+    let mut types = vec![];
+
+    while self.current_tok().ok_or("Ran out of tokens in a type")?.ty != TokenType::RightBracket {
+      types.push(self.parse_type()?);
+
+      if self.current_tok().ok_or("Ran out of tokens in a type")?.ty != TokenType::RightBracket {
+        _ = self.expect(TokenType::Comma)?;
+      }
+    }
+
+    _ = self.expect(TokenType::RightBracket)?;
+
+    Ok(types)
+  }
+
+  fn parse_type(&mut self) -> Result<Type, String> {
+    match self.next_tok().ty {
+      TokenType::Integer => Ok(Type::Integer),
+      TokenType::Floating => Ok(Type::Floating),
+      TokenType::Moot => Ok(Type::Moot),
+
+      TokenType::Identifier => {
+        let span = self.toks[self.tokidx - 1].span;
+
+        if self.current_tok().map(|x| x.ty) != Some(TokenType::LeftBracket) {
+          return Ok(Type::Named(span));
+        }
+
+        // the builtin generic types, e.g. Result[Integer, Floating]
+        let mut args = self.parse_type_arguments()?;
+
+        match (self.ctx.get_str_from_span(span), args.len()) {
+          ("Option", 1) => Ok(Type::Option(Box::new(args.remove(0)))),
+
+          ("Result", 2) => {
+            let err = args.remove(1);
+            Ok(Type::Result(Box::new(args.remove(0)), Box::new(err)))
+          }
+
+          (name, n) => Err(format!(
+            "Unknown generic type {} with {} type arguments",
+            name, n
+          )),
+        }
+      }
+
+      TokenType::LeftParanthesis => {
+        let mut types = vec![];
+
+        while self.current_tok().ok_or("Ran out of tokens in a type")?.ty
+          != TokenType::RightParanthesis
+        {
+          types.push(self.parse_type()?);
+
+          if self.current_tok().ok_or("Ran out of tokens in a type")?.ty
+            != TokenType::RightParanthesis
+          {
+            _ = self.expect(TokenType::Comma)?;
+          }
+        }
+
+        _ = self.expect(TokenType::RightParanthesis)?;
+
+        // () is equivalent to Moot
+        if types.is_empty() {
+          Ok(Type::Moot)
+        } else {
+          Ok(Type::Tuple(types))
+        }
+      }
+
+      ty => Err(format!("Expected a type, but found {}", ty)),
+    }
+  }
+
+  // [<name>: <trait> + <trait>, ...]
+  fn parse_type_parameters(&mut self) -> Result<Vec<TypeParam>, String> {
+    let mut params = vec![];
+
+    let Some(Token {
+      ty: TokenType::LeftBracket,
+      ..
+    }) = self.current_tok()
+    else {
+      return Ok(params);
+    };
+    self.tokidx += 1;
+
+    while self
+      .current_tok()
+      .ok_or("Ran out of tokens in a type parameter list")?
+      .ty
+      != TokenType::RightBracket
+    {
+      let name = self.expect(TokenType::Identifier)?.span;
+      let mut bounds = vec![];
+
+      if let Some(Token {
+        ty: TokenType::Colon,
+        ..
+      }) = self.current_tok()
+      {
+        self.tokidx += 1;
+        bounds.push(self.expect(TokenType::Identifier)?.span);
+
+        while let Some(Token {
+          ty: TokenType::Plus,
+          ..
+        }) = self.current_tok()
+        {
+          self.tokidx += 1;
+          bounds.push(self.expect(TokenType::Identifier)?.span);
+        }
+      }
+
+      params.push(TypeParam { name, bounds });
+
+      if self
+        .current_tok()
+        .ok_or("Ran out of tokens in a type parameter list")?
+        .ty
+        != TokenType::RightBracket
+      {
+        _ = self.expect(TokenType::Comma)?;
+      }
+    }
+
+    _ = self.expect(TokenType::RightBracket)?;
+
+    Ok(params)
+  }
+
+  // (<name>: <type>, ...)
+  fn parse_parameter_declaration(&mut self) -> Result<ParameterDeclList, String> {
+    _ = self.expect(TokenType::LeftParanthesis)?;
+
+    let mut params = vec![];
+
+    while self
+      .current_tok()
+      .ok_or("Ran out of tokens in a parameter list")?
+      .ty
+      != TokenType::RightParanthesis
+    {
+      let name = self.expect(TokenType::Identifier)?;
+
+      // the receiver of a method may leave out its type
+      let ty = if self.ctx.get_str_from_span(name.span) == "self"
+        && !matches!(
+          self.current_tok(),
+          Some(Token {
+            ty: TokenType::Colon,
+            ..
+          })
+        ) {
+        Type::Receiver
+      } else {
+        _ = self.expect(TokenType::Colon)?;
+        self.parse_type()?
+      };
+
+      let default = match self.current_tok() {
+        Some(Token {
+          ty: TokenType::Equal,
+          ..
+        }) => {
+          self.tokidx += 1;
+          Some(self.parse_expr()?)
+        }
+
+        _ => None,
+      };
+
+      params.push(Parameter {
+        name: name.span,
+        ty,
+        default,
+      });
+
+      if self
+        .current_tok()
+        .ok_or("Ran out of tokens in a parameter list")?
+        .ty
+        != TokenType::RightParanthesis
+      {
+        _ = self.expect(TokenType::Comma)?;
+      }
+    }
+
+    _ = self.expect(TokenType::RightParanthesis)?;
+
+    Ok(params)
+  }
+
+  fn parse_function(&mut self) -> Result<NodeIdx, String> {
+    let tokidx = self.tokidx;
+
+    _ = self.expect(TokenType::Defn)?;
+    let name = self.expect(TokenType::Identifier)?;
+    let type_params = self.parse_type_parameters()?;
+    let params = self.parse_parameter_declaration()?;
+
+    let return_type = match self.next_tok().ty {
+      TokenType::ThinArrow => {
+        let out = self.parse_type()?;
+
+        self.expect(TokenType::Colon)?;
+        out
+      }
+
+      TokenType::Colon => Type::Moot,
+
+      _ => {
+        return Err(
+          "Expected either a return-type-arrow or a colon after a function header".to_string(),
+        )
+      }
+    };
+
+    let exec = self.parse_block()?;
+
+    Ok(self.push_node(Node {
+      data: NodeData::FunctionDef(FunctionDef {
+        name: name.span,
+        type_params,
+        params,
+        exec,
+        return_type,
+        exported: false,
+        is_const: false,
+        docs: vec![],
+        attributes: vec![],
+      }),
+      tok: tokidx,
+    }))
+  }
+
+  // parses an indented list of declarations,
+  // e.g. the methods within a trait
+  fn parse_declaration_block<T>(
+    &mut self,
+    mut parse_declaration: impl FnMut(&mut Self) -> Result<T, String>,
+  ) -> Result<Vec<T>, String> {
+    if !self.accept(TokenType::Indent) {
+      return Err("expected an indented block".into());
+    }
+
+    let mut out = vec![];
+
+    loop {
+      _ = self.expect(TokenType::Indentation)?;
+      out.push(parse_declaration(self)?);
+
+      if self.accept(TokenType::Dedent) {
+        break;
+      }
+    }
+
+    Ok(out)
+  }
+
+  // defn <name>(<params>) [-> <type>]
+  fn parse_method_signature(&mut self) -> Result<MethodSig, String> {
+    _ = self.expect(TokenType::Defn)?;
+    let name = self.expect(TokenType::Identifier)?;
+    let params = self.parse_parameter_declaration()?;
+
+    let return_type = if let Some(Token {
+      ty: TokenType::ThinArrow,
+      ..
+    }) = self.current_tok()
+    {
+      self.tokidx += 1;
+      self.parse_type()?
+    } else {
+      Type::Moot
+    };
+
+    Ok(MethodSig {
+      name: name.span,
+      params,
+      return_type,
+    })
+  }
+
+  // const <name>: <type> = <expr>
+  // the value is computed at compile time, see sema::const_eval
+  fn parse_const(&mut self) -> Result<NodeIdx, String> {
+    let tokidx = self.tokidx;
+
+    _ = self.expect(TokenType::Const)?;
+    let name = self.expect(TokenType::Identifier)?;
+    _ = self.expect(TokenType::Colon)?;
+    let ty = self.parse_type()?;
+    _ = self.expect(TokenType::Equal)?;
+    let value = self.parse_expr()?;
+
+    Ok(self.push_node(Node {
+      data: NodeData::ConstDef(ConstDef {
+        name: name.span,
+        ty,
+        value,
+      }),
+      tok: tokidx,
+    }))
+  }
+
+  // extern defn <name>(<params>) -> <type>
+  fn parse_extern(&mut self) -> Result<NodeIdx, String> {
+    let tokidx = self.tokidx;
+
+    _ = self.expect(TokenType::Extern)?;
+    let sig = self.parse_method_signature()?;
+
+    Ok(self.push_node(Node {
+      data: NodeData::Extern(sig),
+      tok: tokidx,
+    }))
+  }
+
+  // alias <name> = <type>
+  // newtype <name> = <type>
+  fn parse_type_definition(&mut self) -> Result<NodeIdx, String> {
+    let tokidx = self.tokidx;
+
+    let distinct = self.next_tok().ty == TokenType::Newtype;
+    let name = self.expect(TokenType::Identifier)?;
+    _ = self.expect(TokenType::Equal)?;
+    let ty = self.parse_type()?;
+
+    Ok(self.push_node(Node {
+      data: NodeData::TypeDef(TypeDef {
+        name: name.span,
+        ty,
+        distinct,
+      }),
+      tok: tokidx,
+    }))
+  }
+
+  // trait <name>:
+  //   <method signatures>
+  fn parse_trait(&mut self) -> Result<NodeIdx, String> {
+    let tokidx = self.tokidx;
+
+    _ = self.expect(TokenType::Trait)?;
+    let name = self.expect(TokenType::Identifier)?;
+    _ = self.expect(TokenType::Colon)?;
+
+    let methods = self.parse_declaration_block(Self::parse_method_signature)?;
+
+    Ok(self.push_node(Node {
+      data: NodeData::TraitDef(TraitDef {
+        name: name.span,
+        methods,
+      }),
+      tok: tokidx,
+    }))
+  }
+
+  // implements <trait> for <type>:
+  //   <method definitions>
+  fn parse_implements(&mut self) -> Result<NodeIdx, String> {
+    let tokidx = self.tokidx;
+
+    _ = self.expect(TokenType::Implements)?;
+    let trait_name = self.expect(TokenType::Identifier)?;
+    _ = self.expect(TokenType::For)?;
+    let ty = self.parse_type()?;
+    _ = self.expect(TokenType::Colon)?;
+
+    let methods = self.parse_declaration_block(Self::parse_function)?;
+
+    Ok(self.push_node(Node {
+      data: NodeData::Implements(Implements {
+        trait_name: trait_name.span,
+        ty,
+        methods,
+      }),
+      tok: tokidx,
+    }))
+  }
+
+  // the doc comments directly before the token at @idx,
+  // only separated from it by other comments and dedents
+  fn doc_comments_before(&self, idx: usize) -> Vec<Span> {
+    let mut docs = vec![];
+
+    for tok in self.toks[..idx].iter().rev() {
+      match tok.ty {
+        TokenType::DocComment => docs.push(tok.span),
+        TokenType::Comment | TokenType::Dedent => (),
+        _ => break,
+      }
+    }
+
+    docs.reverse();
+    docs
+  }
+
+  // @<name>[("<message>")]
+  fn parse_attribute(&mut self) -> Result<Attribute, String> {
+    _ = self.expect(TokenType::At)?;
+
+    // export is a keyword as well
+    let name = match self.next_tok() {
+      Token {
+        ty: TokenType::Identifier | TokenType::Export,
+        span,
+      } => span,
+
+      tok => return Err(format!("Expected an attribute name, but found {}", tok.ty)),
+    };
+
+    match self.ctx.get_str_from_span(name) {
+      "inline" => Ok(Attribute::Inline),
+      "export" => Ok(Attribute::Export),
+
+      "deprecated" => {
+        if !self.accept(TokenType::LeftParanthesis) {
+          return Ok(Attribute::Deprecated(None));
+        }
+
+        let message = self.expect(TokenType::String)?.span;
+        _ = self.expect(TokenType::RightParanthesis)?;
+
+        Ok(Attribute::Deprecated(Some(Span {
+          start: message.start + 1,
+          end: message.end - 1,
+        })))
+      }
+
+      name => Err(format!("Unknown attribute @{}", name)),
+    }
+  }
+
+  // whether the current line declares a function,
+  // e.g. export defn or const defn
+  fn at_function(&self) -> bool {
+    match self.current_tok().map(|x| x.ty) {
+      Some(TokenType::Defn | TokenType::Export) => true,
+
+      Some(TokenType::Const) => matches!(
+        self.toks.get(self.tokidx + 1),
+        Some(Token {
+          ty: TokenType::Defn,
+          ..
+        })
+      ),
+
+      _ => false,
+    }
+  }
+
+  // registers a function declared at the top level,
+  // @func has to be the last node that was pushed
+  fn push_function(
+    &mut self,
+    func: NodeIdx,
+    docs: Vec<Span>,
+    attributes: Vec<Attribute>,
+  ) -> Result<(), String> {
+    let NodeData::FunctionDef(def) = &mut self.nodes[func].data else {
+      unreachable!()
+    };
+
+    def.exported |= attributes.iter().any(|x| matches!(x, Attribute::Export));
+
+    if def.exported && !def.type_params.is_empty() {
+      return Err("Exported functions can not have type parameters".to_string());
+    }
+
+    def.docs = docs;
+    def.attributes = attributes;
+
+    // the entry point is stored into the pre-allocated 0 idx,
+    // a method named main is just a method
+    let func = if self.ctx.get_str_from_span(def.name) == "main" {
+      if !def.type_params.is_empty() {
+        return Err("The main function can not have type parameters".to_string());
+      }
+
+      debug_assert_eq!(func, self.nodes.len() - 1);
+      self.nodes[0] = self.nodes.pop().unwrap();
+      0
+    } else {
+      func
+    };
+
+    self.funcs.push(func);
+    Ok(())
+  }
+
+  // top level function declarations,
+  // e.g. functions, global variables, import declarations
+  fn parse_toplevel(&mut self) -> Result<(), String> {
+    // the doc comments and attributes of the next declaration
+    let mut docs = vec![];
+    let mut attributes = vec![];
+
+    loop {
+      // every declaration starts on a line of its own, without indentation
+      match self.next_tok().ty {
+        TokenType::Indentation => (),
+
+        // the last declaration may not be left out after its doc comments or attributes
+        TokenType::Eof if !attributes.is_empty() => {
+          return Err("Attributes can only be applied to functions".to_string())
+        }
+
+        TokenType::Eof
+          if !docs.is_empty() || !self.doc_comments_before(self.tokidx - 1).is_empty() =>
+        {
+          return Err("Doc comment does not document anything".to_string())
+        }
+
+        TokenType::Eof => break,
+
+        TokenType::Indent => {
+          return Err(
+            "Expected an indentation of level 0 when parsing top level declarations".to_string(),
+          )
+        }
+
+        ty => return Err(format!("Expected the end of the line, but found {}", ty)),
+      }
+
+      docs.extend(self.doc_comments_before(self.tokidx - 1));
+
+      // every attribute is written on a line of its own
+      if let Some(Token {
+        ty: TokenType::At, ..
+      }) = self.current_tok()
+      {
+        attributes.push(self.parse_attribute()?);
+        continue;
+      }
+
+      if !attributes.is_empty() && !self.at_function() {
+        return Err("Attributes can only be applied to functions".to_string());
+      }
+
+      // only functions carry their documentation along
+      if !docs.is_empty() && !self.at_function() {
+        return Err("Doc comment does not document anything".to_string());
+      }
+
+      let docs = std::mem::take(&mut docs);
+      let attributes = std::mem::take(&mut attributes);
+
+      match self.current_tok().map(|x| x.ty) {
+        Some(TokenType::Trait) => {
+          let def = self.parse_trait()?;
+          self.traits.push(def);
+        }
+
+        Some(TokenType::Implements) => {
+          let def = self.parse_implements()?;
+          self.impls.push(def);
+        }
+
+        Some(TokenType::Alias | TokenType::Newtype) => {
+          let def = self.parse_type_definition()?;
+          self.types.push(def);
+        }
+
+        // export defn <name>(...)
+        Some(TokenType::Export) => {
+          self.tokidx += 1;
+
+          let func = self.parse_function()?;
+          let NodeData::FunctionDef(def) = &mut self.nodes[func].data else {
+            unreachable!()
+          };
+
+          def.exported = true;
+          self.push_function(func, docs, attributes)?;
+        }
+
+        // const defn <name>(...)
+        Some(TokenType::Const)
+          if matches!(
+            self.toks.get(self.tokidx + 1),
+            Some(Token {
+              ty: TokenType::Defn,
+              ..
+            })
+          ) =>
+        {
+          self.tokidx += 1;
+
+          let func = self.parse_function()?;
+          let NodeData::FunctionDef(def) = &mut self.nodes[func].data else {
+            unreachable!()
+          };
+
+          def.is_const = true;
+          self.push_function(func, docs, attributes)?;
+        }
+
+        Some(TokenType::Const) => {
+          let def = self.parse_const()?;
+          self.consts.push(def);
+        }
+
+        Some(TokenType::Extern) => {
+          let def = self.parse_extern()?;
+          self.externs.push(def);
+        }
+
+        // include "<header>"
+        Some(TokenType::Include) => {
+          self.tokidx += 1;
+
+          let header = self.expect(TokenType::String)?.span;
+          self.includes.push(Span {
+            start: header.start + 1,
+            end: header.end - 1,
+          });
+        }
+
+        _ => {
+          let func = self.parse_function()?;
+          self.push_function(func, docs, attributes)?;
+        }
+      }
+    }
+
+    Ok(())
+  }
+
+  pub fn parse(mut self) -> Result<Ast, String> {
+    // reserve some space for the main function
+    _ = self.push_node(Node {
+      data: NodeData::Add(Binary { left: 0, right: 0 }),
+      tok: 0,
+    });
+
+    // point the error at the token the parser stopped at
+    if let Err(info) = self.parse_toplevel() {
+      let diagnostic = Diagnostic {
+        tokidx: self.tokidx.min(self.toks.len() - 1),
+        level: DiagnosticLevel::Error,
+        info,
+      };
+
+      return Err(diagnostic.display(self.ctx, &self.toks));
+    }
+
+    Ok(Ast {
+      toks: self.toks,
+      nodes: self.nodes,
+      funcs: self.funcs,
+      traits: self.traits,
+      impls: self.impls,
+      types: self.types,
+      externs: self.externs,
+      consts: self.consts,
+      includes: self.includes,
+    })
+  }
+}
+
+// impl<'a> Ast<'a> {
+//   fn inner_display(&self, indentation: usize, out: &mut String) {
+//     match self {
+
+//     }
+//   }
+// }
+
+// impl<'a> Display for Ast<'a> {
+//   fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+//     let out = m
+//   }
+// }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::testing::context;
+
+  // the data of every node @f picks out, in the order the nodes were pushed
+  fn nodes<'a, T>(ast: &'a Ast, f: impl Fn(&'a NodeData) -> Option<&'a T>) -> Vec<&'a T> {
+    ast.nodes.iter().filter_map(|x| f(&x.data)).collect()
+  }
+
+  #[test]
+  fn rejects_unexpected_indentation() {
+    let ctx = context(
+      "defn main() -> Integer:
+  let x = 1
+    let y = 2
+  return x",
+    );
+
+    let error = Parser::new(&ctx).unwrap().parse().unwrap_err();
+
+    assert_eq!(
+      error,
+      "3:        l
+Error: | Unexpected indentation, the line is indented further than the line before it"
+    );
+  }
+
+  #[test]
+  fn only_registers_a_top_level_main() {
+    let ctx = context(
+      "trait Runner:
+  defn main(self) -> Integer
+
+implements Runner for Integer:
+  defn main(self) -> Integer:
+    return self + 1
+
+defn main() -> Integer:
+  return 4.main()",
+    );
+
+    let ast = Parser::new(&ctx).unwrap().parse().unwrap();
+
+    let NodeData::FunctionDef(main) = &ast.nodes[0].data else {
+      panic!("node 0 is not a function")
+    };
+    assert!(main.params.is_empty());
+    assert_eq!(ast.funcs, vec![0]);
+
+    let NodeData::Implements(implements) = &ast.nodes[ast.impls[0]].data else {
+      panic!("expected an implementation")
+    };
+    let NodeData::FunctionDef(method) = &ast.nodes[implements.methods[0]].data else {
+      panic!("expected a method")
+    };
+    assert_eq!(ctx.get_str_from_span(method.name), "main");
+    assert_eq!(method.params.len(), 1);
+  }
+
+  #[test]
+  fn parses_range_loops() {
+    let ctx = context(
+      "defn main() -> Integer:
+  for i in 1..=9 step 2:
+    for j in 0..i:
+      return j
+  return 0",
+    );
+
+    let ast = Parser::new(&ctx).unwrap().parse().unwrap();
+
+    let loops = nodes(&ast, |x| match x {
+      NodeData::For(x) => Some(x),
+      _ => None,
+    });
+
+    // the body of a loop is parsed before the loop is pushed
+    let [inner, outer] = loops[..] else {
+      panic!("expected two loops, found {}", loops.len())
+    };
+
+    assert_eq!(ctx.get_str_from_span(outer.var), "i");
+    assert!(outer.inclusive);
+    assert!(matches!(
+      ast.nodes[outer.step.unwrap()].data,
+      NodeData::Integer(2)
+    ));
+
+    assert_eq!(ctx.get_str_from_span(inner.var), "j");
+    assert!(!inner.inclusive);
+    assert!(inner.step.is_none());
+    assert!(matches!(ast.nodes[inner.end].data, NodeData::Identifier(_)));
+  }
+
+  #[test]
+  fn parses_tuples_and_tuple_patterns() {
+    let ctx = context(
+      "defn divmod(a: Integer, b: Integer) -> (Integer, Integer):
+  return (a / b, a - a / b * b)
+
+defn main() -> Integer:
+  let ((q, r), c) = (divmod(7, 2), 3)
+  return q",
+    );
+
+    let ast = Parser::new(&ctx).unwrap().parse().unwrap();
+
+    let NodeData::FunctionDef(divmod) = &ast.nodes[ast.funcs[0]].data else {
+      panic!("expected a function")
+    };
+    assert_eq!(ctx.get_str_from_span(divmod.name), "divmod");
+    assert!(matches!(
+      divmod.return_type,
+      Type::Tuple(ref x) if matches!(x[..], [Type::Integer, Type::Integer])
+    ));
+
+    let tuples = nodes(&ast, |x| match x {
+      NodeData::Tuple(x) => Some(x),
+      _ => None,
+    });
+    assert_eq!(tuples.iter().map(|x| x.len()).collect::<Vec<_>>(), [2, 2]);
+
+    let lets = nodes(&ast, |x| match x {
+      NodeData::Let(x) => Some(x),
+      _ => None,
+    });
+    let Pattern::Tuple(ref outer) = lets[0].pattern else {
+      panic!("expected a tuple pattern")
+    };
+    assert!(matches!(outer[..], [Pattern::Tuple(ref x), Pattern::Name(_)] if x.len() == 2));
+  }
+
+  #[test]
+  fn parses_type_parameters() {
+    let ctx = context(
+      "defn pick[T, U: Show + Size](a: T, b: U) -> T:
+  return a
+
+defn main() -> Integer:
+  return pick(1, 2.0)",
+    );
+
+    let ast = Parser::new(&ctx).unwrap().parse().unwrap();
+
+    let NodeData::FunctionDef(pick) = &ast.nodes[ast.funcs[0]].data else {
+      panic!("expected a function")
+    };
+
+    let params: Vec<(&str, Vec<&str>)> = pick
+      .type_params
+      .iter()
+      .map(|x| {
+        let bounds = x.bounds.iter().map(|x| ctx.get_str_from_span(*x)).collect();
+        (ctx.get_str_from_span(x.name), bounds)
+      })
+      .collect();
+
+    assert_eq!(params, [("T", vec![]), ("U", vec!["Show", "Size"])]);
+    assert!(matches!(pick.params[0].ty, Type::Named(x) if ctx.get_str_from_span(x) == "T"));
+    assert!(matches!(pick.return_type, Type::Named(x) if ctx.get_str_from_span(x) == "T"));
+  }
+
+  #[test]
+  fn parses_traits_and_implementations() {
+    let ctx = context(
+      "trait Shape:
+  defn area(self) -> Integer
+  defn scale(self, k: Integer) -> Self
+
+implements Shape for (Integer, Integer):
+  defn area(self) -> Integer:
+    let (w, h) = self
+    return w * h
+  defn scale(self, k: Integer) -> Self:
+    let (w, h) = self
+    return (w * k, h * k)
+
+defn main() -> Integer:
+  return (2, 3).scale(2).area()",
+    );
+
+    let ast = Parser::new(&ctx).unwrap().parse().unwrap();
+
+    let NodeData::TraitDef(shape) = &ast.nodes[ast.traits[0]].data else {
+      panic!("expected a trait")
+    };
+    let methods: Vec<&str> = shape
+      .methods
+      .iter()
+      .map(|x| ctx.get_str_from_span(x.name))
+      .collect();
+    assert_eq!(methods, ["area", "scale"]);
+    assert!(matches!(shape.methods[0].params[0].ty, Type::Receiver));
+    assert!(
+      matches!(shape.methods[1].return_type, Type::Named(x) if ctx.get_str_from_span(x) == "Self")
+    );
+
+    let NodeData::Implements(implements) = &ast.nodes[ast.impls[0]].data else {
+      panic!("expected an implementation")
+    };
+    assert_eq!(ctx.get_str_from_span(implements.trait_name), "Shape");
+    assert!(matches!(implements.ty, Type::Tuple(ref x) if x.len() == 2));
+    assert_eq!(implements.methods.len(), 2);
+
+    let calls = nodes(&ast, |x| match x {
+      NodeData::MethodCall(x) => Some(x),
+      _ => None,
+    });
+    let methods: Vec<&str> = calls
+      .iter()
+      .map(|x| ctx.get_str_from_span(x.method))
+      .collect();
+    assert_eq!(methods, ["scale", "area"]);
+  }
+
+  #[test]
+  fn parses_aliases_and_newtypes() {
+    let ctx = context(
+      "alias Meters = Floating
+newtype UserId = Integer
+
+defn main() -> Integer:
+  return Integer(UserId(4))",
+    );
+
+    let ast = Parser::new(&ctx).unwrap().parse().unwrap();
+
+    let defs: Vec<(&str, bool)> = ast
+      .types
+      .iter()
+      .map(|x| match &ast.nodes[*x].data {
+        NodeData::TypeDef(def) => (ctx.get_str_from_span(def.name), def.distinct),
+        _ => panic!("expected a type definition"),
+      })
+      .collect();
+
+    assert_eq!(defs, [("Meters", false), ("UserId", true)]);
+  }
+
+  #[test]
+  fn parses_optional_types_and_the_try_operator() {
+    let ctx = context(
+      "defn half(x: Option[Integer]) -> Result[Integer, Floating]:
+  let y = x?
+  return Ok(y / 2)
+
+defn main() -> Integer:
+  return half(Some(4)).unwrap_or(0)",
+    );
+
+    let ast = Parser::new(&ctx).unwrap().parse().unwrap();
+
+    let NodeData::FunctionDef(half) = &ast.nodes[ast.funcs[0]].data else {
+      panic!("expected a function")
+    };
+    assert!(matches!(half.params[0].ty, Type::Option(ref x) if matches!(**x, Type::Integer)));
+    assert!(matches!(
+      half.return_type,
+      Type::Result(ref ok, ref err) if matches!((&**ok, &**err), (Type::Integer, Type::Floating))
+    ));
+
+    let tries = nodes(&ast, |x| match x {
+      NodeData::Try(x) => Some(x),
+      _ => None,
+    });
+    assert!(matches!(tries[..], [x] if matches!(ast.nodes[*x].data, NodeData::Identifier(_))));
+  }
+
+  #[test]
+  fn parses_includes_and_extern_functions() {
+    let ctx = context(
+      "include \"math.h\"
+
+extern defn sqrt(x: Floating) -> Floating
+
+defn main() -> Integer:
+  return Integer(sqrt(16.0))",
+    );
+
+    let ast = Parser::new(&ctx).unwrap().parse().unwrap();
+
+    let includes: Vec<&str> = ast
+      .includes
+      .iter()
+      .map(|x| ctx.get_str_from_span(*x))
+      .collect();
+    assert_eq!(includes, ["math.h"]);
+
+    let NodeData::Extern(sqrt) = &ast.nodes[ast.externs[0]].data else {
+      panic!("expected an extern function")
+    };
+    assert_eq!(ctx.get_str_from_span(sqrt.name), "sqrt");
+    assert!(matches!(
+      sqrt.params[..],
+      [Parameter {
+        ty: Type::Floating,
+        ..
+      }]
+    ));
+    assert!(matches!(sqrt.return_type, Type::Floating));
+  }
+
+  #[test]
+  fn parses_exported_functions() {
+    let ctx = context(
+      "export defn inc(x: Integer) -> Integer:
+  return x + 1
+
+defn main() -> Integer:
+  return inc(4)",
+    );
+
+    let ast = Parser::new(&ctx).unwrap().parse().unwrap();
+
+    let exported: Vec<(&str, bool)> = ast
+      .funcs
+      .iter()
+      .map(|x| match &ast.nodes[*x].data {
+        NodeData::FunctionDef(def) => (ctx.get_str_from_span(def.name), def.exported),
+        _ => panic!("expected a function"),
+      })
+      .collect();
+
+    assert_eq!(exported, [("inc", true), ("main", false)]);
+  }
+
+  #[test]
+  fn parses_defaults_and_named_arguments() {
+    let ctx = context(
+      "defn connect(host: Integer, port: Integer = 80) -> Integer:
+  return host + port
+
+defn main() -> Integer:
+  return connect(1, port=8080)",
+    );
+
+    let ast = Parser::new(&ctx).unwrap().parse().unwrap();
+
+    let NodeData::FunctionDef(connect) = &ast.nodes[ast.funcs[0]].data else {
+      panic!("expected a function")
+    };
+    assert!(connect.params[0].default.is_none());
+    assert!(matches!(
+      ast.nodes[connect.params[1].default.unwrap()].data,
+      NodeData::Integer(80)
+    ));
+
+    let calls = nodes(&ast, |x| match x {
+      NodeData::Call(x) => Some(x),
+      _ => None,
+    });
+    let names: Vec<Option<&str>> = calls[0]
+      .args
+      .iter()
+      .map(|x| x.name.map(|x| ctx.get_str_from_span(x)))
+      .collect();
+    assert_eq!(names, [None, Some("port")]);
+    assert!(matches!(
+      ast.nodes[calls[0].args[1].value].data,
+      NodeData::Integer(8080)
+    ));
+  }
+
+  #[test]
+  fn parses_const_functions_and_constants() {
+    let ctx = context(
+      "const defn square(x: Integer) -> Integer:
+  return x * x
+
+const BASE: Integer = square(4) + 1
+
+defn main() -> Integer:
+  return BASE",
+    );
+
+    let ast = Parser::new(&ctx).unwrap().parse().unwrap();
+
+    let NodeData::FunctionDef(square) = &ast.nodes[ast.funcs[0]].data else {
+      panic!("expected a function")
+    };
+    assert!(square.is_const);
+
+    let NodeData::ConstDef(base) = &ast.nodes[ast.consts[0]].data else {
+      panic!("expected a constant")
+    };
+    assert_eq!(ctx.get_str_from_span(base.name), "BASE");
+    assert!(matches!(base.ty, Type::Integer));
+    assert!(matches!(ast.nodes[base.value].data, NodeData::Add(_)));
+  }
+
+  #[test]
+  fn parses_expressions_across_lines() {
+    let ctx = context(
+      "defn add3(a: Integer,
+          b: Integer,
+  c: Integer) -> Integer:
+  return a + b \\
+    + c
+
+defn main() -> Integer:
+  return add3(1,
+    # the second one
+    2,
+
+    3)",
+    );
+
+    let ast = Parser::new(&ctx).unwrap().parse().unwrap();
+
+    let NodeData::FunctionDef(add3) = &ast.nodes[ast.funcs[0]].data else {
+      panic!("expected a function")
+    };
+    assert_eq!(add3.params.len(), 3);
+
+    // the continued line still belongs to the return
+    let NodeData::Block(body) = &ast.nodes[add3.exec].data else {
+      panic!("expected a block")
+    };
+    let [ret] = body[..] else {
+      panic!("expected a single statement, found {}", body.len())
+    };
+    let NodeData::Return(sum) = ast.nodes[ret].data else {
+      panic!("expected a return")
+    };
+    assert!(matches!(ast.nodes[sum].data, NodeData::Add(_)));
+
+    let calls = nodes(&ast, |x| match x {
+      NodeData::Call(x) => Some(x),
+      _ => None,
+    });
+    assert_eq!(calls[0].args.len(), 3);
+  }
+
+  #[test]
+  fn parses_blocks_indented_with_tabs() {
+    let ctx = context(
+      "defn main() -> Integer:
+\tfor i in 0..3:
+\t\tfor j in 0..3:
+\t\t\t# comment
+\t\t\tlet u = j
+\t\tlet v = i
+\treturn 7",
+    );
+
+    let ast = Parser::new(&ctx).unwrap().parse().unwrap();
+
+    // let v is back in the body of the outer loop after the inner one ends
+    let loops = nodes(&ast, |x| match x {
+      NodeData::For(x) => Some(x),
+      _ => None,
+    });
+    let [inner, outer] = loops[..] else {
+      panic!("expected two loops, found {}", loops.len())
+    };
+
+    let NodeData::Block(body) = &ast.nodes[outer.body].data else {
+      panic!("expected a block")
+    };
+    assert!(matches!(ast.nodes[body[0]].data, NodeData::For(_)));
+    assert!(matches!(ast.nodes[body[1]].data, NodeData::Let(_)));
+    assert_eq!(body.len(), 2);
+
+    let NodeData::Block(body) = &ast.nodes[inner.body].data else {
+      panic!("expected a block")
+    };
+    assert_eq!(body.len(), 1);
+
+    let mixed = context("defn main() -> Integer:\n\tlet t = 0\n  \treturn t");
+    let error = Parser::new(&mixed).and_then(|x| x.parse()).unwrap_err();
+    assert!(
+      error.ends_with("Error: | Indentation mixes tabs and spaces, tabs are counted as 4 columns")
+    );
+  }
+
+  #[test]
+  fn parses_nested_blocks() {
+    let ctx = context(
+      "defn main() -> Integer:
+  let x = 2
+  do:
+    let x = 40
+    do:
+      let y = x
+  return x",
+    );
+
+    let ast = Parser::new(&ctx).unwrap().parse().unwrap();
+
+    let NodeData::FunctionDef(main) = &ast.nodes[ast.funcs[0]].data else {
+      panic!("expected a function")
+    };
+    let NodeData::Block(body) = &ast.nodes[main.exec].data else {
+      panic!("expected a block")
+    };
+    assert_eq!(body.len(), 3);
+
+    let NodeData::Block(outer) = &ast.nodes[body[1]].data else {
+      panic!("expected a nested block")
+    };
+    assert!(matches!(ast.nodes[outer[0]].data, NodeData::Let(_)));
+    assert!(matches!(ast.nodes[outer[1]].data, NodeData::Block(ref x) if x.len() == 1));
+  }
+
+  #[test]
+  fn parses_if_expressions() {
+    let ctx = context(
+      "defn main() -> Integer:
+  let x = 3
+  return if x < 0: 0 - 1 else if x > 0: 1 else: 0",
+    );
+
+    let ast = Parser::new(&ctx).unwrap().parse().unwrap();
+
+    let ifs = nodes(&ast, |x| match x {
+      NodeData::If(x) => Some(x),
+      _ => None,
+    });
+
+    // the else if is parsed before the if it belongs to
+    let [inner, outer] = ifs[..] else {
+      panic!("expected two ifs, found {}", ifs.len())
+    };
+
+    let otherwise = outer.otherwise.unwrap();
+    assert!(matches!(ast.nodes[otherwise].data, NodeData::If(ref x) if std::ptr::eq(x, inner)));
+    assert!(matches!(ast.nodes[outer.cond].data, NodeData::LessThan(_)));
+    assert!(matches!(
+      ast.nodes[inner.cond].data,
+      NodeData::GreaterThan(_)
+    ));
+    assert!(inner.otherwise.is_some());
+
+    let returns = nodes(&ast, |x| match x {
+      NodeData::Return(x) => Some(x),
+      _ => None,
+    });
+    assert!(matches!(ast.nodes[*returns[0]].data, NodeData::If(_)));
+  }
+
+  #[test]
+  fn parses_doc_comments_and_attributes() {
+    let ctx = context(
+      "## the area of a square
+## with sides of length x
+@inline
+@deprecated(\"use area instead\")
+defn old_area(x: Integer) -> Integer:
+  return x * x
+
+@export
+defn main() -> Integer:
+  return old_area(3)",
+    );
+
+    let ast = Parser::new(&ctx).unwrap().parse().unwrap();
+
+    let NodeData::FunctionDef(old_area) = &ast.nodes[ast.funcs[0]].data else {
+      panic!("expected a function")
+    };
+
+    let docs: Vec<&str> = old_area
+      .docs
+      .iter()
+      .map(|x| ctx.get_str_from_span(*x))
+      .collect();
+    assert_eq!(docs, ["the area of a square", "with sides of length x"]);
+
+    let [Attribute::Inline, Attribute::Deprecated(Some(message))] = old_area.attributes[..] else {
+      panic!("unexpected attributes {:?}", old_area.attributes)
+    };
+    assert_eq!(ctx.get_str_from_span(message), "use area instead");
+
+    let NodeData::FunctionDef(main) = &ast.nodes[ast.funcs[1]].data else {
+      panic!("expected a function")
+    };
+    assert!(main.docs.is_empty());
+    assert!(matches!(main.attributes[..], [Attribute::Export]));
+  }
+
+  #[test]
+  fn rejects_doc_comments_that_do_not_document_a_function() {
+    for src in [
+      "## a distinct integer\nnewtype Meters = Integer",
+      "## things with an area\ntrait Shape:\n  defn area(self) -> Integer",
+      "## the answer\nconst answer: Integer = 42",
+      "defn main() -> Integer:\n  return 0\n\n## nothing follows",
+    ] {
+      let ctx = context(src);
+      let error = Parser::new(&ctx).unwrap().parse().unwrap_err();
+
+      assert!(
+        error.ends_with("Error: | Doc comment does not document anything"),
+        "{src:?}: {error}"
+      );
+    }
+  }
+}
@@ -0,0 +1,181 @@
+// resolves the arguments of a call against the signature of the callee
+//
+// arguments are passed by position first and by the name of a parameter after,
+// and the parameters left out take their default value
+//
+//   defn connect(host: String, port: Integer = 80, tls: Integer = 0)
+//
+//   connect("x", tls=1)  ->  [Argument(0), Default(port), Argument(1)]
+//
+// the frontend emits the arguments in the order they were written in,
+// and then passes them positionally in the order resolved here
+
+use crate::{
+  context::CompilerContext,
+  diagnostic::{Diagnostic, DiagnosticLevel},
+  node::{Argument, NodeIdx, ParameterDeclList},
+  token::TokIdx,
+};
+
+// where the value of a parameter comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArgumentSource {
+  // the argument written at this position of the call
+  Argument(usize),
+
+  // the default value of the parameter
+  Default(NodeIdx),
+}
+
+fn error(ctx: &CompilerContext, tokidx: TokIdx, info: String) {
+  ctx.push_diagnostic(Diagnostic {
+    info,
+    level: DiagnosticLevel::Error,
+    tokidx,
+  });
+}
+
+// the source of every parameter in @params,
+// None if the arguments do not fit the parameters,
+// after reporting every problem with them
+pub fn resolve(
+  ctx: &CompilerContext,
+  params: &ParameterDeclList,
+  args: &[Argument],
+  tok: TokIdx,
+) -> Option<Vec<ArgumentSource>> {
+  let mut slots: Vec<Option<ArgumentSource>> = vec![None; params.len()];
+  let mut valid = true;
+
+  for (i, arg) in args.iter().enumerate() {
+    let slot = match arg.name {
+      None if args[..i].iter().any(|x| x.name.is_some()) => {
+        error(
+          ctx,
+          tok,
+          "Positional arguments can not follow named arguments".to_string(),
+        );
+        valid = false;
+        continue;
+      }
+
+      None if i >= params.len() => {
+        error(
+          ctx,
+          tok,
+          format!(
+            "Expected at most {} arguments in call, but found {}",
+            params.len(),
+            args.len()
+          ),
+        );
+        valid = false;
+        break;
+      }
+
+      None => i,
+
+      Some(name) => {
+        let name = ctx.get_str_from_span(name);
+
+        match params
+          .iter()
+          .position(|x| ctx.get_str_from_span(x.name) == name)
+        {
+          Some(slot) => slot,
+
+          None => {
+            error(ctx, tok, format!("Unknown parameter {} in call", name));
+            valid = false;
+            continue;
+          }
+        }
+      }
+    };
+
+    if slots[slot].is_some() {
+      error(
+        ctx,
+        tok,
+        format!(
+          "Parameter {} is passed more than once",
+          ctx.get_str_from_span(params[slot].name)
+        ),
+      );
+      valid = false;
+    }
+
+    slots[slot] = Some(ArgumentSource::Argument(i));
+  }
+
+  for (slot, param) in slots.iter_mut().zip(params.iter()) {
+    if slot.is_some() {
+      continue;
+    }
+
+    match param.default {
+      Some(default) => *slot = Some(ArgumentSource::Default(default)),
+
+      None => {
+        error(
+          ctx,
+          tok,
+          format!(
+            "Missing argument for parameter {}",
+            ctx.get_str_from_span(param.name)
+          ),
+        );
+        valid = false;
+      }
+    }
+  }
+
+  valid.then(|| slots.into_iter().flatten().collect())
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{ir::interpreter::Value, testing::compile};
+
+  #[test]
+  fn passes_named_and_default_arguments_by_position() {
+    let compiled = compile(
+      "extern defn llabs(x: Integer) -> Integer
+
+defn connect(host: Integer, port: Integer = 80, scale: Floating = 1.5 * 2.0) -> Integer:
+  return host + port + Integer(scale)
+
+defn main() -> Integer:
+  let a = connect(1)
+  let b = connect(port=8, host=2)
+  let c = connect(3, scale=10.0)
+  return a + b + c - 100 + llabs(x=0 - 1)",
+    );
+
+    assert!(compiled.diagnostics.is_empty());
+    assert_eq!(compiled.value, Some(Value::Integer(91)));
+  }
+
+  #[test]
+  fn reports_unknown_duplicate_and_missing_arguments() {
+    let compiled = compile(
+      "defn f(a: Integer, b: Integer = 1) -> Integer:
+  return a + b
+
+defn main() -> Integer:
+  return f(c=1) + f(1, a=2) + f(b=1, 2)",
+    );
+
+    assert_eq!(
+      compiled.diagnostics,
+      [
+        "Unknown parameter c in call",
+        "Missing argument for parameter a",
+        "Parameter a is passed more than once",
+        "Positional arguments can not follow named arguments",
+        "Missing argument for parameter a"
+      ]
+    );
+    assert_eq!(compiled.value, None);
+  }
+}
@@ -0,0 +1,111 @@
+// evaluates constants at compile time by interpreting their initializers
+//
+// const LIMIT: Integer = square(4) + 1
+//
+// an initializer, and every const defn, can only call other const functions,
+// which is checked while typechecking,
+// the constants are evaluated once the unit has been lowered to THIR,
+// afterwards every use of a constant is replaced with its value
+//
+// the initializers are run by the interpreter,
+// which bounds the evaluation so a diverging const defn can not hang the compiler
+
+use crate::{
+  diagnostic::{Diagnostic, DiagnosticLevel},
+  ir::{
+    interpreter::{Interpreter, Value},
+    thir, InstructionValue, IrFunction, IrUnit, Linkage, Type,
+  },
+  token::TokIdx,
+};
+
+use super::SemaContext;
+
+fn error(sema: &SemaContext, tokidx: TokIdx, info: String) {
+  sema.ctx.push_diagnostic(Diagnostic {
+    info,
+    level: DiagnosticLevel::Error,
+    tokidx,
+  });
+}
+
+// const functions can only call other const functions
+fn check_const_function(sema: &SemaContext, unit: &IrUnit, function: &IrFunction) {
+  for instr in function.body.instrs.iter() {
+    if let InstructionValue::Call { func, .. } = instr.val {
+      let callee = &unit.funcs[func];
+
+      if !callee.is_const || callee.linkage == Linkage::Extern {
+        error(
+          sema,
+          instr.tok,
+          format!(
+            "Unable to call the non-const function {} at compile time",
+            sema.ctx.get_str_from_span(callee.name)
+          ),
+        );
+      }
+    }
+  }
+}
+
+// checks that the constants can be evaluated
+pub fn check(sema: &SemaContext, unit: &IrUnit) {
+  for func in unit.funcs.iter().filter(|x| x.is_const) {
+    check_const_function(sema, unit, func);
+  }
+
+  for constant in unit.consts.iter() {
+    check_const_function(sema, unit, &constant.init);
+
+    if !matches!(
+      sema.types.erase(&constant.ty),
+      Type::Integer | Type::Floating
+    ) {
+      error(
+        sema,
+        constant.tok,
+        format!(
+          "Constants can only be of type Integer or Floating, but {} is {}",
+          sema.ctx.get_str_from_span(constant.name),
+          constant.ty
+        ),
+      );
+    }
+  }
+}
+
+pub fn evaluate(sema: &SemaContext, mut unit: thir::Unit) -> thir::Unit {
+  let mut evaluator = Interpreter::new(sema.ctx, &unit);
+
+  let mut values = vec![];
+  for (idx, constant) in unit.consts.iter().enumerate() {
+    match evaluator.eval_const(idx, constant.tok) {
+      Ok(value) => values.push(Some(value)),
+
+      Err((tok, info)) => {
+        if !info.is_empty() {
+          error(sema, tok, info);
+        }
+        values.push(None);
+      }
+    }
+  }
+
+  // replace every use of a constant with its value
+  for func in unit.funcs.iter_mut() {
+    for instr in func.body.instrs.iter_mut() {
+      let InstructionValue::Const(idx) = instr.val else {
+        continue;
+      };
+
+      instr.val = match values[idx] {
+        Some(Value::Integer(i)) => InstructionValue::ConstInteger(i),
+        Some(Value::Floating(f)) => InstructionValue::ConstFloat(f),
+        _ => continue,
+      };
+    }
+  }
+
+  unit
+}
@@ -0,0 +1,16 @@
+// replaces every newtype with the type it wraps once typechecking is done,
+// and lowers the unit to THIR, so that later passes only ever see builtin types
+//
+// the type arguments of instantiations and the types of implementations
+// are kept as they are, they only name functions and keep
+// e.g. max[UserId] and max[Integer] apart
+
+use crate::ir::{thir, IrUnit, Type};
+
+use super::SemaContext;
+
+// fails with the first type that has no place in THIR,
+// none are left once a unit typechecked
+pub fn erase(sema: &SemaContext, unit: IrUnit) -> Result<thir::Unit, Type> {
+  unit.map_types(|ty| thir::Type::try_from(&sema.types.erase(ty)))
+}
@@ -1,9 +1,23 @@
-use crate::{context::CompilerContext, ir::IrUnit};
+use crate::{
+  context::CompilerContext,
+  diagnostic::DiagnosticLevel,
+  ir::{
+    thir,
+    verify::{self, Stage},
+    IrUnit,
+  },
+};
 
 use self::types::Types;
 
 // mod binary_lowering;
 // mod type_checking;
+pub mod arguments;
+mod const_eval;
+mod erasure;
+mod monomorphize;
+mod returns;
+mod traits;
 mod type_propogation;
 mod types;
 
@@ -17,16 +31,75 @@ pub struct SemaContext<'a> {
 }
 
 impl<'a> SemaContext<'a> {
-  fn inner_run(self, mut unit: IrUnit) -> IrUnit {
-    unit = type_propogation::propogate(&self, unit);
-    unit
-  }
+  fn new(ctx: &'a CompilerContext, unit: &IrUnit) -> Self {
+    let newtypes = unit
+      .newtypes
+      .iter()
+      .map(|x| (ctx.get_str_from_span(x.name).to_string(), x.ty.clone()))
+      .collect();
 
-  pub fn run(ctx: &'a CompilerContext, unit: IrUnit) -> IrUnit {
     Self {
       ctx,
-      types: Types {},
+      types: Types::new(unit.funcs.len(), newtypes),
+    }
+  }
+
+  fn failed(&self) -> bool {
+    self
+      .ctx
+      .get_diagnostics()
+      .iter()
+      .any(|x| matches!(x.level, DiagnosticLevel::Error))
+  }
+
+  fn inner_run(self, mut unit: IrUnit) -> Result<thir::Unit, Box<IrUnit>> {
+    // a missing return is the only way the source can leave the IR malformed
+    returns::check(&self, &unit);
+    verify::check(self.ctx, &unit, "ast2ir", Stage::Untyped);
+
+    traits::check_impls(&self, &unit);
+    unit = type_propogation::propogate(&self, unit);
+
+    // calls refer to the instantiations requested by type propogation
+    // before monomorphization adds them
+    unit = monomorphize::monomorphize(&self, unit);
+    verify::check(
+      self.ctx,
+      &unit,
+      "type propogation and monomorphization",
+      Stage::Typed,
+    );
+
+    const_eval::check(&self, &unit);
+
+    // only a unit that typechecked has a type for every value
+    if self.failed() {
+      return Err(Box::new(unit));
+    }
+
+    let unit = erasure::erase(&self, unit)
+      .unwrap_or_else(|ty| panic!("sema left the type {} unsettled", ty));
+    verify::check(self.ctx, &unit, "erasure", Stage::Typed);
+
+    let unit = const_eval::evaluate(&self, unit);
+    verify::check(self.ctx, &unit, "constant evaluation", Stage::Typed);
+
+    match self.failed() {
+      true => Err(Box::new(unit.into())),
+      false => Ok(unit),
     }
-    .inner_run(unit)
+  }
+
+  // typechecks @unit and lowers it to THIR,
+  // the unit is handed back as far as it got if sema reported an error
+  pub fn run(ctx: &'a CompilerContext, unit: IrUnit) -> Result<thir::Unit, Box<IrUnit>> {
+    Self::new(ctx, &unit).inner_run(unit)
+  }
+
+  // lowers a unit that is typed already, e.g. textual IR, to THIR
+  // without checking it
+  pub fn lower(ctx: &'a CompilerContext, unit: IrUnit) -> Result<thir::Unit, String> {
+    erasure::erase(&Self::new(ctx, &unit), unit)
+      .map_err(|ty| format!("Unable to lower the unsettled type {} to THIR", ty))
   }
 }
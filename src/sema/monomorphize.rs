@@ -0,0 +1,134 @@
+// generates a concrete function for every instantiation of a generic function
+//
+// type propogation redirects every call to a template to an instantiation,
+//   e.g. max(1, 2) -> max[Integer](1, 2)
+// which is then generated here by substituting the type arguments
+// into the signature of the template and propogating its body,
+// possibly queueing further instantiations,
+// up to the limit of nested instantiations in types.rs
+//
+// afterwards the templates are removed from the unit,
+// so that later passes never see unresolved type parameters
+
+use std::{collections::HashMap, convert::Infallible};
+
+use crate::ir::{FuncIdx, InstructionValue, IrFunction, IrUnit};
+
+use super::{type_propogation::FunctionTypePropogator, types::PendingInstance, SemaContext};
+
+fn instantiate(sema: &SemaContext, unit: &IrUnit, pending: PendingInstance) -> IrFunction {
+  let PendingInstance {
+    template,
+    type_args,
+    depth,
+    ..
+  } = pending;
+  let template = &unit.funcs[template];
+
+  let function = IrFunction {
+    name: template.name,
+    params: template
+      .params
+      .iter()
+      .map(|x| sema.types.substitute(x, &type_args))
+      .collect(),
+    return_type: sema.types.substitute(&template.return_type, &type_args),
+    type_params: vec![],
+    method_of: template.method_of,
+    linkage: template.linkage,
+    is_const: template.is_const,
+    inline: template.inline,
+    docs: template.docs.clone(),
+    // the instructions carry the types they were built with,
+    // which may name the type parameters as well
+    body: template
+      .body
+      .clone()
+      .map_types(|x| Ok::<_, Infallible>(sema.types.substitute(x, &type_args)))
+      .unwrap_or_else(|x| match x {}),
+    type_args,
+  };
+
+  FunctionTypePropogator::propogate_instance(sema, unit, &function, depth)
+}
+
+pub fn monomorphize(sema: &SemaContext, mut unit: IrUnit) -> IrUnit {
+  while let Some(pending) = sema.types.next_pending_instance() {
+    // instantiations are generated in the order they were requested
+    debug_assert_eq!(pending.instance, unit.funcs.len());
+
+    let function = instantiate(sema, &unit, pending);
+    unit.funcs.push(function);
+  }
+
+  // drop the templates, and fix up the indices of the remaining functions
+  let mut remap: HashMap<FuncIdx, FuncIdx> = HashMap::new();
+  let mut funcs = vec![];
+
+  for (idx, func) in std::mem::take(&mut unit.funcs).into_iter().enumerate() {
+    if func.type_params.is_empty() {
+      remap.insert(idx, funcs.len());
+      funcs.push(func);
+    }
+  }
+
+  let inits = unit.consts.iter_mut().map(|x| &mut x.init);
+
+  for func in funcs.iter_mut().chain(inits) {
+    for instr in func.body.instrs.iter_mut() {
+      if let InstructionValue::Call { ref mut func, .. } = instr.val {
+        // calls that could not be resolved to an instantiation
+        // have already been reported
+        if let Some(new) = remap.get(func) {
+          *func = *new;
+        }
+      }
+    }
+  }
+
+  for imp in unit.impls.iter_mut() {
+    for method in imp.methods.iter_mut() {
+      *method = remap[method];
+    }
+  }
+
+  IrUnit { funcs, ..unit }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{ir::interpreter::Value, testing::compile};
+
+  #[test]
+  fn substitutes_the_types_of_the_instructions() {
+    let compiled = compile(
+      "defn max[T](a: T, b: T) -> T:
+  return if a < b: b else: a
+
+defn main() -> Integer:
+  return max(4, 9)",
+    );
+
+    assert!(compiled.diagnostics.is_empty());
+    assert_eq!(compiled.value, Some(Value::Integer(9)));
+  }
+
+  #[test]
+  fn stops_at_the_limit_of_nested_instantiations() {
+    let compiled = compile(
+      "defn f[T](x: T, n: Integer) -> Integer:
+  if n < 1:
+    return 0
+  return f((x, x), n - 1)
+
+defn main() -> Integer:
+  return f(1, 3)",
+    );
+
+    assert_eq!(
+      compiled.diagnostics,
+      ["Instantiating f exceeded the limit of 16 nested instantiations"]
+    );
+    assert_eq!(compiled.value, None);
+  }
+}
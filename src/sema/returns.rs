@@ -0,0 +1,75 @@
+// checks that a function returning a value returns on every path
+//
+// defn f(x: Integer) -> Integer:
+//   if x < 0:
+//     return 0
+//
+// ast2ir ends a body that can fall off its end with an Exit,
+// which is only fine for a function returning Moot,
+// so any other function that can reach one is rejected
+// before the verifier gets to see it
+
+use crate::{
+  diagnostic::{Diagnostic, DiagnosticLevel},
+  ir::{InstructionValue, IrType, IrUnit},
+};
+
+use super::SemaContext;
+
+pub fn check(sema: &SemaContext, unit: &IrUnit) {
+  for func in unit.funcs.iter() {
+    if func.body.instrs.is_empty() || func.return_type.is_moot() {
+      continue;
+    }
+
+    let cfg = func.cfg();
+    let reachable = cfg.reachable();
+
+    for block in (0..cfg.blocks().len()).filter(|x| reachable[*x]) {
+      let exit = func.body.terminator(block);
+
+      if matches!(exit.val, InstructionValue::Exit) {
+        sema.ctx.push_diagnostic(Diagnostic {
+          info: "not every path returns a value".to_string(),
+          level: DiagnosticLevel::Error,
+          tokidx: exit.tok,
+        });
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::testing::compile;
+
+  #[test]
+  fn rejects_functions_that_can_fall_off_their_end() {
+    let compiled = compile(
+      "defn f() -> Integer:
+  let x = 1
+
+defn sign(x: Integer) -> Integer:
+  if x < 0:
+    return 0 - 1
+  else if x > 0:
+    return 1
+
+defn nothing(x: Integer):
+  let y = x
+
+defn main() -> Integer:
+  nothing(1)
+  return f() + sign(2)",
+    );
+
+    assert_eq!(
+      compiled.diagnostics,
+      [
+        "not every path returns a value",
+        "not every path returns a value"
+      ]
+    );
+    assert_eq!(compiled.value, None);
+  }
+}
@@ -0,0 +1,224 @@
+// coherence checks for trait implementations,
+// and the static resolution of method calls
+//
+// every implementation has to define exactly the methods of its trait,
+// with the signatures of the trait after substituting Self,
+// and a trait can only be implemented once for every type
+//
+// within a template, a method called on a type parameter
+// has to be declared by one of the bounds of the parameter
+
+use crate::{
+  diagnostic::{Diagnostic, DiagnosticLevel},
+  ir::{FuncIdx, ImplIdx, IrMethodSig, IrUnit, TraitIdx, Type},
+  token::TokIdx,
+};
+
+use super::SemaContext;
+
+fn error(sema: &SemaContext, tokidx: TokIdx, info: String) {
+  sema.ctx.push_diagnostic(Diagnostic {
+    info,
+    level: DiagnosticLevel::Error,
+    tokidx,
+  });
+}
+
+pub fn check_impls(sema: &SemaContext, unit: &IrUnit) {
+  for (idx, imp) in unit.impls.iter().enumerate() {
+    let tr = &unit.traits[imp.trait_idx];
+    let trait_name = sema.ctx.get_str_from_span(tr.name);
+
+    if unit.impls[..idx]
+      .iter()
+      .any(|x| x.trait_idx == imp.trait_idx && sema.types.same_type(&x.ty, &imp.ty))
+    {
+      error(
+        sema,
+        imp.tok,
+        format!(
+          "{} is implemented more than once for {}",
+          trait_name, imp.ty
+        ),
+      );
+    }
+
+    for sig in tr.methods.iter() {
+      let name = sema.ctx.get_str_from_span(sig.name);
+
+      let Some(func) = imp
+        .methods
+        .iter()
+        .map(|x| &unit.funcs[*x])
+        .find(|x| sema.ctx.get_str_from_span(x.name) == name)
+      else {
+        error(
+          sema,
+          imp.tok,
+          format!("Missing method {} of {} for {}", name, trait_name, imp.ty),
+        );
+        continue;
+      };
+
+      let self_ty = [imp.ty.clone()];
+      let params: Vec<Type> = sig
+        .params
+        .iter()
+        .map(|x| sema.types.substitute(x, &self_ty))
+        .collect();
+      let return_type = sema.types.substitute(&sig.return_type, &self_ty);
+
+      let matches = params.len() == func.params.len()
+        && params
+          .iter()
+          .zip(func.params.iter())
+          .all(|(l, r)| sema.types.same_type(l, r))
+        && sema.types.same_type(&return_type, &func.return_type);
+
+      if !matches {
+        error(
+          sema,
+          imp.tok,
+          format!(
+            "Method {} of {} does not match the signature declared by the trait",
+            name, trait_name
+          ),
+        );
+      }
+    }
+
+    for func in imp.methods.iter().map(|x| &unit.funcs[*x]) {
+      let name = sema.ctx.get_str_from_span(func.name);
+
+      if !tr
+        .methods
+        .iter()
+        .any(|x| sema.ctx.get_str_from_span(x.name) == name)
+      {
+        error(
+          sema,
+          imp.tok,
+          format!("{} is not a method of {}", name, trait_name),
+        );
+      }
+    }
+  }
+}
+
+pub fn find_impl(
+  sema: &SemaContext,
+  unit: &IrUnit,
+  trait_idx: TraitIdx,
+  ty: &Type,
+) -> Option<ImplIdx> {
+  unit
+    .impls
+    .iter()
+    .position(|x| x.trait_idx == trait_idx && sema.types.same_type(&x.ty, ty))
+}
+
+// finds the function implementing the method @name for a receiver of type @ty,
+// the method has to be unique among all the traits implemented for @ty
+pub fn resolve_method(
+  sema: &SemaContext,
+  unit: &IrUnit,
+  ty: &Type,
+  name: &str,
+) -> Result<FuncIdx, String> {
+  let candidates: Vec<FuncIdx> = unit
+    .impls
+    .iter()
+    .filter(|x| sema.types.same_type(&x.ty, ty))
+    .flat_map(|x| x.methods.iter().copied())
+    .filter(|x| sema.ctx.get_str_from_span(unit.funcs[*x].name) == name)
+    .collect();
+
+  match candidates[..] {
+    [func] => Ok(func),
+    [] => Err(format!("No method {} found for type {}", name, ty)),
+    _ => Err(format!(
+      "Method {} is ambiguous for type {}, it is implemented by multiple traits",
+      name, ty
+    )),
+  }
+}
+
+// finds the trait method @name among the traits bounding
+// the type parameter @n of a template, returning its return type,
+// a method call on a type parameter can only use what its bounds declare
+pub fn resolve_bound_method(
+  sema: &SemaContext,
+  unit: &IrUnit,
+  bounds: &[TraitIdx],
+  n: usize,
+  name: &str,
+) -> Result<Type, String> {
+  let candidates: Vec<&IrMethodSig> = bounds
+    .iter()
+    .flat_map(|x| unit.traits[*x].methods.iter())
+    .filter(|x| sema.ctx.get_str_from_span(x.name) == name)
+    .collect();
+
+  let ty = Type::Generic(n);
+
+  match candidates[..] {
+    // Self within the signature is the type parameter itself
+    [sig] => Ok(sema.types.substitute(&sig.return_type, &[ty])),
+
+    [] => Err(format!(
+      "No method {} found for type {}, none of its bounds declare it",
+      name, ty
+    )),
+
+    _ => Err(format!(
+      "Method {} is ambiguous for type {}, it is declared by multiple of its bounds",
+      name, ty
+    )),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::testing::compile;
+
+  #[test]
+  fn only_calls_the_methods_of_the_bounds_on_a_type_parameter() {
+    let compiled = compile(
+      "trait Named:
+  defn id(self) -> Integer
+
+trait Shape:
+  defn area(self) -> Integer
+  defn scale(self, k: Integer) -> Self
+
+implements Named for Integer:
+  defn id(self) -> Integer:
+    return self
+
+implements Shape for Integer:
+  defn area(self) -> Integer:
+    return self * self
+  defn scale(self, k: Integer) -> Integer:
+    return self * k
+
+defn unbounded[T](x: T) -> Integer:
+  return x.id()
+
+defn bounded[T: Shape, U: Named](x: T, u: U) -> Integer:
+  let y = x.scale(2)
+  return y.area() + u.id() + y.id()
+
+defn main() -> Integer:
+  return unbounded(1) + bounded(2, 3)",
+    );
+
+    assert_eq!(
+      compiled.diagnostics,
+      [
+        "No method id found for type T0, none of its bounds declare it",
+        "No method id found for type T0, none of its bounds declare it"
+      ]
+    );
+    assert_eq!(compiled.value, None);
+  }
+}
@@ -1,38 +1,100 @@
 use crate::{
   diagnostic::{Diagnostic, DiagnosticLevel},
-  ir::{InstrIdx, Instruction, InstructionValue, IrBlock, IrFunction, IrUnit, Type},
+  ir::{
+    builder::IrBuilder, FuncIdx, InstrIdx, InstructionValue, IrConst, IrFunction, IrUnit, Type,
+  },
+  token::TokIdx,
 };
 
-use super::SemaContext;
+use super::{traits, types::INSTANTIATION_LIMIT, SemaContext};
 
 pub fn propogate<'a>(sema: &'a SemaContext<'a>, unit: IrUnit) -> IrUnit {
   let mut funcs = vec![];
 
   for func in unit.funcs.iter() {
-    funcs.push(FunctionTypePropogator::propogate(sema, func));
+    // templates are only typed through their instantiations,
+    // apart from the method calls on their type parameters
+    if !func.type_params.is_empty() {
+      FunctionTypePropogator::check_template(sema, &unit, func);
+      funcs.push(func.clone());
+      continue;
+    }
+
+    funcs.push(FunctionTypePropogator::propogate(sema, &unit, func));
   }
 
-  IrUnit { funcs }
+  let consts = unit
+    .consts
+    .iter()
+    .map(|x| IrConst {
+      init: FunctionTypePropogator::propogate(sema, &unit, &x.init),
+      ..x.clone()
+    })
+    .collect();
+
+  IrUnit {
+    funcs,
+    consts,
+    ..unit
+  }
 }
 
 // type-lowering construct for a single function
 // contains a reference to the global typechecking context
-struct FunctionTypePropogator<'a> {
+pub struct FunctionTypePropogator<'a> {
   sema: &'a SemaContext<'a>,
+
+  // the unit containing the function,
+  // used to look up the signatures of called functions
+  unit: &'a IrUnit,
+
   function: &'a IrFunction,
-  out_buffer: Vec<Instruction>,
+
+  // how many instantiations @function is nested within,
+  // 0 unless it is an instantiation itself
+  depth: usize,
+
+  // set when checking a template, see check_template
+  template: bool,
+
+  // the typed instructions, in the same order and blocks as the untyped ones
+  builder: IrBuilder,
 }
 
 impl<'a> FunctionTypePropogator<'a> {
+  // errors within a template are left to its instantiations,
+  // apart from the ones reported through bound_error
+  fn error(&self, tokidx: TokIdx, info: String) {
+    if !self.template {
+      self.bound_error(tokidx, info);
+    }
+  }
+
+  fn bound_error(&self, tokidx: TokIdx, info: String) {
+    self.sema.ctx.push_diagnostic(Diagnostic {
+      info,
+      level: DiagnosticLevel::Error,
+      tokidx,
+    });
+  }
+
   fn propogate_instruction(&mut self, instridx: InstrIdx) {
-    let instr = &self.function.instrs.0[instridx];
+    let function = self.function;
+    let instr = &function.body.instrs[instridx];
 
     let tok = instr.tok;
 
-    let out = match instr.val {
+    // calls may get redirected to another function
+    let mut val = instr.val.clone();
+
+    let ty = match instr.val {
       // constants have their types generated at emission,
       // thus nothing has to happen
-      InstructionValue::ConstInteger(_) | InstructionValue::ConstFloat(_) => instr.clone(),
+      InstructionValue::ConstInteger(_) | InstructionValue::ConstFloat(_) => instr.ty.clone(),
+
+      InstructionValue::Param(n) => self.function.params[n].clone(),
+
+      InstructionValue::Const(n) => self.unit.consts[n].ty.clone(),
 
       InstructionValue::Add(l, r)
       | InstructionValue::Subtract(l, r)
@@ -40,78 +102,659 @@ impl<'a> FunctionTypePropogator<'a> {
       | InstructionValue::Divide(l, r) => {
         // instructions can only reference instructions that come before them,
         // thus these instructions are guaranteed to be typed
-        let l_ty = self.out_buffer[l].ty;
-        let r_ty = self.out_buffer[r].ty;
+        let l_ty = &self.builder[l].ty;
+        let r_ty = &self.builder[r].ty;
 
-        let ty = if !self.sema.types.binary_compatable_types(l_ty, r_ty) {
-          self.sema.ctx.push_diagnostic(Diagnostic {
-            info: format!(
+        if matches!(l_ty, Type::Invalid) || matches!(r_ty, Type::Invalid) {
+          Type::Invalid
+        } else if !self.sema.types.binary_compatable_types(l_ty, r_ty) {
+          self.error(
+            instr.tok,
+            format!(
               "Invalid binary operation types in add operator: {} and {}",
               l_ty, r_ty
             ),
+          );
+
+          Type::Invalid
+        } else if !self.sema.types.coerce_type(l_ty, r_ty) {
+          self.error(
+            instr.tok,
+            "Unable to coerce the left side operand of a binary operation to the type of the right side operand"
+              .to_string(),
+          );
+
+          Type::Invalid
+        } else {
+          r_ty.clone()
+        }
+      }
 
-            level: DiagnosticLevel::Error,
+      InstructionValue::LessThan(l, r) | InstructionValue::LessEqual(l, r) => {
+        let l_ty = &self.builder[l].ty;
+        let r_ty = &self.builder[r].ty;
 
-            tokidx: instr.tok,
-          });
+        if !self.sema.types.binary_compatable_types(l_ty, r_ty) {
+          self.error(
+            instr.tok,
+            format!("Invalid operand types in comparison: {} and {}", l_ty, r_ty),
+          );
 
           Type::Invalid
-        } else if !self.sema.types.coerce_type(l_ty, r_ty) {
-          self.sema.ctx.push_diagnostic(Diagnostic {
-            info: format!(
-              "Unable to coerce the left side operand of a binary operation to the type of the right side operand"
+        } else {
+          Type::Boolean
+        }
+      }
+
+      // an assign may carry the shape of a destructuring pattern,
+      // which the assigned value has to fit into
+      InstructionValue::Assign(val) => {
+        let val_ty = &self.builder[val].ty;
+
+        match self.sema.types.unify(&instr.ty, val_ty) {
+          Some(ty) => ty,
+
+          None => {
+            if !matches!(val_ty, Type::Invalid) {
+              self.error(
+                instr.tok,
+                format!("Unable to destructure a value of type {}", val_ty),
+              );
+            }
+
+            Type::Invalid
+          }
+        }
+      }
+
+      // explicit conversions, e.g. UserId(1) or Integer(id)
+      InstructionValue::Cast(val, ref ty) => {
+        let val_ty = &self.builder[val].ty;
+
+        if matches!(val_ty, Type::Invalid) {
+          Type::Invalid
+        } else if !self.sema.types.convertible(val_ty, ty) {
+          self.error(
+            instr.tok,
+            format!("Unable to convert a value of type {} to {}", val_ty, ty),
+          );
+
+          Type::Invalid
+        } else {
+          ty.clone()
+        }
+      }
+
+      InstructionValue::OptionSome(val) => match &self.builder[val].ty {
+        Type::Invalid => Type::Invalid,
+        ty => Type::Option(Box::new(ty.clone())),
+      },
+
+      // the missing types are filled in once the value is used
+      InstructionValue::OptionNone => Type::Option(Box::new(Type::Undecided)),
+
+      InstructionValue::ResultOk(val) => match &self.builder[val].ty {
+        Type::Invalid => Type::Invalid,
+        ty => Type::Result(Box::new(ty.clone()), Box::new(Type::Undecided)),
+      },
+
+      InstructionValue::ResultErr(val) => match &self.builder[val].ty {
+        Type::Invalid => Type::Invalid,
+        ty => Type::Result(Box::new(Type::Undecided), Box::new(ty.clone())),
+      },
+
+      InstructionValue::IsFailure(val) => match &self.builder[val].ty {
+        Type::Option(_) | Type::Result(..) => Type::Boolean,
+        Type::Invalid => Type::Invalid,
+
+        ty => {
+          self.error(
+            instr.tok,
+            format!("Expected an Option or Result, but found {}", ty),
+          );
+
+          Type::Invalid
+        }
+      },
+
+      // errors are reported by the IsFailure before it
+      InstructionValue::Unwrap(val) => match &self.builder[val].ty {
+        Type::Option(ty) | Type::Result(ty, _) => *ty.clone(),
+        _ => Type::Invalid,
+      },
+
+      InstructionValue::UnwrapOr(val, default) => self.propogate_unwrap_or(tok, val, default),
+
+      // the ? operator can only pass on a failure
+      // that fits into the return type of the function
+      InstructionValue::Failure(val) => {
+        let val_ty = &self.builder[val].ty;
+        let return_type = &self.function.return_type;
+
+        let fits = match (val_ty, return_type) {
+          (Type::Option(_), Type::Option(_)) => true,
+          (Type::Result(_, err), Type::Result(_, expected)) => {
+            self.sema.types.unify(err, expected).is_some()
+          }
+          _ => false,
+        };
+
+        // values that are neither are reported by the IsFailure before
+        if !matches!(val_ty, Type::Option(_) | Type::Result(..)) {
+          Type::Invalid
+        } else if !fits {
+          self.error(
+            instr.tok,
+            format!(
+              "Unable to return the failure case of {} from a function returning {}",
+              val_ty, return_type
             ),
-            level: DiagnosticLevel::Error,
-            tokidx: instr.tok,
-          });
+          );
+
+          Type::Invalid
+        } else {
+          return_type.clone()
+        }
+      }
+
+      InstructionValue::Tuple(ref vals) => {
+        Type::Tuple(vals.iter().map(|x| self.builder[*x].ty.clone()).collect())
+      }
+
+      InstructionValue::TupleGet(tuple, n) => match &self.builder[tuple].ty {
+        Type::Tuple(types) if n < types.len() => types[n].clone(),
+
+        Type::Invalid => Type::Invalid,
+
+        ty => {
+          self.error(
+            instr.tok,
+            format!("Unable to read element {} of a value of type {}", n, ty),
+          );
+
+          Type::Invalid
+        }
+      },
+
+      // the slot of a template holds the type argument it was instantiated with
+      InstructionValue::Alloca(ref ty) => {
+        let ty = self.sema.types.substitute(ty, &self.function.type_args);
+        val = InstructionValue::Alloca(ty.clone());
+
+        if ty == Type::Moot {
+          self.error(
+            instr.tok,
+            "Unable to reserve a stack slot for a Moot value".to_string(),
+          );
 
           Type::Invalid
         } else {
-          r_ty
+          Type::Pointer(Box::new(ty))
+        }
+      }
+
+      InstructionValue::Load(ptr) => self.pointee(tok, ptr).unwrap_or(Type::Invalid),
+
+      InstructionValue::Store(ptr, stored) => {
+        if let Some(ty) = self.pointee(tok, ptr) {
+          self.settle(stored, &ty);
+          let stored_ty = &self.builder[stored].ty;
+
+          if !matches!(stored_ty, Type::Invalid) && !self.sema.types.same_type(&ty, stored_ty) {
+            self.error(
+              instr.tok,
+              format!(
+                "Unable to store a value of type {} behind a pointer to {}",
+                stored_ty, ty
+              ),
+            );
+          }
+        }
+
+        Type::Moot
+      }
+
+      InstructionValue::GetElementAddr(ptr, n) => match self.pointee(tok, ptr) {
+        Some(Type::Tuple(types)) if n < types.len() => Type::Pointer(Box::new(types[n].clone())),
+
+        Some(ty) => {
+          self.error(
+            instr.tok,
+            format!("Unable to address element {} of a value of type {}", n, ty),
+          );
+
+          Type::Invalid
+        }
+
+        None => Type::Invalid,
+      },
+
+      InstructionValue::Call { func, ref params } => {
+        let (func, ty) = self.propogate_call(tok, func, params);
+
+        val = InstructionValue::Call {
+          func,
+          params: params.clone(),
         };
+        ty
+      }
 
-        Instruction {
-          val: instr.val.clone(),
-          ty,
-          tok,
+      // methods are resolved statically from the type of the receiver
+      InstructionValue::MethodCall {
+        ref method,
+        ref params,
+      } => match &self.builder[params[0]].ty {
+        Type::Invalid => Type::Invalid,
+
+        Type::Option(_) | Type::Result(..) if method == "unwrap_or" && params.len() == 2 => {
+          val = InstructionValue::UnwrapOr(params[0], params[1]);
+          self.propogate_unwrap_or(tok, params[0], params[1])
+        }
+
+        // only reached within a template, the instantiations see the type argument
+        Type::Generic(n) => {
+          let bounds = &self.function.type_params[*n];
+
+          match traits::resolve_bound_method(self.sema, self.unit, bounds, *n, method) {
+            Ok(ty) => ty,
+
+            Err(info) => {
+              self.bound_error(tok, info);
+              Type::Invalid
+            }
+          }
+        }
+
+        receiver => match traits::resolve_method(self.sema, self.unit, receiver, method) {
+          Ok(func) => {
+            let (func, ty) = self.propogate_call(tok, func, params);
+
+            val = InstructionValue::Call {
+              func,
+              params: params.clone(),
+            };
+            ty
+          }
+
+          Err(info) => {
+            self.error(tok, info);
+            Type::Invalid
+          }
+        },
+      },
+
+      // operands of a phi may come from a back-edge,
+      // and are checked once the whole function has been propogated
+      InstructionValue::Phi(ref vals) => match instr.ty {
+        Type::Undecided => self.propogate_phi(instridx, vals),
+        ref declared => declared.clone(),
+      },
+
+      InstructionValue::Branch(cond, ..) => {
+        let cond_ty = &self.builder[cond].ty;
+
+        if !matches!(cond_ty, Type::Boolean | Type::Invalid) {
+          self.error(
+            instr.tok,
+            format!("Expected a Boolean condition, but found {}", cond_ty),
+          );
         }
+
+        Type::Moot
       }
 
+      InstructionValue::Jump(_) | InstructionValue::Exit | InstructionValue::Nop => Type::Moot,
+
       InstructionValue::Return(ridx) => {
-        let ty = self.out_buffer[ridx].ty;
+        self.settle(ridx, &self.function.return_type);
+        let ty = &self.builder[ridx].ty;
+
+        if !matches!(ty, Type::Invalid)
+          && !self.sema.types.coerce_type(ty, &self.function.return_type)
+        {
+          self.error(
+            instr.tok,
+            format!(
+              "Expected a return value of type {}, but found {}",
+              self.function.return_type, ty
+            ),
+          );
+        }
+
+        ty.clone()
+      }
+    };
+
+    self.builder.build(val, ty, tok);
+  }
+
+  // checks the arguments of a call against the signature of the callee,
+  // returns the function that ends up being called and the type of the call
+  fn propogate_call(&mut self, tok: TokIdx, func: FuncIdx, params: &[InstrIdx]) -> (FuncIdx, Type) {
+    let callee = &self.unit.funcs[func];
+
+    let arg_types: Vec<Type> = params.iter().map(|x| self.builder[*x].ty.clone()).collect();
+
+    if callee.params.len() != params.len() {
+      self.error(
+        tok,
+        format!(
+          "Expected {} arguments in call, but found {}",
+          callee.params.len(),
+          params.len()
+        ),
+      );
+
+      (func, Type::Invalid)
+    } else if arg_types.contains(&Type::Invalid) {
+      (func, Type::Invalid)
+    } else if !callee.type_params.is_empty() {
+      self.propogate_generic_call(tok, func, arg_types)
+    } else {
+      for (param, arg) in callee.params.iter().zip(params.iter()) {
+        self.settle(*arg, param);
+        let arg_ty = &self.builder[*arg].ty;
+
+        if !self.sema.types.same_type(param, arg_ty) {
+          self.error(
+            tok,
+            format!(
+              "Expected an argument of type {}, but found {}",
+              param, arg_ty
+            ),
+          );
+        }
+      }
+
+      (func, callee.return_type.clone())
+    }
+  }
+
+  // infers the type arguments of a call to a template from the
+  // types of its arguments, and redirects the call to the matching instantiation
+  fn propogate_generic_call(
+    &self,
+    tok: TokIdx,
+    template: FuncIdx,
+    arg_types: Vec<Type>,
+  ) -> (FuncIdx, Type) {
+    let callee = &self.unit.funcs[template];
 
-        Instruction {
-          val: instr.val.clone(),
-          ty,
+    let mut type_args = vec![None; callee.type_params.len()];
+
+    for (param, arg_ty) in callee.params.iter().zip(arg_types.iter()) {
+      if !self.sema.types.infer(param, arg_ty, &mut type_args) {
+        self.error(
           tok,
+          format!(
+            "Expected an argument of type {}, but found {}",
+            self.sema.types.substitute_partial(param, &type_args),
+            arg_ty
+          ),
+        );
+      }
+    }
+
+    // type arguments with undecided parts, e.g. the type held by a None,
+    // can not be instantiated
+    let Some(type_args) = type_args
+      .into_iter()
+      .map(|x| x.filter(Type::is_complete))
+      .collect::<Option<Vec<_>>>()
+    else {
+      self.error(
+        tok,
+        "Unable to infer every type argument of a call".to_string(),
+      );
+
+      return (template, Type::Invalid);
+    };
+
+    // every type argument has to implement the bounds of its parameter
+    for (bounds, ty) in callee.type_params.iter().zip(type_args.iter()) {
+      for bound in bounds {
+        if traits::find_impl(self.sema, self.unit, *bound, ty).is_none() {
+          self.error(
+            tok,
+            format!(
+              "Type {} does not implement {}",
+              ty,
+              self
+                .sema
+                .ctx
+                .get_str_from_span(self.unit.traits[*bound].name)
+            ),
+          );
+        }
+      }
+    }
+
+    let ty = self.sema.types.substitute(&callee.return_type, &type_args);
+
+    // a template being checked only requests its instantiations once it is instantiated
+    if self.template {
+      return (template, ty);
+    }
+
+    match self.sema.types.instantiate(template, type_args, self.depth) {
+      Some(instance) => (instance, ty),
+
+      None => {
+        self.error(
+          tok,
+          format!(
+            "Instantiating {} exceeded the limit of {} nested instantiations",
+            self.sema.ctx.get_str_from_span(callee.name),
+            INSTANTIATION_LIMIT
+          ),
+        );
+
+        (template, Type::Invalid)
+      }
+    }
+  }
+
+  // the type behind the pointer @ptr,
+  // None if it is not a pointer, which is reported unless it is invalid
+  fn pointee(&self, tok: TokIdx, ptr: InstrIdx) -> Option<Type> {
+    match &self.builder[ptr].ty {
+      Type::Pointer(ty) => Some(*ty.clone()),
+      Type::Invalid => None,
+
+      ty => {
+        self.error(tok, format!("Expected a pointer, but found {}", ty));
+        None
+      }
+    }
+  }
+
+  // fills in the undecided parts of the type of a value once
+  // the type it is used as is known, e.g. the type held by a None
+  fn settle(&mut self, val: InstrIdx, ty: &Type) {
+    let val_ty = &self.builder[val].ty;
+
+    if val_ty.is_complete() {
+      return;
+    }
+
+    let Some(ty) = self.sema.types.unify(val_ty, ty) else {
+      return;
+    };
+
+    self.builder[val].ty = ty.clone();
+
+    // the value may have been passed on from another instruction
+    match self.builder[val].val {
+      InstructionValue::Assign(src) => self.settle(src, &ty),
+
+      InstructionValue::Phi(ref vals) => {
+        for val in vals.clone() {
+          self.settle(val, &ty);
         }
       }
 
-      _ => unimplemented!(),
+      InstructionValue::Tuple(ref vals) => {
+        for (val, ty) in vals.clone().into_iter().zip(ty.components()) {
+          self.settle(val, ty);
+        }
+      }
+
+      _ => (),
+    }
+  }
+
+  // unifies the types of the operands of a phi that come before it,
+  // e.g. the branches of an if, where one branch may be None
+  // and the other one Some(1)
+  fn propogate_phi(&mut self, phi: InstrIdx, vals: &[InstrIdx]) -> Type {
+    let vals: Vec<InstrIdx> = vals.iter().copied().filter(|x| *x < phi).collect();
+
+    let Some((first, rest)) = vals.split_first() else {
+      return Type::Invalid;
+    };
+
+    let mut ty = self.builder[*first].ty.clone();
+    for val in rest {
+      let val_ty = &self.builder[*val].ty;
+
+      // mismatches are reported by check_phis
+      if let Some(unified) = self
+        .sema
+        .types
+        .unify(&ty, val_ty)
+        .or_else(|| self.sema.types.unify(val_ty, &ty))
+      {
+        ty = unified;
+      }
+    }
+
+    for val in vals.iter() {
+      self.settle(*val, &ty);
+    }
+
+    ty
+  }
+
+  fn propogate_unwrap_or(&mut self, tok: TokIdx, val: InstrIdx, default: InstrIdx) -> Type {
+    let (Type::Option(ty) | Type::Result(ty, _)) = self.builder[val].ty.clone() else {
+      unreachable!()
     };
 
-    self.out_buffer.push(out);
+    self.settle(default, &ty);
+    let default_ty = &self.builder[default].ty;
+
+    match self.sema.types.unify(&ty, default_ty) {
+      Some(ty) => ty,
+
+      None => {
+        self.error(
+          tok,
+          format!(
+            "Expected a default value of type {}, but found {}",
+            ty, default_ty
+          ),
+        );
+
+        Type::Invalid
+      }
+    }
+  }
+
+  // every value has to have a complete type once the function is propogated,
+  // only the first value that does not is reported
+  fn check_complete(&self) {
+    if let Some(instr) = self.builder.instrs().iter().find(|x| !x.ty.is_complete()) {
+      self.error(
+        instr.tok,
+        format!("Unable to infer the type of a value of type {}", instr.ty),
+      );
+    }
+  }
+
+  // every operand of a phi has to be of the phi's type
+  fn check_phis(&self) {
+    for phi in self.builder.instrs().iter() {
+      let InstructionValue::Phi(ref vals) = phi.val else {
+        continue;
+      };
+
+      for val in vals {
+        let val_ty = &self.builder[*val].ty;
+
+        if !self.sema.types.same_type(val_ty, &phi.ty) {
+          self.error(
+            phi.tok,
+            format!("Expected a value of type {}, but found {}", phi.ty, val_ty),
+          );
+        }
+      }
+    }
   }
 
   fn inner_propogate(mut self) -> Self {
-    for instr in 0..self.function.instrs.0.len() {
+    for instr in 0..self.function.body.instrs.len() {
       self.propogate_instruction(instr)
     }
+    self.check_phis();
+    self.check_complete();
     self
   }
 
-  pub fn propogate(typechecker: &'a SemaContext<'a>, function: &'a IrFunction) -> IrFunction {
+  pub(super) fn propogate(
+    typechecker: &'a SemaContext<'a>,
+    unit: &'a IrUnit,
+    function: &'a IrFunction,
+  ) -> IrFunction {
+    Self::propogate_instance(typechecker, unit, function, 0)
+  }
+
+  // checks that the method calls on the type parameters of the template @function
+  // are declared by their bounds, as they would otherwise only fail
+  // once instantiated with a type that does not implement them
+  //
+  // the rest of a template is checked through its instantiations,
+  // so its other errors are not reported and its typed body is dropped
+  fn check_template(typechecker: &'a SemaContext<'a>, unit: &'a IrUnit, function: &'a IrFunction) {
+    Self {
+      sema: typechecker,
+      unit,
+      function,
+      depth: 0,
+      template: true,
+      builder: IrBuilder::new(),
+    }
+    .inner_propogate();
+  }
+
+  // same as propogate, for an instantiation nested @depth deep
+  pub(super) fn propogate_instance(
+    typechecker: &'a SemaContext<'a>,
+    unit: &'a IrUnit,
+    function: &'a IrFunction,
+    depth: usize,
+  ) -> IrFunction {
     IrFunction {
       name: function.name,
-      instrs: IrBlock(
-        Self {
-          sema: typechecker,
-          function,
-          out_buffer: vec![],
-        }
-        .inner_propogate()
-        .out_buffer,
-      ),
+      params: function.params.clone(),
+      return_type: function.return_type.clone(),
+      type_params: function.type_params.clone(),
+      type_args: function.type_args.clone(),
+      method_of: function.method_of,
+      linkage: function.linkage,
+      is_const: function.is_const,
+      inline: function.inline,
+      docs: function.docs.clone(),
+      body: Self {
+        sema: typechecker,
+        unit,
+        function,
+        depth,
+        template: false,
+        builder: IrBuilder::new(),
+      }
+      .inner_propogate()
+      .builder
+      .finish(),
     }
   }
 }
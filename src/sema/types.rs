@@ -1,27 +1,209 @@
-use crate::ir::Type;
+use std::{
+  cell::{Cell, RefCell},
+  collections::HashMap,
+};
+
+use crate::ir::{FuncIdx, Type};
+
+// how deeply instantiations may be nested within each other,
+// polymorphic recursion would otherwise keep requesting new ones forever
+//
+// defn f[T](x: T, n: Integer) -> Integer:
+//   return f((x, x), n - 1)
+pub const INSTANTIATION_LIMIT: usize = 16;
+
+// an instantiation that still has to be generated
+pub struct PendingInstance {
+  pub template: FuncIdx,
+  pub type_args: Vec<Type>,
+  pub instance: FuncIdx,
+
+  // how many instantiations the request was made within
+  pub depth: usize,
+}
 
 // handles type conflicts, comparisons
 // and type declarations
-pub struct Types {}
+pub struct Types {
+  // every instantiation of a generic function,
+  // maps the template and its type arguments to the instantiated function
+  instances: RefCell<HashMap<(FuncIdx, Vec<Type>), FuncIdx>>,
+
+  // instantiations that still have to be generated
+  pending: RefCell<Vec<PendingInstance>>,
+
+  // index of the next instantiated function,
+  // instantiations are placed after every function of the unit
+  next_instance: Cell<FuncIdx>,
+
+  // maps the name of every newtype to the type it wraps
+  newtypes: HashMap<String, Type>,
+}
 
 impl Types {
+  pub fn new(first_instance: FuncIdx, newtypes: HashMap<String, Type>) -> Self {
+    Self {
+      instances: RefCell::new(HashMap::new()),
+      pending: RefCell::new(vec![]),
+      next_instance: Cell::new(first_instance),
+      newtypes,
+    }
+  }
+
   // checks if a type is coercable to another type
   // returns true if coercable
-  pub fn coerce_type(&self, from: Type, to: Type) -> bool {
+  pub fn coerce_type(&self, from: &Type, to: &Type) -> bool {
     match (from, to) {
-      (Type::Integer, Type::Integer) => true,
       (Type::Integer, Type::Floating) => true,
-      (Type::Floating, Type::Floating) => true,
-      _ => false,
+      _ => self.same_type(from, to),
     }
   }
 
   // checks if types are binary compatable
-  pub fn binary_compatable_types(&self, left: Type, right: Type) -> bool {
+  pub fn binary_compatable_types(&self, left: &Type, right: &Type) -> bool {
+    matches!(
+      (left, right),
+      (Type::Integer, Type::Integer) | (Type::Floating, Type::Floating)
+    )
+  }
+
+  // checks if two types are exactly the same,
+  // undecided and invalid types are never the same as anything
+  pub fn same_type(&self, left: &Type, right: &Type) -> bool {
+    if let Some(pairs) = left.zip(right) {
+      return pairs.iter().all(|(l, r)| self.same_type(l, r));
+    }
+
     match (left, right) {
-      (Type::Integer, Type::Integer) => true,
-      (Type::Floating, Type::Floating) => true,
-      _ => false,
+      (Type::Undecided | Type::Invalid, _) | (_, Type::Undecided | Type::Invalid) => false,
+
+      _ => left == right,
+    }
+  }
+
+  // replaces every newtype within @ty with the type it wraps
+  pub fn erase(&self, ty: &Type) -> Type {
+    match ty {
+      Type::Newtype(name) => self.erase(&self.newtypes[name]),
+      ty => ty.map(|x| self.erase(x)),
+    }
+  }
+
+  // checks if a value can be explicitly converted to another type,
+  // newtypes convert to and from the type they wrap,
+  // and numbers convert to each other
+  pub fn convertible(&self, from: &Type, to: &Type) -> bool {
+    let (from, to) = (self.erase(from), self.erase(to));
+
+    self.same_type(&from, &to)
+      || matches!(
+        (from, to),
+        (
+          Type::Integer | Type::Floating,
+          Type::Integer | Type::Floating
+        )
+      )
+  }
+
+  // fills in the undecided parts of @shape with @ty,
+  // returns None if @ty does not fit into @shape
+  // e.g. (Undecided, Undecided) and (Integer, Floating) -> (Integer, Floating)
+  pub fn unify(&self, shape: &Type, ty: &Type) -> Option<Type> {
+    if matches!(shape, Type::Undecided) {
+      return Some(ty.clone());
+    }
+
+    if let Some(pairs) = shape.zip(ty) {
+      let mut components = pairs
+        .iter()
+        .map(|(shape, ty)| self.unify(shape, ty))
+        .collect::<Option<Vec<_>>>()?
+        .into_iter();
+
+      // rebuild the compound type out of the unified components
+      return Some(shape.map(|_| components.next().unwrap()));
+    }
+
+    self.same_type(shape, ty).then(|| ty.clone())
+  }
+
+  // replaces every type parameter within @ty with its type argument
+  pub fn substitute(&self, ty: &Type, args: &[Type]) -> Type {
+    match ty {
+      Type::Generic(n) => args[*n].clone(),
+      ty => ty.map(|x| self.substitute(x, args)),
+    }
+  }
+
+  // same as substitute, but leaves type parameters
+  // that have not been inferred yet in place
+  pub fn substitute_partial(&self, ty: &Type, args: &[Option<Type>]) -> Type {
+    match ty {
+      Type::Generic(n) => args[*n].clone().unwrap_or(Type::Generic(*n)),
+      ty => ty.map(|x| self.substitute_partial(x, args)),
+    }
+  }
+
+  // infers type arguments by matching a parameter type against
+  // the type of an argument passed to it,
+  // returns false if the two types can not be matched
+  pub fn infer(&self, param: &Type, arg: &Type, args: &mut [Option<Type>]) -> bool {
+    match (param, arg) {
+      (Type::Generic(n), _) => match &args[*n] {
+        Some(inferred) => self.same_type(inferred, arg),
+
+        None => {
+          args[*n] = Some(arg.clone());
+          true
+        }
+      },
+
+      _ => match param.zip(arg) {
+        Some(pairs) => pairs.iter().all(|(param, ty)| self.infer(param, ty, args)),
+        None => self.same_type(param, arg),
+      },
+    }
+  }
+
+  // returns the function instantiating @template with @args,
+  // instantiations are only generated once for every set of type arguments
+  //
+  // @depth is how many instantiations the call is made within,
+  // None if a new instantiation would be nested deeper than the limit
+  pub fn instantiate(&self, template: FuncIdx, args: Vec<Type>, depth: usize) -> Option<FuncIdx> {
+    if let Some(instance) = self.instances.borrow().get(&(template, args.clone())) {
+      return Some(*instance);
+    }
+
+    if depth >= INSTANTIATION_LIMIT {
+      return None;
+    }
+
+    let instance = self.next_instance.get();
+    self.next_instance.set(instance + 1);
+
+    self
+      .instances
+      .borrow_mut()
+      .insert((template, args.clone()), instance);
+    self.pending.borrow_mut().push(PendingInstance {
+      template,
+      type_args: args,
+      instance,
+      depth: depth + 1,
+    });
+
+    Some(instance)
+  }
+
+  // takes the oldest instantiation that has not been generated yet
+  pub fn next_pending_instance(&self) -> Option<PendingInstance> {
+    let mut pending = self.pending.borrow_mut();
+
+    if pending.is_empty() {
+      None
+    } else {
+      Some(pending.remove(0))
     }
   }
 }
@@ -0,0 +1,78 @@
+// the pipeline main runs a program through,
+// shared by the tests of the stages along it
+
+use crate::{
+  ast2ir::IrEmitter,
+  context::{CompilerContext, CompilerContextBuilder},
+  emitter::Emitter,
+  emitters::ir2c_emitter::Ir2CEmitterContext,
+  ir::{
+    interpreter::{Interpreter, Value},
+    thir,
+  },
+  optimizers::optimize,
+  parser::{Ast, Parser},
+  sema::SemaContext,
+};
+
+pub fn context(src: &str) -> CompilerContext {
+  CompilerContextBuilder::new().filedata(src.into()).take()
+}
+
+// panics on a parse error, as a test program is expected to parse
+pub fn parse(ctx: &CompilerContext) -> Ast {
+  Parser::new(ctx).and_then(|x| x.parse()).unwrap()
+}
+
+// lowers and typechecks @ast,
+// None if either of them reported an error
+pub fn check(ctx: &CompilerContext, ast: &Ast) -> Option<thir::Unit> {
+  IrEmitter::emit(ctx, ast)
+    .ok()
+    .and_then(|x| SemaContext::run(ctx, x).ok())
+}
+
+// the info of every diagnostic reported so far
+pub fn diagnostics(ctx: &CompilerContext) -> Vec<String> {
+  ctx
+    .get_diagnostics()
+    .iter()
+    .map(|x| x.info.clone())
+    .collect()
+}
+
+// what compiling a program the way main does produced
+pub struct Compiled {
+  // the info of every diagnostic reported
+  pub diagnostics: Vec<String>,
+
+  // the value main returned, None if the unit failed to emit or typecheck
+  pub value: Option<Value>,
+
+  // the C source and header, None if the unit failed to emit or typecheck
+  pub files: Option<(String, Option<String>)>,
+}
+
+pub fn compile(src: &str) -> Compiled {
+  let ctx = context(src);
+
+  let ast = parse(&ctx);
+  let ir = check(&ctx, &ast).map(|x| optimize(&ctx, x));
+
+  let (value, files) = match ir {
+    Some(ir) => {
+      let value = Interpreter::new(&ctx, &ir).run_main().unwrap();
+      let files = Ir2CEmitterContext::emit(&ctx, &ast, ir).unwrap();
+
+      (Some(value), Some((files.source, files.header)))
+    }
+
+    None => (None, None),
+  };
+
+  Compiled {
+    diagnostics: diagnostics(&ctx),
+    value,
+    files,
+  }
+}
@@ -7,6 +7,9 @@ pub enum TokenType {
   Number,
   Identifier,
 
+  // a string literal, the span includes the quotes
+  String,
+
   Plus,
   Minus,
   Asterisk,
@@ -15,19 +18,64 @@ pub enum TokenType {
   LeftParanthesis,
   RightParanthesis,
 
+  LeftBracket,
+  RightBracket,
+
+  // range operators, `..` and `..=`
+  DotDot,
+  DotDotEqual,
+
   ThinArrow,
 
   Colon,
-
-  // used for scope
+  Comma,
+  Dot,
+  Question,
+  Equal,
+
+  // comparisons, `<`, `<=`, `>` and `>=`
+  Less,
+  LessEqual,
+  Greater,
+  GreaterEqual,
+
+  // the start of a line, the span covers its leading whitespace
   Indentation,
 
+  // placed before the indentation of a line that is indented further
+  // than the previous one, or less, one dedent for every closed level
+  Indent,
+  Dedent,
+
   // keywords
   Return,
   Defn,
+  Trait,
+  Implements,
+  Alias,
+  Newtype,
+  Extern,
+  Export,
+  Const,
+  Include,
+  Let,
+  For,
+  In,
+  Step,
+  If,
+  Else,
+  While,
+  Do,
 
   Comment,
 
+  // a comment starting with ##, documenting the declaration after it,
+  // the span only covers the text of the comment
+  DocComment,
+
+  // the start of an attribute, e.g. @inline
+  At,
+
   // type keywords
   Integer,
   Floating,
@@ -35,7 +83,7 @@ pub enum TokenType {
 
   // custom token that does not match to any rule in the parser,
   // used for early returns
-  EOF,
+  Eof,
 }
 
 // indexes into the compiled file,
@@ -65,6 +113,7 @@ impl Display for TokenType {
     f.write_str(match self {
       TokenType::Number => "number",
       TokenType::Identifier => "identifier",
+      TokenType::String => "string",
 
       TokenType::Plus => "plus",
       TokenType::Minus => "minus",
@@ -74,22 +123,57 @@ impl Display for TokenType {
       TokenType::LeftParanthesis => "left paranthesis",
       TokenType::RightParanthesis => "right paranthesis",
 
+      TokenType::LeftBracket => "left bracket",
+      TokenType::RightBracket => "right bracket",
+
+      TokenType::DotDot => "..",
+      TokenType::DotDotEqual => "..=",
+
       TokenType::Colon => "colon",
+      TokenType::Comma => "comma",
+      TokenType::Dot => ".",
+      TokenType::Question => "?",
+      TokenType::Equal => "=",
+
+      TokenType::Less => "<",
+      TokenType::LessEqual => "<=",
+      TokenType::Greater => ">",
+      TokenType::GreaterEqual => ">=",
 
       TokenType::ThinArrow => "->",
 
       TokenType::Indentation => "indentation",
+      TokenType::Indent => "indent",
+      TokenType::Dedent => "dedent",
 
       TokenType::Return => "return",
       TokenType::Defn => "defn",
+      TokenType::Trait => "trait",
+      TokenType::Implements => "implements",
+      TokenType::Alias => "alias",
+      TokenType::Newtype => "newtype",
+      TokenType::Extern => "extern",
+      TokenType::Export => "export",
+      TokenType::Const => "const",
+      TokenType::Include => "include",
+      TokenType::Let => "let",
+      TokenType::For => "for",
+      TokenType::In => "in",
+      TokenType::Step => "step",
+      TokenType::If => "if",
+      TokenType::Else => "else",
+      TokenType::While => "while",
+      TokenType::Do => "do",
 
       TokenType::Integer => "Integer",
       TokenType::Floating => "Floating",
       TokenType::Moot => "Moot",
 
       TokenType::Comment => "comment",
+      TokenType::DocComment => "doc comment",
+      TokenType::At => "@",
 
-      TokenType::EOF => "EOF",
+      TokenType::Eof => "EOF",
     })
   }
 }